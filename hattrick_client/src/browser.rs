@@ -0,0 +1,84 @@
+use hattrick_packets_lib::discovery::{
+    discover_lan_servers, DiscoveredServer, DISCOVERY_TIMEOUT_MS,
+};
+use hattrick_packets_lib::transport::TransportKind;
+use hattrick_packets_lib::websocket::WEBSOCKET_PORT;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The file favorite servers are saved in, it lives in whatever folder the client is run from.
+pub static FAVORITES_FILE: &str = "favorites.json";
+
+/// Favorite is a server the player saved so they can join it again without typing its address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Favorite {
+    pub name: String,
+    pub address: String,
+}
+
+/// Reads the saved favorites, a missing or unreadable file is the same as having no favorites.
+pub fn load_favorites() -> Vec<Favorite> {
+    std::fs::read_to_string(FAVORITES_FILE)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Saves the favorites, overwriting whatever was saved before. Failing to save is not worth stopping the game over, so it is only logged.
+pub fn save_favorites(favorites: &[Favorite]) {
+    let result = serde_json::to_string_pretty(favorites)
+        .map_err(|e| e.to_string())
+        .and_then(|text| std::fs::write(FAVORITES_FILE, text).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        println!("failed to save favorites: {}", e);
+    }
+}
+
+/// The address to join a discovered server on with the given transport, websockets use their own port.
+pub fn join_address(server: &DiscoveredServer, transport_kind: TransportKind) -> String {
+    match transport_kind {
+        TransportKind::Tcp | TransportKind::Udp => server.address.to_string(),
+        TransportKind::WebSocket => {
+            SocketAddr::new(server.address.ip(), WEBSOCKET_PORT).to_string()
+        }
+    }
+}
+
+/// ServerBrowser is the list of servers found on the local network, searching runs on its own thread so the menu keeps drawing while it waits on answers.
+#[derive(Clone, Default)]
+pub struct ServerBrowser {
+    servers: Arc<Mutex<Vec<DiscoveredServer>>>,
+    searching: Arc<Mutex<bool>>,
+}
+
+impl ServerBrowser {
+    /// Starts a new search for servers, the list is replaced once the search is over. Does nothing if a search is already running.
+    pub fn refresh(&self) {
+        {
+            let mut searching = self.searching.lock().unwrap();
+            if *searching {
+                return;
+            }
+            *searching = true;
+        }
+        let browser = self.clone();
+        thread::spawn(move || {
+            match discover_lan_servers(Duration::from_millis(DISCOVERY_TIMEOUT_MS)) {
+                Ok(servers) => *browser.servers.lock().unwrap() = servers,
+                Err(e) => println!("failed to search for servers: {}", e),
+            }
+            *browser.searching.lock().unwrap() = false;
+        });
+    }
+
+    pub fn servers(&self) -> Vec<DiscoveredServer> {
+        self.servers.lock().unwrap().clone()
+    }
+
+    pub fn is_searching(&self) -> bool {
+        *self.searching.lock().unwrap()
+    }
+}
@@ -1,38 +1,182 @@
+use crate::browser::{join_address, load_favorites, save_favorites, Favorite, ServerBrowser};
 use hattrick_packets_lib::clientinfo::ClientInfo;
+use hattrick_packets_lib::codec::Codec;
+use hattrick_packets_lib::compression::decompress;
+use hattrick_packets_lib::conditioner::{ConditionedTransport, NetworkConditions};
+use hattrick_packets_lib::connection::{
+    ConnectionMonitor, ConnectionState, DisconnectReason, MAX_BAD_PACKETS,
+};
+use hattrick_packets_lib::delta::SnapshotDecoder;
+use hattrick_packets_lib::framing::FramedStream;
 use hattrick_packets_lib::gamestate::{GameState, MAX_VOTE_NUM};
 use hattrick_packets_lib::gametypes::GameType;
+use hattrick_packets_lib::handshake::{
+    Capability, ClientHello, HandshakeResponse, ServerWelcome, HANDSHAKE_CODEC,
+};
+use hattrick_packets_lib::interpolation::SnapshotBuffer;
 use hattrick_packets_lib::keystate::KeyState;
+use hattrick_packets_lib::message::{ClientMessage, ServerMessage};
+use hattrick_packets_lib::ping::{PingTracker, Pong};
 use hattrick_packets_lib::pong::{get_pong_paddle_width, PONG_BALL_RADIUS, PONG_PADDLE_HEIGHT};
+use hattrick_packets_lib::prediction::Predictor;
+use hattrick_packets_lib::room::RoomInfo;
 use hattrick_packets_lib::tank::{TANK_BULLET_RADIUS, TANK_HEIGHT, TANK_WIDTH};
 use hattrick_packets_lib::team::Team;
 use hattrick_packets_lib::team::Team::{BlueTeam, RedTeam};
+use hattrick_packets_lib::transport::{Channel, Transport, TransportKind, IDLE_TIMEOUT_MS};
+use hattrick_packets_lib::udp::UdpTransport;
+use hattrick_packets_lib::websocket::connect_websocket;
 use hattrick_packets_lib::{
-    get_angle_of_travel_degrees, get_vote_count_for_number, round_number, two_point_angle,
-    GAME_HEIGHT, GAME_WIDTH,
+    get_angle_of_travel_degrees, round_number, two_point_angle, GAME_HEIGHT, GAME_WIDTH,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use hattrick_server::Server;
 use macroquad::prelude::*;
 use macroquad::ui::root_ui;
-use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::{sleep, JoinHandle};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
+
+mod browser;
+
+/// The wire codec the client asks the server to use for every packet after connecting.
+static CONNECTION_CODEC: Codec = Codec::Bincode;
+
+/// Number of lines of the chat log to draw on screen.
+static CHAT_LOG_LINES: usize = 6;
+
+/// The name other players see in their server list when the player hosts a game.
+#[cfg(not(target_arch = "wasm32"))]
+static HOSTED_SERVER_NAME: &str = "Hosted game";
+
+/// Highest round trip time in milliseconds that the ping readout is drawn green for.
+static MAX_GOOD_PING_MS: u128 = 100;
+
+/// ConnectionShared is everything the render loop and the connection thread share, each field is locked separately.
+#[derive(Clone)]
+struct ConnectionShared {
+    /// The most recent game state received from the server, with our own movement predicted ahead of it.
+    game_state: Arc<Mutex<GameState>>,
+    /// Recent snapshots from the server, used to draw everyone else smoothly a little in the past.
+    snapshots: Arc<Mutex<SnapshotBuffer>>,
+    /// The id the server gave us, our key in the client list.
+    client_id: Arc<Mutex<Option<String>>>,
+    /// The session token the server gave us, sent when reconnecting so we carry on as the same player.
+    session_token: Arc<Mutex<Option<String>>>,
+    /// Set to false to stop the connection thread, the connection thread sets it to false when it ends.
+    running: Arc<Mutex<bool>>,
+    /// Why the last connection ended, shown on the lost connection screen.
+    disconnect_reason: Arc<Mutex<Option<String>>>,
+    /// Messages that get sent to the server at the start of our next turn, things like chat.
+    outgoing: Arc<Mutex<Vec<ClientMessage>>>,
+    /// Chat lines and game events received from the server, oldest first.
+    chat_log: Arc<Mutex<Vec<String>>>,
+    /// True while the player is typing a chat message, so we dont send movement keys.
+    chatting: Arc<Mutex<bool>>,
+    /// Pings sent to the server, for the round trip time and the servers clock.
+    pings: Arc<Mutex<PingTracker>>,
+    /// The room we are playing in, the server tells us when we connect and whenever we change room.
+    room: Arc<Mutex<Option<RoomInfo>>>,
+    /// The rooms open on the server, as of the last time we asked.
+    rooms: Arc<Mutex<Vec<RoomInfo>>>,
+    /// True while the player has the room list open, so we dont send movement keys.
+    in_room_menu: Arc<Mutex<bool>>,
+    /// When the server said it is going to shut down, None unless it is shutting down.
+    shutdown_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl ConnectionShared {
+    fn new() -> Self {
+        ConnectionShared {
+            game_state: Arc::new(Mutex::new(GameState::default())),
+            snapshots: Arc::new(Mutex::new(SnapshotBuffer::default())),
+            client_id: Arc::new(Mutex::new(None)),
+            session_token: Arc::new(Mutex::new(None)),
+            running: Arc::new(Mutex::new(true)),
+            disconnect_reason: Arc::new(Mutex::new(None)),
+            outgoing: Arc::new(Mutex::new(vec![])),
+            chat_log: Arc::new(Mutex::new(vec![])),
+            chatting: Arc::new(Mutex::new(false)),
+            pings: Arc::new(Mutex::new(PingTracker::default())),
+            room: Arc::new(Mutex::new(None)),
+            rooms: Arc::new(Mutex::new(vec![])),
+            in_room_menu: Arc::new(Mutex::new(false)),
+            shutdown_at: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Stops the connection, recording why it stopped for the player to see.
+    fn disconnect(&self, reason: String) {
+        *self.disconnect_reason.lock().unwrap() = Some(reason);
+        *self.running.lock().unwrap() = false;
+    }
+
+    /// Builds the game state to draw this frame, everyone else is interpolated between snapshots while our own client is the predicted one from the latest game state.
+    fn render_game_state(&self, latest_gs: &GameState) -> GameState {
+        let mut gs = match self.snapshots.lock().unwrap().sample(SystemTime::now()) {
+            Some(gs) => gs,
+            None => return latest_gs.clone(),
+        };
+        if let Some(client_id) = &*self.client_id.lock().unwrap() {
+            if let Some(own_client) = latest_gs.client_list.get(client_id) {
+                gs.client_list.insert(client_id.clone(), own_client.clone());
+            }
+        }
+        gs
+    }
+
+    /// The time right now by the servers clock, as well as we know it. Times in the game state are by the servers clock.
+    fn server_now(&self) -> SystemTime {
+        self.pings.lock().unwrap().remote_time(SystemTime::now())
+    }
+
+    /// Adds a line to the chat log.
+    fn log(&self, line: String) {
+        self.chat_log.lock().unwrap().push(line);
+    }
+}
+
+/// What came of waiting on the servers turn.
+enum ServerTurn {
+    /// The server ended its turn with a snapshot we can use.
+    Snapshot(GameState),
+    /// The server ended its turn with a snapshot that arrived too late to use, which only happens when snapshots can be lost.
+    Stale,
+    /// The server did not end its turn in time.
+    Waiting,
+}
 
 enum LocalState {
     AwaitingIp,
     Playing,
+    /// The list of rooms on the server, shown over the game while it keeps playing.
+    Rooms,
     LostConnection,
 }
 
-// IDEA: potentially move the server code into a library, and let the client spawn a server hosting thread to host from their client??
+/// Browsers have no os to ask for random numbers, so they come from macroquads generator, which is seeded from the clock as the game starts.
+#[cfg(target_arch = "wasm32")]
+fn browser_random(buffer: &mut [u8]) -> Result<(), getrandom::Error> {
+    for byte in buffer.iter_mut() {
+        *byte = macroquad::rand::gen_range(0u32, 256) as u8;
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+getrandom::register_custom_getrandom!(browser_random);
 
 #[macroquad::main("???")]
 async fn main() {
     println!("I am the client");
+    #[cfg(target_arch = "wasm32")]
+    macroquad::rand::srand(macroquad::miniquad::date::now() as u64);
 
-    let game_state = Arc::new(Mutex::new(GameState::default()));
-    let running_thread_state = Arc::new(Mutex::new(true));
+    let connection = ConnectionShared::new();
+    let mut chat_input = String::new();
+    let mut room_name = String::new();
     let mut connect_thread = None;
     let mut local_state = LocalState::AwaitingIp;
     let mut _ip = String::new();
@@ -42,6 +186,28 @@ async fn main() {
         _ip = "localhost:8111".to_string();
     }
     let mut team_id = BlueTeam; // BLUE = 0, RED = 1
+                                // browsers can only open websockets
+    let mut transport_kind = if cfg!(target_arch = "wasm32") {
+        TransportKind::WebSocket
+    } else {
+        TransportKind::default()
+    };
+    // asks the server to compress what it sends us, worth it on slow connections
+    let mut compression = false;
+    // only for testing, makes what we send to the server worse on purpose. Set with --net-latency, --net-jitter, --net-loss, --net-duplicate and --net-bandwidth.
+    let network_conditions = NetworkConditions::from_args(&std::env::args().collect::<Vec<_>>());
+    if let Some(conditions) = network_conditions {
+        println!("network conditioner on: {}", conditions);
+    }
+    // the server the player is hosting from this client, if they are hosting one. It is stopped when they go back to the main menu.
+    // browser builds can not host, they have no threads or sockets to run a server with.
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut hosted_server: Option<Server> = None;
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut host_error: Option<String> = None;
+    let browser = ServerBrowser::default();
+    browser.refresh(); // look for servers on the local network straight away, so the list is there by the time the menu is read
+    let mut favorites = load_favorites();
 
     loop {
         // check game state to decide what we are doing
@@ -90,12 +256,137 @@ async fn main() {
                 root_ui().label(None, "Team: ");
                 draw_rectangle(40.0, 85.0, 10.0, 10.0, team_color);
 
+                if root_ui().button(
+                    None,
+                    format!("Compression: {}", if compression { "on" } else { "off" }),
+                ) {
+                    compression = !compression;
+                }
+
+                if root_ui().button(None, format!("Transport: {}", transport_kind)) {
+                    transport_kind = match transport_kind {
+                        TransportKind::Tcp => TransportKind::Udp,
+                        TransportKind::Udp => TransportKind::WebSocket,
+                        TransportKind::WebSocket => TransportKind::Tcp,
+                    };
+                }
+
+                let mut join_to = None;
                 if root_ui().button(None, "Connect") {
+                    join_to = Some(_ip.clone());
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    root_ui().same_line(0.0);
+                    match &hosted_server {
+                        None => {
+                            if root_ui().button(None, "Host game") {
+                                match host_server() {
+                                    Ok(server) => {
+                                        join_to = Some(hosted_address(&server, transport_kind));
+                                        hosted_server = Some(server);
+                                        host_error = None;
+                                    }
+                                    Err(e) => host_error = Some(format!("failed to host: {e}")),
+                                }
+                            }
+                        }
+                        Some(server) => {
+                            if root_ui().button(None, "Join hosted game") {
+                                join_to = Some(hosted_address(server, transport_kind));
+                            }
+                            root_ui().same_line(0.0);
+                            if root_ui().button(None, "Stop hosting") {
+                                if let Some(server) = hosted_server.take() {
+                                    server.stop();
+                                }
+                            }
+                        }
+                    }
+                    if let Some(e) = &host_error {
+                        root_ui().label(None, e);
+                    }
+                }
+                root_ui().same_line(0.0);
+                if root_ui().button(None, "Save to favorites")
+                    && !favorites.iter().any(|favorite| favorite.address == _ip)
+                {
+                    favorites.push(Favorite {
+                        name: _ip.clone(),
+                        address: _ip.clone(),
+                    });
+                    save_favorites(&favorites);
+                }
+
+                // servers that answered our search of the local network, click one to join it.
+                root_ui().label(None, "Local servers");
+                if browser.is_searching() {
+                    root_ui().label(None, "searching...");
+                } else if root_ui().button(None, "Refresh") {
+                    browser.refresh();
+                }
+                for server in browser.servers() {
+                    let announcement = &server.announcement;
+                    let status = if !announcement.is_compatible() {
+                        " (incompatible)"
+                    } else if announcement.is_full() {
+                        " (full)"
+                    } else {
+                        ""
+                    };
+                    let address = join_address(&server, transport_kind);
+                    if root_ui().button(
+                        None,
+                        format!(
+                            "{} - {} {}/{}{}",
+                            announcement.name,
+                            announcement.game_mode,
+                            announcement.player_count,
+                            announcement.capacity,
+                            status
+                        ),
+                    ) {
+                        join_to = Some(address.clone());
+                    }
+                    root_ui().same_line(0.0);
+                    if root_ui().button(None, "Save")
+                        && !favorites.iter().any(|favorite| favorite.address == address)
+                    {
+                        favorites.push(Favorite {
+                            name: announcement.name.clone(),
+                            address,
+                        });
+                        save_favorites(&favorites);
+                    }
+                }
+
+                // servers the player saved, these are kept between games.
+                root_ui().label(None, "Favorites");
+                let mut removed = None;
+                for (index, favorite) in favorites.iter().enumerate() {
+                    if root_ui().button(None, format!("{} ({})", favorite.name, favorite.address)) {
+                        join_to = Some(favorite.address.clone());
+                    }
+                    root_ui().same_line(0.0);
+                    if root_ui().button(None, "Remove") {
+                        removed = Some(index);
+                    }
+                }
+                if let Some(index) = removed {
+                    favorites.remove(index);
+                    save_favorites(&favorites);
+                }
+
+                if let Some(address) = join_to {
+                    _ip = address.clone(); // so reconnecting goes back to the same server
+                    *connection.session_token.lock().unwrap() = None; // a fresh connect is a new player, only reconnecting resumes
                     connect_thread = Some(spawn_connect_thread(
-                        game_state.clone(),
-                        running_thread_state.clone(),
-                        _ip.clone(),
+                        connection.clone(),
+                        address,
                         team_id.clone(),
+                        transport_kind,
+                        compression,
+                        network_conditions,
                     ));
                     local_state = LocalState::Playing;
                 }
@@ -125,54 +416,75 @@ async fn main() {
                 draw_rectangle(0.0, 0.0, GAME_WIDTH, GAME_HEIGHT, GRAY);
 
                 // get the new game state that was most recently received from the connection thread
-                let local_gs = { game_state.lock().unwrap().clone() };
+                let latest_gs = { connection.game_state.lock().unwrap().clone() };
+                let local_gs = connection.render_game_state(&latest_gs);
                 // game type independent code
                 {
-                    let ping = SystemTime::now().duration_since(local_gs.time).unwrap(); // time from last game state to now, including game framerate added, making this number rather high on average.
+                    let (rtt, jitter) = {
+                        let pings = connection.pings.lock().unwrap();
+                        (pings.rtt(), pings.jitter())
+                    }; // round trip time measured by our pings, None until the first pong comes back.
                     let ping_color = {
-                        if ping.as_millis() > 16 {
-                            RED
-                        } else {
+                        if rtt.is_some_and(|rtt| rtt.as_millis() <= MAX_GOOD_PING_MS) {
                             GREEN
+                        } else {
+                            RED
                         }
                     };
 
-                    if *running_thread_state.lock().unwrap() {
-                        draw_text(
-                            &format!("Ping: {:.2}ms", ping.as_secs_f64() * 1000.0),
-                            10.,
-                            10.,
-                            18.,
-                            ping_color,
-                        );
+                    if *connection.running.lock().unwrap() {
+                        let ping_text = match rtt {
+                            None => "Ping: ...".to_string(),
+                            Some(rtt) => format!(
+                                "Ping: {:.1}ms Jitter: {:.1}ms",
+                                rtt.as_secs_f64() * 1000.0,
+                                jitter.as_secs_f64() * 1000.0
+                            ),
+                        };
+                        draw_text(&ping_text, 10., 10., 18., ping_color);
+                        if let Some(room) = &*connection.room.lock().unwrap() {
+                            draw_text(&format!("Room: {}", room.name), 10., 48., 18., BLACK);
+                        }
+                        if let Some(shutdown_at) = *connection.shutdown_at.lock().unwrap() {
+                            let seconds = shutdown_at
+                                .saturating_duration_since(Instant::now())
+                                .as_secs_f32()
+                                .ceil();
+                            draw_text(
+                                &format!("Server shutting down in {}", seconds),
+                                10.,
+                                66.,
+                                18.,
+                                RED,
+                            );
+                        }
+                        if root_ui().button(vec2(GAME_WIDTH + 10.0, 10.0), "Rooms") {
+                            connection
+                                .outgoing
+                                .lock()
+                                .unwrap()
+                                .push(ClientMessage::ListRooms);
+                            *connection.in_room_menu.lock().unwrap() = true;
+                            local_state = LocalState::Rooms;
+                        } // the list is filled in once the server answers
                     } else {
                         local_state = LocalState::LostConnection;
                     }
 
                     if local_gs.vote_running {
-                        draw_text(
-                            &format!("Vote Running {}", get_vote_count_for_number(2, &local_gs)),
-                            50.0,
-                            50.0,
-                            16.0,
-                            BLACK,
-                        );
+                        draw_text("Vote Running", 50.0, 50.0, 16.0, BLACK);
 
-                        local_gs
-                            .client_list
-                            .iter()
-                            .filter(|client| client.1.vote_number != 0)
-                            .enumerate()
-                            .for_each(|(index, (_, client))| {
-                                let y = 60 + (index * 10);
-                                draw_text(
-                                    &format!("client vote:{}", client.vote_number),
-                                    50.0,
-                                    y as f32,
-                                    16.0,
-                                    BLACK,
-                                );
-                            });
+                        // the server hides everyone elses vote until the vote is over, so only our own is shown
+                        let own_id = connection.client_id.lock().unwrap().clone();
+                        let own_vote = own_id
+                            .and_then(|own_id| local_gs.client_list.get(&own_id))
+                            .map_or(0, |client| client.vote_number);
+                        let vote_text = if own_vote == 0 {
+                            "you have not voted".to_string()
+                        } else {
+                            format!("your vote:{}", own_vote)
+                        };
+                        draw_text(&vote_text, 50.0, 60.0, 16.0, BLACK);
                     }
                 }
 
@@ -195,9 +507,10 @@ async fn main() {
                             let width =
                                 get_pong_paddle_width(&local_gs.client_list, &client_state.team_id);
 
-                            let time_since_last_power_hit = SystemTime::now()
+                            let time_since_last_power_hit = connection
+                                .server_now()
                                 .duration_since(client_state.pong_client_state.time_of_power_hit)
-                                .unwrap()
+                                .unwrap_or_default()
                                 .as_secs_f32();
 
                             // draw power hit circles on paddle
@@ -368,6 +681,178 @@ async fn main() {
                     }
                 }
 
+                // scoreboard, held open with tab
+                if is_key_down(KeyCode::Tab) {
+                    draw_rectangle(
+                        GAME_WIDTH / 4.0,
+                        GAME_HEIGHT / 4.0,
+                        GAME_WIDTH / 2.0,
+                        18.0 * (local_gs.client_list.len() + 1) as f32 + 8.0,
+                        LIGHTGRAY,
+                    );
+                    draw_text(
+                        "Player    Ping",
+                        GAME_WIDTH / 4.0 + 8.0,
+                        GAME_HEIGHT / 4.0 + 18.0,
+                        18.0,
+                        BLACK,
+                    );
+                    let own_id = connection.client_id.lock().unwrap().clone();
+                    let mut players: Vec<_> = local_gs.client_list.iter().collect();
+                    players.sort_by(|a, b| a.0.cmp(b.0));
+                    for (index, (uuid, client)) in players.into_iter().enumerate() {
+                        let name: String = uuid.chars().take(8).collect();
+                        let you = if own_id.as_deref() == Some(uuid.as_str()) {
+                            " (you)"
+                        } else {
+                            ""
+                        };
+                        draw_text(
+                            &format!("{}  {}ms{}", name, client.rtt_ms, you),
+                            GAME_WIDTH / 4.0 + 8.0,
+                            GAME_HEIGHT / 4.0 + 18.0 * (index + 2) as f32,
+                            18.0,
+                            match client.team_id {
+                                RedTeam => RED,
+                                BlueTeam => BLUE,
+                            },
+                        );
+                    }
+                }
+
+                // chat box, enter opens it, typing adds to the message, and enter again sends it.
+                {
+                    let mut chatting = connection.chatting.lock().unwrap();
+                    if *chatting {
+                        while let Some(c) = get_char_pressed() {
+                            if !c.is_control() {
+                                chat_input.push(c);
+                            }
+                        }
+                        if is_key_pressed(KeyCode::Backspace) {
+                            chat_input.pop();
+                        }
+                        if is_key_pressed(KeyCode::Enter) {
+                            if !chat_input.trim().is_empty() {
+                                connection
+                                    .outgoing
+                                    .lock()
+                                    .unwrap()
+                                    .push(ClientMessage::Chat(chat_input.clone()));
+                            }
+                            chat_input.clear();
+                            *chatting = false;
+                        }
+                        draw_text(
+                            &format!("Say: {}_", chat_input),
+                            10.0,
+                            GAME_HEIGHT - 10.0,
+                            18.0,
+                            BLACK,
+                        );
+                    } else if is_key_pressed(KeyCode::Enter) {
+                        while get_char_pressed().is_some() {} // throw away anything typed before the chat box was open
+                        *chatting = true;
+                    } else if is_key_pressed(KeyCode::T) {
+                        let own_id = connection.client_id.lock().unwrap().clone();
+                        if let Some(own_client) =
+                            own_id.and_then(|id| local_gs.client_list.get(&id).cloned())
+                        {
+                            connection
+                                .outgoing
+                                .lock()
+                                .unwrap()
+                                .push(ClientMessage::TeamRequest(own_client.team_id.other()));
+                        } // the server decides if we can switch, and tells everyone if we did
+                    }
+                }
+
+                // draw the most recent lines of the chat log above the chat box
+                {
+                    let chat_log = connection.chat_log.lock().unwrap();
+                    for (index, line) in chat_log.iter().rev().take(CHAT_LOG_LINES).enumerate() {
+                        draw_text(
+                            line,
+                            10.0,
+                            GAME_HEIGHT - 30.0 - (index as f32 * 18.0),
+                            18.0,
+                            BLACK,
+                        );
+                    }
+                }
+
+                frame_delay().await;
+                next_frame().await;
+            }
+
+            // state for when the player is looking at the rooms on the server, the game keeps going behind this.
+            LocalState::Rooms => {
+                clear_background(Color {
+                    r: 80.0 / 255.0,
+                    g: 80.0 / 255.0,
+                    b: 80.0 / 255.0,
+                    a: 1.0,
+                });
+                let current_room = connection.room.lock().unwrap().clone();
+                if let Some(room) = &current_room {
+                    root_ui().label(None, &format!("In room: {}", room.name));
+                }
+
+                let mut request = None;
+                if root_ui().button(None, "Back to game") {
+                    *connection.in_room_menu.lock().unwrap() = false;
+                    local_state = LocalState::Playing;
+                }
+                root_ui().same_line(0.0);
+                if root_ui().button(None, "Refresh") {
+                    request = Some(ClientMessage::ListRooms);
+                }
+                root_ui().same_line(0.0);
+                if root_ui().button(None, "Leave room") {
+                    request = Some(ClientMessage::LeaveRoom);
+                } // back to the lobby
+
+                root_ui().label(None, "Room name");
+                root_ui().input_text(1, "", &mut room_name);
+                if root_ui().button(None, "Create room") {
+                    request = Some(ClientMessage::CreateRoom(room_name.clone()));
+                }
+
+                // every room on the server, click one to join it.
+                root_ui().label(None, "Rooms");
+                for room in connection.rooms.lock().unwrap().iter() {
+                    let here = if current_room
+                        .as_ref()
+                        .is_some_and(|current| current.id == room.id)
+                    {
+                        " (you are here)"
+                    } else {
+                        ""
+                    };
+                    if root_ui().button(
+                        None,
+                        format!(
+                            "{} - {} {} players{}",
+                            room.name, room.game_mode, room.player_count, here
+                        ),
+                    ) {
+                        request = Some(ClientMessage::JoinRoom(room.id));
+                    }
+                }
+
+                if let Some(request) = request {
+                    let back_to_game = !matches!(request, ClientMessage::ListRooms);
+                    connection.outgoing.lock().unwrap().push(request);
+                    if back_to_game {
+                        *connection.in_room_menu.lock().unwrap() = false;
+                        local_state = LocalState::Playing;
+                    } // the server tells us which room we ended up in, or why it refused
+                }
+                if !*connection.running.lock().unwrap() {
+                    *connection.in_room_menu.lock().unwrap() = false;
+                    local_state = LocalState::LostConnection;
+                }
+
                 frame_delay().await;
                 next_frame().await;
             }
@@ -382,18 +867,33 @@ async fn main() {
                     20.0,
                     RED,
                 );
+                if let Some(reason) = &*connection.disconnect_reason.lock().unwrap() {
+                    draw_text(
+                        reason,
+                        screen_width() / 2.0,
+                        (screen_height() / 2.0) + 20.0,
+                        18.0,
+                        BLACK,
+                    );
+                }
                 if root_ui().button(None, "Reconnect?") {
-                    let mut lock = running_thread_state.lock().unwrap();
+                    let mut lock = connection.running.lock().unwrap();
                     *lock = true;
                     connect_thread = Some(spawn_connect_thread(
-                        game_state.clone(),
-                        running_thread_state.clone(),
+                        connection.clone(),
                         _ip.clone(),
                         team_id.clone(),
+                        transport_kind,
+                        compression,
+                        network_conditions,
                     ));
                     local_state = LocalState::Playing;
                 }
                 if root_ui().button(None, "Back to main menu") {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Some(server) = hosted_server.take() {
+                        server.stop();
+                    } // leaving our own game closes it for everyone
                     local_state = LocalState::AwaitingIp;
                 }
                 frame_delay().await;
@@ -402,7 +902,7 @@ async fn main() {
         }
 
         if is_key_pressed(KeyCode::Escape) {
-            let mut end = running_thread_state.lock().unwrap();
+            let mut end = connection.running.lock().unwrap();
             *end = false;
             println!("disconnected from connection thread");
             break;
@@ -428,106 +928,396 @@ async fn main() {
     if let Some(t) = connect_thread {
         let _ = t.join();
     }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(server) = hosted_server {
+        server.stop();
+    }
+}
+
+/// Starts a server inside the client for the player to host a game on, other players on the local network can find it and join like any other server.
+/// It runs on the usual ports, so hosting fails if a server is already running on this machine.
+#[cfg(not(target_arch = "wasm32"))]
+fn host_server() -> std::io::Result<Server> {
+    Server::builder()
+        .name(HOSTED_SERVER_NAME)
+        .udp(true) // every transport is turned on, so other players can join however they like
+        .start()
+}
+
+/// The address to join the hosted server on over the given transport, this is always on this machine.
+#[cfg(not(target_arch = "wasm32"))]
+fn hosted_address(server: &Server, transport_kind: TransportKind) -> String {
+    let port = match (transport_kind, server.websocket_addr()) {
+        (TransportKind::WebSocket, Some(websocket_addr)) => websocket_addr.port(),
+        _ => server.local_addr().port(),
+    };
+    format!("localhost:{}", port)
 }
 
-/// This function takes in the game state arc mutex, the running state arc mutex, an ip address, and the team to connect to and joins the given ip game server.
+/// This function takes in the shared connection state, an ip address, the team to connect to, the kind of transport to use, if we want compression and any network conditions to test with, and joins the given ip game server.
 /// It will mutate the game state each frame by locking the mutex. To stop the connection thread, set the running state to false. This thread also concludes when connection is lost.
+/// If the connection could not be made, the server rejected us, or we were kicked, the reason is put in disconnect_reason so it can be shown to the player.
 fn spawn_connect_thread(
-    game_state: Arc<Mutex<GameState>>,
-    running: Arc<Mutex<bool>>,
+    connection: ConnectionShared,
     ip_address: String,
     team_id: Team,
+    transport_kind: TransportKind,
+    compression: bool,
+    network_conditions: Option<NetworkConditions>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
-        let mut stream = TcpStream::connect(ip_address).unwrap();
-        let _ = stream.set_read_timeout(Option::from(Duration::from_secs(5)));
-        let _ = stream.set_write_timeout(Option::from(Duration::from_secs(5)));
-        let mut _local_gs: Option<GameState> = None;
-        println!("connected");
+        *connection.disconnect_reason.lock().unwrap() = None;
+        let mut transport = match connect_transport(&ip_address, transport_kind) {
+            Ok(transport) => match network_conditions {
+                Some(conditions) => Box::new(ConditionedTransport::new(transport, conditions)),
+                None => transport,
+            },
+            Err(e) => {
+                connection.disconnect(format!("failed to connect: {e}"));
+                return;
+            }
+        };
+
+        let session_token = connection.session_token.lock().unwrap().clone();
+        let welcome = match client_handshake(transport.as_mut(), session_token, compression) {
+            Ok(welcome) => welcome,
+            Err(reason) => {
+                println!("handshake failed: {reason}");
+                connection.disconnect(reason);
+                return;
+            }
+        };
+        let codec = welcome.codec();
+        let compressed = welcome.compression(); // the server may have turned down compression even though we asked
+        let client_id = welcome.client_id;
+        let rules = welcome.rules; // our own movement is predicted with the servers rules, or it would be corrected every snapshot
+        let snapshot_rate = welcome.snapshot_rate;
+        *connection.session_token.lock().unwrap() = Some(welcome.session_token);
+        if welcome.resumed {
+            connection.log("reconnected as the same player".to_string());
+        } else {
+            connection
+                .outgoing
+                .lock()
+                .unwrap()
+                .push(ClientMessage::TeamRequest(team_id.clone()));
+        } // the server puts new players on a team, ask for the one picked in the menu
+        *connection.snapshots.lock().unwrap() = SnapshotBuffer::new(snapshot_rate);
+        *connection.pings.lock().unwrap() = PingTracker::default();
+        *connection.shutdown_at.lock().unwrap() = None;
+        *connection.client_id.lock().unwrap() = Some(client_id.clone());
+        println!("connected over {transport_kind}");
+        if compressed {
+            println!("compression on");
+        }
         let mut vote_num: u8 = 0;
+        let mut monitor = ConnectionMonitor::new(
+            Duration::from_millis(IDLE_TIMEOUT_MS),
+            MAX_BAD_PACKETS,
+            Instant::now(),
+        );
+        let mut snapshots = SnapshotDecoder::default();
+        let mut predictor = Predictor::default();
+        let mut local_gs = GameState::default(); // the newest game state from the server, without any prediction
         loop {
-            let mut buf: [u8; 8192] = [0; 8192];
-
-            let client_packet = ClientInfo {
-                time: SystemTime::now(),
-                mouse_pos: mouse_position(),
-                team_id: team_id.clone(),
-                key_state: KeyState::new(),
-                vote_number: {
-                    match &_local_gs {
-                        None => vote_num,
-                        Some(gs) => {
-                            if gs.vote_running {
-                                if is_key_pressed(KeyCode::Left) {
-                                    vote_num = (vote_num as i32 - 1).clamp(0, MAX_VOTE_NUM) as u8;
-                                }
-                                if is_key_pressed(KeyCode::Right) {
-                                    vote_num = (vote_num as i32 + 1).clamp(0, MAX_VOTE_NUM) as u8;
-                                }
-                            } else {
-                                vote_num = 0;
-                            }
-                            vote_num
-                        }
-                    }
-                },
+            // read every message the server sends us this turn, up until the snapshot that ends it.
+            // over udp the snapshot can be lost, in which case we carry on with the last one we got.
+            let turn = match receive_server_turn(
+                transport.as_mut(),
+                codec,
+                compressed,
+                &connection,
+                &mut snapshots,
+                &mut monitor,
+            ) {
+                Ok(turn) => turn,
+                Err(reason) => {
+                    println!("connection ended: {reason}");
+                    connection.disconnect(reason);
+                    break;
+                }
             };
+            let waiting = matches!(turn, ServerTurn::Waiting);
+            if let ServerTurn::Snapshot(gs) = turn {
+                connection
+                    .snapshots
+                    .lock()
+                    .unwrap()
+                    .push(gs.clone(), SystemTime::now());
+                local_gs = gs;
+            }
 
-            let ser = serde_json::to_string(&client_packet).unwrap();
+            if let ConnectionState::Disconnected(reason) = monitor.check(Instant::now()) {
+                let reason = reason.clone();
+                let _ = send_messages(
+                    transport.as_mut(),
+                    codec,
+                    &[ClientMessage::Disconnect(reason.clone())],
+                ); // the server may already be gone, so it does not matter if this fails.
+                println!("connection ended: {reason}");
+                connection.disconnect(reason.to_string());
+                break;
+            } // the server stopped responding, or sent too many packets we could not read.
 
-            let read = stream.read(&mut buf);
+            let still_running = *connection.running.lock().unwrap();
+            if still_running && waiting && transport.is_reliable() {
+                if monitor.needs_heartbeat(Instant::now()) {
+                    match send_messages(transport.as_mut(), codec, &[ClientMessage::Heartbeat]) {
+                        Ok(()) => monitor.sent(Instant::now()),
+                        Err(e) => {
+                            connection.disconnect(DisconnectReason::ConnectionLost(e).to_string());
+                            break;
+                        }
+                    }
+                }
+                continue;
+            } // over a reliable transport the server always finishes its turn eventually, so wait for it rather than taking another turn of our own.
 
-            let write = stream.write(ser.as_bytes());
-            let flush = stream.flush();
+            let mut messages: Vec<ClientMessage> =
+                std::mem::take(&mut *connection.outgoing.lock().unwrap());
 
-            if read.is_err() || write.is_err() || flush.is_err() {
-                // gracefully close thread if internet connection
-                break;
+            if let Some(sequence) = snapshots.latest_sequence() {
+                messages.push(ClientMessage::SnapshotAck(sequence));
+            } // lets the server send the next snapshot as only what changed since this one.
+
+            if let Some(id) = connection.pings.lock().unwrap().ping(Instant::now()) {
+                messages.push(ClientMessage::Ping(id));
             }
 
-            let mut cleaned_buf = vec![];
-            for value in buf {
-                // make small buffer of the data into a vector sent by the server
-                if !String::from_utf8_lossy(&[value]).contains('\0') {
-                    cleaned_buf.push(value);
+            let previous_vote = vote_num;
+            if local_gs.vote_running {
+                if is_key_pressed(KeyCode::Left) {
+                    vote_num = (vote_num as i32 - 1).clamp(0, MAX_VOTE_NUM) as u8;
+                }
+                if is_key_pressed(KeyCode::Right) {
+                    vote_num = (vote_num as i32 + 1).clamp(0, MAX_VOTE_NUM) as u8;
                 }
+            } else {
+                vote_num = 0;
             }
-            //let output_from_buf = String::from_utf8(cleaned_buf).unwrap();
-
-            // only read the output if its ok, if not skip a frame. This can happen because of parsing errors.
-            match String::from_utf8(cleaned_buf) {
-                Ok(output_from_buf) => {
-                    match serde_json::from_str::<GameState>(&output_from_buf) {
-                        Ok(gs) => match game_state.lock() {
-                            Ok(mut lock) => {
-                                *lock = gs.clone();
-                                _local_gs = Some(gs.clone());
-                            }
-                            Err(e) => {
-                                println!("mutex guard error: {e}");
-                            }
-                        },
-                        Err(e) => {
-                            println!("failed to parse: {e}");
+            if vote_num != previous_vote {
+                messages.push(ClientMessage::Vote(vote_num));
+            } // only tell the server about our vote when it changes.
+
+            if still_running {
+                let input = predictor.track(ClientInfo {
+                    time: SystemTime::now(),
+                    mouse_pos: mouse_position(),
+                    key_state: {
+                        if *connection.chatting.lock().unwrap()
+                            || *connection.in_room_menu.lock().unwrap()
+                        {
+                            KeyState::default() // dont move while typing a chat message or picking a room
+                        } else {
+                            KeyState::new()
                         }
-                    };
+                    },
+                    input_sequence: 0, // set by the predictor
+                    view_time: connection
+                        .snapshots
+                        .lock()
+                        .unwrap()
+                        .render_time(SystemTime::now()),
+                });
+                messages.push(ClientMessage::Input(input));
 
-                    if !(*running.lock().unwrap()) {
-                        // if the thread running state has been instructed to stop, then we break out of the loop gracefully
-                        break;
-                    }
-                }
-                Err(err) => {
-                    println!("Skipped packet due to parse error: \n{}", err);
-                    //println!("cleaned buffer length: {}, contents: {:?}",cleaned_buf.len(), cleaned_buf);
-                }
+                // draw our own paddle or tank where we expect it to be once the server has our inputs, rather than where it was a round trip ago.
+                let mut predicted_gs = local_gs.clone();
+                predictor.reconcile(
+                    &mut predicted_gs,
+                    &client_id,
+                    &rules,
+                    snapshot_rate,
+                    connection.server_now(),
+                );
+                *connection.game_state.lock().unwrap() = predicted_gs;
+            } else {
+                // if the thread running state has been instructed to stop, then we let the server know and break out of the loop gracefully
+                messages.push(ClientMessage::Disconnect(DisconnectReason::Left));
+            }
+
+            if let Err(e) = send_messages(transport.as_mut(), codec, &messages) {
+                println!("connection ended: {e}");
+                connection.disconnect(DisconnectReason::ConnectionLost(e).to_string());
+                break;
+            }
+            monitor.sent(Instant::now());
+
+            if !still_running {
+                break;
             }
         }
-        *running.lock().unwrap() = false;
+        *connection.running.lock().unwrap() = false;
         println!("connection thread finished");
     })
 }
 
+/// Opens a connection to the given address over the given kind of transport.
+fn connect_transport(
+    ip_address: &str,
+    transport_kind: TransportKind,
+) -> Result<Box<dyn Transport>, String> {
+    match transport_kind {
+        TransportKind::Tcp => {
+            let stream = TcpStream::connect(ip_address).map_err(|e| e.to_string())?;
+            let _ = stream.set_write_timeout(Option::from(Duration::from_secs(5)));
+            let _ = stream.set_nodelay(true); // input is tiny and sent every snapshot, dont let it wait to be batched
+            Ok(Box::new(FramedStream::new(stream)))
+        }
+        TransportKind::Udp => Ok(Box::new(
+            UdpTransport::connect(ip_address).map_err(|e| e.to_string())?,
+        )),
+        TransportKind::WebSocket => Ok(Box::new(
+            connect_websocket(ip_address).map_err(|e| e.to_string())?,
+        )),
+    }
+}
+
+/// Sends each message to the server in order, stopping at the first message that fails.
+/// Pongs get their send time set right before they go out.
+fn send_messages(
+    transport: &mut dyn Transport,
+    codec: Codec,
+    messages: &[ClientMessage],
+) -> Result<(), String> {
+    for message in messages {
+        let ser = match message {
+            ClientMessage::Pong(pong) => codec.encode(&ClientMessage::Pong(pong.stamped())),
+            _ => codec.encode(message),
+        }
+        .map_err(|e| e.to_string())?;
+        transport
+            .send_on(message.channel(), &ser)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Reads messages from the server until the snapshot that ends its turn, returning that snapshot.
+/// The monitor hears about every message, unreadable ones are skipped until there have been too many of them.
+/// Events and errors are added to the chat log, a disconnect from the server or a broken connection returns the reason the connection ended.
+fn receive_server_turn(
+    transport: &mut dyn Transport,
+    codec: Codec,
+    compressed: bool,
+    connection: &ConnectionShared,
+    snapshots: &mut SnapshotDecoder,
+    monitor: &mut ConnectionMonitor,
+) -> Result<ServerTurn, String> {
+    let deadline = Instant::now() + transport.turn_timeout();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let frame = match transport.recv_timeout(remaining) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(ServerTurn::Waiting),
+            Err(e) => return Err(DisconnectReason::ConnectionLost(e.to_string()).to_string()),
+        };
+        // only use the frame if it parses, if not skip a frame. This can happen because of a version mismatch with the server.
+        let decoded = if compressed {
+            decompress(&frame)
+                .map_err(|e| e.to_string())
+                .and_then(|frame| {
+                    codec
+                        .decode::<ServerMessage>(&frame)
+                        .map_err(|e| e.to_string())
+                })
+        } else {
+            codec
+                .decode::<ServerMessage>(&frame)
+                .map_err(|e| e.to_string())
+        };
+        let message = match decoded {
+            Ok(message) => {
+                monitor.heard(Instant::now());
+                message
+            }
+            Err(e) => {
+                println!("failed to parse: {e}");
+                if monitor.bad_packet(Instant::now()) == &ConnectionState::Connected {
+                    continue;
+                }
+                return Ok(ServerTurn::Waiting); // too many bad packets, the monitor has the reason
+            }
+        };
+
+        match message {
+            ServerMessage::Snapshot(snapshot) => {
+                return Ok(match snapshots.decode(snapshot) {
+                    Some(gs) => ServerTurn::Snapshot(gs),
+                    None => ServerTurn::Stale, // we keep the last game state
+                });
+            }
+            ServerMessage::Event(event) => connection.log(event.log_line()),
+            ServerMessage::Error(e) => connection.log(format!("server error: {e}")),
+            ServerMessage::Disconnect(reason) => {
+                return Err(format!("disconnected by server: {reason}"))
+            }
+            ServerMessage::TeamRejected(reason) => {
+                connection.log(format!("team change refused: {reason}"))
+            }
+            ServerMessage::Heartbeat => {} // hearing it was all it was for
+            ServerMessage::Ping(id) => connection
+                .outgoing
+                .lock()
+                .unwrap()
+                .push(ClientMessage::Pong(Pong::reply_to(id))),
+            ServerMessage::Pong(pong) => {
+                connection.pings.lock().unwrap().pong(&pong, Instant::now());
+            }
+            ServerMessage::RoomList(rooms) => *connection.rooms.lock().unwrap() = rooms,
+            ServerMessage::RoomJoined(room) => {
+                connection.log(format!("joined room {}", room.name));
+                connection.snapshots.lock().unwrap().clear(); // the old rooms game should not be drawn blended into the new one
+                *connection.room.lock().unwrap() = Some(room);
+            }
+            ServerMessage::RoomRejected(reason) => {
+                connection.log(format!("room change refused: {reason}"))
+            }
+            ServerMessage::ShuttingDown { seconds } => {
+                connection.log(format!("server is shutting down in {seconds} seconds"));
+                *connection.shutdown_at.lock().unwrap() =
+                    Some(Instant::now() + Duration::from_secs(seconds as u64));
+            }
+        }
+    }
+}
+
+/// Sends our hello to the server and waits for its answer, returning the servers welcome, or the reason we could not join.
+/// If we have a session token from before, the server will give us our old player back if it still has it.
+fn client_handshake(
+    transport: &mut dyn Transport,
+    session_token: Option<String>,
+    compression: bool,
+) -> Result<ServerWelcome, String> {
+    let mut capabilities = vec![Capability::Codec(CONNECTION_CODEC)];
+    if compression {
+        capabilities.push(Capability::Compression);
+    }
+    let mut hello = ClientHello::new(env!("CARGO_PKG_VERSION"), capabilities);
+    hello.session_token = session_token;
+    let ser = HANDSHAKE_CODEC.encode(&hello).map_err(|e| e.to_string())?;
+    transport
+        .send_on(Channel::Reliable, &ser)
+        .map_err(|e| e.to_string())?;
+
+    let frame = transport
+        .recv_timeout(Duration::from_millis(IDLE_TIMEOUT_MS))
+        .map_err(|e| e.to_string())?
+        .ok_or("server did not answer the handshake")?;
+    match HANDSHAKE_CODEC
+        .decode::<HandshakeResponse>(&frame)
+        .map_err(|e| format!("could not read server handshake: {e}"))?
+    {
+        HandshakeResponse::Welcome(welcome) => {
+            if let Some(cookie) = welcome.udp_cookie {
+                transport.set_cookie(cookie);
+            } // over udp the server only trusts our address once this comes back
+            Ok(welcome)
+        }
+        HandshakeResponse::Rejected(reason) => Err(format!("rejected by server: {reason}")),
+    }
+}
+
 /// This function simply sleeps the given thread for the duration of time necessary to keep the game running at a maximum of 60 fps.
 /// To calculate number of milliseconds to wait each frame, divide 1000 by the desired framerate.
 /// E.g. 1000.0 ms/60.0 = 16.66_ ms meaning each frame needs to be 16.66_ ms delayed to make a good 60 fps.
@@ -1,9 +1,11 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hattrick_packets_lib::clientinfo::ClientInfo;
 use hattrick_packets_lib::clientstate::ClientState;
+use hattrick_packets_lib::codec::Codec;
 use hattrick_packets_lib::gamestate::GameState;
-use hattrick_packets_lib::gametypes::GameType;
 use hattrick_packets_lib::gametypes::GameType::{PONG, TANK};
 use hattrick_packets_lib::pong::PongGameState;
+use hattrick_packets_lib::rules::{PongRules, TankRules};
 use hattrick_packets_lib::tank::{respawn_tank, TankBullet, TankGameState};
 use hattrick_packets_lib::team::Team;
 use hattrick_packets_lib::{
@@ -15,13 +17,13 @@ use std::time::SystemTime;
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("round digits", |b| {
-        b.iter(|| round_digits(black_box(&mut 9.87654321), black_box(5)))
+        b.iter(|| round_digits(black_box(&mut 9.876_543), black_box(5)))
     });
     c.bench_function("round number", |b| {
-        b.iter(|| round_number(black_box(&9.87654321), black_box(5)))
+        b.iter(|| round_number(black_box(&9.876_543), black_box(5)))
     });
     c.bench_function("magnitude", |b| {
-        b.iter(|| (black_box(1.23456789 as f32), black_box(9.87654321 as f32)).mag())
+        b.iter(|| (black_box(1.234_567_9_f32), black_box(9.876_543_f32)).mag())
     });
     c.bench_function("get vote num", |b| {
         b.iter(|| {
@@ -36,6 +38,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 1,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             gs.client_list.insert(
@@ -48,6 +54,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 2,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             gs.client_list.insert(
@@ -60,6 +70,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 1,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             get_vote_count_for_number(1, &gs)
@@ -104,6 +118,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 1,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             gs.client_list.insert(
@@ -116,6 +134,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 2,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             gs.client_list.insert(
@@ -128,11 +150,15 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 1,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
 
             match &mut gs.game_type {
-                PONG(pgs) => pgs.step_game_state(&gs.client_list),
+                PONG(pgs) => pgs.step_game_state(&gs.client_list, &PongRules::default()),
                 TANK(_tgs) => {
                     panic!("Error, game type was of type tank game type, this should not happen.");
                 }
@@ -150,13 +176,14 @@ fn criterion_benchmark(c: &mut Criterion) {
                     y_vel: rand::thread_rng().gen_range(-5.0..5.0),
                     bounce_count: 0,
                     team: Team::RedTeam,
+                    lag_compensation: 0.0,
                 })
             }
             for _ in 0..1000 {
                 for bullet in tgs.bullets.iter_mut() {
                     bullet.step(&10.0);
                 }
-                tgs.remove_dead_bullets();
+                tgs.remove_dead_bullets(&TankRules::default());
             }
         })
     });
@@ -179,6 +206,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 1,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             gs.client_list.insert(
@@ -191,6 +222,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 2,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             gs.client_list.insert(
@@ -203,6 +238,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                     pong_client_state: Default::default(),
                     tank_client_state: Default::default(),
                     vote_number: 1,
+                    last_input_sequence: 0,
+                    rtt_ms: 0,
+                    lag_compensation: 0.0,
+                    team_changed_at: None,
                 },
             );
             for _ in 0..10 {
@@ -213,7 +252,7 @@ fn criterion_benchmark(c: &mut Criterion) {
                             respawn_tank(
                                 &mut client.tank_client_state,
                                 &tgs.bullets,
-                                &mut gs.client_list,
+                                &gs.client_list,
                             );
                         }
                     }
@@ -223,5 +262,87 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// Builds a tank game state with a handful of clients and bullets, roughly what a busy server would be sending every frame.
+fn busy_game_state() -> GameState {
+    let mut tgs = TankGameState::default();
+    for _ in 0..20 {
+        tgs.bullets.push(TankBullet {
+            x: rand::thread_rng().gen_range(0.0..GAME_WIDTH),
+            y: rand::thread_rng().gen_range(0.0..GAME_HEIGHT),
+            x_vel: rand::thread_rng().gen_range(-5.0..5.0),
+            y_vel: rand::thread_rng().gen_range(-5.0..5.0),
+            bounce_count: 0,
+            team: Team::RedTeam,
+            lag_compensation: 0.0,
+        })
+    }
+    let mut gs = GameState {
+        game_type: TANK(tgs),
+        ..GameState::default()
+    };
+    for index in 0..8 {
+        gs.client_list.insert(
+            format!("test{}", index),
+            ClientState {
+                mouse_pos: (
+                    rand::thread_rng().gen_range(0.0..GAME_WIDTH),
+                    rand::thread_rng().gen_range(0.0..GAME_HEIGHT),
+                ),
+                ..ClientState::default()
+            },
+        );
+    }
+    gs
+}
+
+fn codec_benchmark(c: &mut Criterion) {
+    let gs = busy_game_state();
+    let client_info = ClientInfo::default();
+
+    let mut group = c.benchmark_group("game state codec");
+    for codec in [Codec::Json, Codec::Bincode] {
+        let encoded = codec.encode(&gs).unwrap();
+        println!(
+            "{:?} encoded game state size: {} bytes",
+            codec,
+            encoded.len()
+        );
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("encode", format!("{:?}", codec)),
+            &gs,
+            |b, gs| b.iter(|| codec.encode(black_box(gs)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("decode", format!("{:?}", codec)),
+            &encoded,
+            |b, encoded| b.iter(|| codec.decode::<GameState>(black_box(encoded)).unwrap()),
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("client info codec");
+    for codec in [Codec::Json, Codec::Bincode] {
+        let encoded = codec.encode(&client_info).unwrap();
+        println!(
+            "{:?} encoded client info size: {} bytes",
+            codec,
+            encoded.len()
+        );
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("encode", format!("{:?}", codec)),
+            &client_info,
+            |b, client_info| b.iter(|| codec.encode(black_box(client_info)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("decode", format!("{:?}", codec)),
+            &encoded,
+            |b, encoded| b.iter(|| codec.decode::<ClientInfo>(black_box(encoded)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark, codec_benchmark);
 criterion_main!(benches);
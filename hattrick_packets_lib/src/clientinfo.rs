@@ -1,19 +1,20 @@
 use crate::keystate::KeyState;
-use crate::team::Team;
-use crate::team::Team::BlueTeam;
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
 /// Client info is the struct that each client creates, serializes, and sends to the server, it is not meant to be used directly to store client data, but to be interpreted.
-/// Example, client sends a ClientInfo that has mouse position of x: 150.0, y: 300.0, system time is irrelevant here, and team id is blue.
+/// Example, client sends a ClientInfo that has mouse position of x: 150.0, y: 300.0, system time is irrelevant here, and the server has them on the blue team.
 /// For pong, blue means the client is bound to the top of the screen, meaning that his y value of his mouse is ignored and instead set to where ever his team is meant to be, and the x is only used for his position.
+/// The team is not part of the input, the server decides it, a client can only ask to change team with a team request.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ClientInfo {
     pub time: SystemTime,
     pub mouse_pos: (f32, f32),
-    pub team_id: Team,
     pub key_state: KeyState,
-    pub vote_number: u8,
+    /// Counts up by one for every input the client sends, starting at 1. 0 means the input was not sent by a client, like the ai's.
+    pub input_sequence: u32,
+    /// The server time that other players were being drawn at when this input was made, None if the client is not interpolating.
+    pub view_time: Option<SystemTime>,
 }
 
 /// Probably shouldn't ever use a default client info, unless the deserialization fails?
@@ -22,9 +23,9 @@ impl Default for ClientInfo {
         ClientInfo {
             time: SystemTime::now(),
             mouse_pos: (0.0, 0.0),
-            team_id: BlueTeam,
             key_state: KeyState::default(),
-            vote_number: 0,
+            input_sequence: 0,
+            view_time: None,
         }
     }
 }
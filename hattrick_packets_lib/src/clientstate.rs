@@ -8,8 +8,8 @@ use std::time::SystemTime;
 
 /// ClientState is a struct that the server generates using the data given from each client in the form of a ClientInfo struct. This separation allows for good programming ergonomics.
 /// It also allows the server to be able to make decisions to ignore specific client info, if it is not possible, for example, if a client info packet says blue team, and then red, then blue again, it is
-/// unlikely that the server should allow it, and because of this interpretation, could be stopped. This is not a feature at the moment but is an idea of why this distinction was made.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// unlikely that the server should allow it, and because of this interpretation, could be stopped. The server cleans up each ClientInfo with the validation module before it becomes a ClientState.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ClientState {
     pub time: SystemTime,
     pub team_id: Team,
@@ -18,32 +18,16 @@ pub struct ClientState {
     pub pong_client_state: PongClientState,
     pub tank_client_state: TankClientState,
     pub vote_number: u8,
-}
-
-impl ClientState {
-    /// Takes self, and another ClientState is input, and counts how many differing struct variables there are,
-    /// at the moment, this function is extremely subjective, and probably shouldn't be used for much.
-    pub fn differ_count(&self, cs: &ClientState) -> i32 {
-        let mut count = 0;
-
-        // if self.pos.0 != cs.pos.0 {
-        //     count += 1;
-        // }
-        //
-        // if self.pos.1 != cs.pos.1 {
-        //     count += 1;
-        // }
-
-        if self.time != cs.time {
-            count += 1;
-        }
-
-        if self.team_id != cs.team_id {
-            count += 1;
-        }
-
-        count
-    }
+    /// The sequence number of the newest input the server has applied for this client, the client uses it to know which of its predicted inputs still need replaying.
+    pub last_input_sequence: u32,
+    /// The round trip time to this client in milliseconds as measured by the servers pings, 0 until the first pong arrives. Shown on the scoreboard.
+    pub rtt_ms: u32,
+    /// How far in seconds behind the server this client sees other players, used to rewind hit checks for their shots. Only the server uses this, so it is never sent.
+    #[serde(skip)]
+    pub lag_compensation: f32,
+    /// When the server last moved this client to a different team, used for the team change cool down. Only the server uses this, so it is never sent.
+    #[serde(skip)]
+    pub team_changed_at: Option<SystemTime>,
 }
 
 impl Default for ClientState {
@@ -57,6 +41,10 @@ impl Default for ClientState {
             pong_client_state: Default::default(),
             tank_client_state: TankClientState::default(),
             vote_number: 0,
+            last_input_sequence: 0,
+            rtt_ms: 0,
+            lag_compensation: 0.0,
+            team_changed_at: None,
         }
     }
 }
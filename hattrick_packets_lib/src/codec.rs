@@ -0,0 +1,111 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Codec is the wire format used to turn packets into the bytes of a frame, and back again.
+/// The codec is asked for by the client in its hello when it connects, after the handshake every frame in both directions uses the chosen codec.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    /// Human readable json, the original format, useful for debugging packets.
+    /// Json is the default as it is the format every version of the game understands.
+    #[default]
+    Json,
+    /// Compact binary format, much smaller and faster than json, but not self describing.
+    Bincode,
+}
+
+/// CodecError is returned when a packet could not be encoded or decoded, it holds the message from the underlying serde format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError {
+    pub codec: Codec,
+    pub message: String,
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} codec error: {}", self.codec, self.message)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl Codec {
+    /// Serializes the given value into bytes using this codec.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Codec::Json => serde_json::to_vec(value).map_err(|e| self.error(e)),
+            Codec::Bincode => bincode::serialize(value).map_err(|e| self.error(e)),
+        }
+    }
+
+    /// Deserializes the given bytes into a value using this codec.
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| self.error(e)),
+            Codec::Bincode => bincode::deserialize(bytes).map_err(|e| self.error(e)),
+        }
+    }
+
+    fn error(&self, e: impl Display) -> CodecError {
+        CodecError {
+            codec: *self,
+            message: e.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientinfo::ClientInfo;
+    use crate::codec::Codec;
+    use crate::gamestate::GameState;
+    use crate::gametypes::GameType::TANK;
+    use crate::tank::{TankBullet, TankGameState};
+    use crate::team::Team;
+
+    #[test]
+    fn codec_round_trip_test() {
+        let mut tgs = TankGameState::default();
+        tgs.bullets.push(TankBullet {
+            x: 1.0,
+            y: 2.0,
+            x_vel: 3.0,
+            y_vel: 4.0,
+            bounce_count: 1,
+            team: Team::RedTeam,
+            lag_compensation: 0.0,
+        });
+        let gs = GameState {
+            game_type: TANK(tgs),
+            ..GameState::default()
+        };
+
+        for codec in [Codec::Json, Codec::Bincode] {
+            let bytes = codec.encode(&gs).unwrap();
+            let decoded: GameState = codec.decode(&bytes).unwrap();
+            assert_eq!(decoded.time, gs.time);
+            match decoded.game_type {
+                TANK(tgs) => assert_eq!(tgs.bullets.len(), 1),
+                _ => panic!("decoded game type was not tank"),
+            }
+
+            let info = ClientInfo::default();
+            let decoded: ClientInfo = codec.decode(&codec.encode(&info).unwrap()).unwrap();
+            assert_eq!(decoded.mouse_pos, info.mouse_pos);
+        }
+    }
+
+    #[test]
+    fn bincode_smaller_than_json_test() {
+        let gs = GameState::default();
+        let json = Codec::Json.encode(&gs).unwrap();
+        let bincode = Codec::Bincode.encode(&gs).unwrap();
+        assert!(bincode.len() < json.len());
+    }
+
+    #[test]
+    fn codec_decode_error_test() {
+        assert!(Codec::Bincode.decode::<GameState>(&[1, 2, 3]).is_err());
+        assert!(Codec::Json.decode::<GameState>(b"{").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::framing::MAX_FRAME_SIZE;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// The compression level used if the server is not told otherwise, from 0 for fastest to 9 for smallest.
+pub static DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// Payloads smaller than this many bytes are sent as they are, compressing them saves little and can even make them bigger.
+pub static DEFAULT_COMPRESSION_MIN_SIZE: usize = 256;
+
+/// The first byte of every payload on a compressed stream, saying if the rest of the payload is compressed.
+const RAW_PAYLOAD: u8 = 0;
+const DEFLATED_PAYLOAD: u8 = 1;
+
+/// CompressionConfig is how hard the server tries to compress, and which payloads are worth compressing at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// From 0 for fastest to 9 for smallest.
+    pub level: u32,
+    /// Payloads smaller than this many bytes are not compressed.
+    pub min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            level: DEFAULT_COMPRESSION_LEVEL,
+            min_size: DEFAULT_COMPRESSION_MIN_SIZE,
+        }
+    }
+}
+
+/// CompressionStats is how much compression has saved, so whoever runs the server can see if it is worth turning on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Every payload that went through the compressor.
+    pub payloads: u64,
+    /// The payloads that were big enough to be compressed, and got smaller when they were.
+    pub compressed_payloads: u64,
+    /// Bytes before compression.
+    pub bytes_in: u64,
+    /// Bytes actually sent, including the byte that says if a payload is compressed.
+    pub bytes_out: u64,
+}
+
+impl CompressionStats {
+    /// Bytes sent for every byte that would have been sent without compression, lower is better. 1 if nothing has been sent.
+    pub fn ratio(&self) -> f64 {
+        if self.bytes_in == 0 {
+            1.0
+        } else {
+            self.bytes_out as f64 / self.bytes_in as f64
+        }
+    }
+
+    /// Adds the other stats onto these, for totalling up stats from many connections.
+    pub fn merge(&mut self, other: &CompressionStats) {
+        self.payloads += other.payloads;
+        self.compressed_payloads += other.compressed_payloads;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+    }
+}
+
+impl Display for CompressionStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} payloads, {} compressed, {} bytes down to {} bytes ({:.1}% of the original size)",
+            self.payloads,
+            self.compressed_payloads,
+            self.bytes_in,
+            self.bytes_out,
+            self.ratio() * 100.0
+        )
+    }
+}
+
+/// CompressionError is returned when a compressed payload could not be read, the payload should be treated as a bad packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressionError {
+    Empty,
+    UnknownFlag(u8),
+    /// The payload unpacks to more than the largest frame we accept, it is either broken or someone is trying to make us run out of memory.
+    TooLarge,
+    Corrupt(String),
+}
+
+impl Display for CompressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionError::Empty => write!(f, "compressed payload is empty"),
+            CompressionError::UnknownFlag(flag) => {
+                write!(f, "unknown compression flag {}", flag)
+            }
+            CompressionError::TooLarge => write!(
+                f,
+                "compressed payload unpacks to more than {} bytes",
+                MAX_FRAME_SIZE
+            ),
+            CompressionError::Corrupt(e) => write!(f, "corrupt compressed payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CompressionError {}
+
+/// Compressor compresses every payload sent on one side of a connection, and keeps stats on how much it saved.
+/// Each payload is compressed on its own, so lost or reordered payloads over udp do not stop the rest being read.
+#[derive(Debug, Clone, Default)]
+pub struct Compressor {
+    config: CompressionConfig,
+    stats: CompressionStats,
+}
+
+impl Compressor {
+    pub fn new(config: CompressionConfig) -> Self {
+        Compressor {
+            config,
+            stats: CompressionStats::default(),
+        }
+    }
+
+    /// Compresses the payload if it is big enough and compressing it helps, either way the result starts with a byte saying which it is.
+    pub fn compress(&mut self, payload: &[u8]) -> Vec<u8> {
+        let mut out = None;
+        if payload.len() >= self.config.min_size {
+            let mut encoder = DeflateEncoder::new(
+                vec![DEFLATED_PAYLOAD],
+                Compression::new(self.config.level.min(9)),
+            );
+            if encoder.write_all(payload).is_ok() {
+                out = encoder
+                    .finish()
+                    .ok()
+                    .filter(|deflated| deflated.len() < payload.len() + 1);
+            }
+        } // a payload that does not shrink is sent raw
+
+        let compressed = out.is_some();
+        let out = out.unwrap_or_else(|| {
+            let mut raw = Vec::with_capacity(payload.len() + 1);
+            raw.push(RAW_PAYLOAD);
+            raw.extend_from_slice(payload);
+            raw
+        });
+
+        self.stats.payloads += 1;
+        if compressed {
+            self.stats.compressed_payloads += 1;
+        }
+        self.stats.bytes_in += payload.len() as u64;
+        self.stats.bytes_out += out.len() as u64;
+        out
+    }
+
+    pub fn stats(&self) -> &CompressionStats {
+        &self.stats
+    }
+
+    /// Returns the stats so far, and starts counting again from nothing.
+    pub fn take_stats(&mut self) -> CompressionStats {
+        std::mem::take(&mut self.stats)
+    }
+}
+
+/// Reads a payload made by a Compressor, returning it as it was before it was compressed.
+pub fn decompress(payload: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    match payload.split_first() {
+        None => Err(CompressionError::Empty),
+        Some((&RAW_PAYLOAD, rest)) => Ok(rest.to_vec()),
+        Some((&DEFLATED_PAYLOAD, rest)) => {
+            let mut out = vec![];
+            // read one byte past the limit, so a payload that is too large can be told apart from one that is exactly the limit
+            DeflateDecoder::new(rest)
+                .take(MAX_FRAME_SIZE as u64 + 1)
+                .read_to_end(&mut out)
+                .map_err(|e| CompressionError::Corrupt(e.to_string()))?;
+            if out.len() > MAX_FRAME_SIZE {
+                return Err(CompressionError::TooLarge);
+            }
+            Ok(out)
+        }
+        Some((&flag, _)) => Err(CompressionError::UnknownFlag(flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::compression::{
+        decompress, CompressionConfig, CompressionError, Compressor, DEFAULT_COMPRESSION_MIN_SIZE,
+    };
+    use crate::gamestate::GameState;
+    use crate::gametypes::GameType::TANK;
+    use crate::tank::{TankBullet, TankGameState};
+    use crate::team::Team;
+
+    #[test]
+    fn compression_round_trip_test() {
+        let mut tgs = TankGameState::default();
+        for i in 0..100 {
+            tgs.bullets.push(TankBullet {
+                x: i as f32,
+                y: 2.0,
+                x_vel: 3.0,
+                y_vel: 4.0,
+                bounce_count: 1,
+                team: Team::RedTeam,
+                lag_compensation: 0.0,
+            });
+        }
+        let gs = GameState {
+            game_type: TANK(tgs),
+            ..GameState::default()
+        };
+        let payload = Codec::Json.encode(&gs).unwrap();
+
+        let mut compressor = Compressor::new(CompressionConfig::default());
+        let compressed = compressor.compress(&payload);
+        assert!(compressed.len() < payload.len() / 2); // lots of bullets that look alike should compress well
+        assert_eq!(decompress(&compressed).unwrap(), payload);
+
+        // small payloads are passed through with just the flag byte
+        let small = vec![7; DEFAULT_COMPRESSION_MIN_SIZE - 1];
+        let passed = compressor.compress(&small);
+        assert_eq!(passed.len(), small.len() + 1);
+        assert_eq!(decompress(&passed).unwrap(), small);
+
+        let stats = compressor.stats();
+        assert_eq!(stats.payloads, 2);
+        assert_eq!(stats.compressed_payloads, 1);
+        assert!(stats.ratio() < 1.0);
+
+        assert_eq!(decompress(&[]), Err(CompressionError::Empty));
+        assert_eq!(
+            decompress(&[9, 1, 2]),
+            Err(CompressionError::UnknownFlag(9))
+        );
+        assert!(decompress(&[1, 0xff, 0xff]).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use crate::transport::{Channel, Transport, TransportError};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// How long in milliseconds a payload lost on a reliable transport is held back, standing in for the wait before tcp sends it again.
+pub static LOSS_RESEND_DELAY_MS: u64 = 200;
+
+/// How long to wait on the transport underneath at a time while payloads are waiting to be sent, so they go out close to when they are due.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(1);
+
+/// NetworkConditions is how bad to make the connection, for testing the netcode against the kind of connections players actually have.
+/// Everything applies to what this side sends, so turn the conditioner on for both the server and the client to make both directions bad.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NetworkConditions {
+    /// Added to every payload sent.
+    pub latency_ms: u64,
+    /// Each payload gets up to this much more or less latency, picked at random. Payloads that can be lost can also be reordered by this.
+    pub jitter_ms: u64,
+    /// Chance from 0 to 1 of a payload being lost. Payloads sent reliably are held back instead, like tcp would while it sends them again.
+    pub loss: f64,
+    /// Chance from 0 to 1 of a payload being sent twice. Only payloads that can be lost are duplicated, reliable transports never deliver the same payload twice.
+    pub duplicate: f64,
+    /// Most kilobits sent each second, payloads queue up behind each other once this is used up. None for no limit.
+    pub bandwidth_kbps: Option<u32>,
+}
+
+impl NetworkConditions {
+    /// Reads the conditions from command line arguments, like --net-latency 100 --net-loss 5.
+    /// Latency and jitter are in milliseconds, loss and duplicate are percentages and bandwidth is in kilobits per second.
+    /// Returns None if none of them were given, values that do not parse are the same as not giving them.
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let arg_value = |name: &str| {
+            args.iter()
+                .skip_while(|arg| *arg != name)
+                .nth(1)
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|value| value.is_finite() && *value >= 0.0)
+        };
+        let conditions = NetworkConditions {
+            latency_ms: arg_value("--net-latency").unwrap_or(0.0) as u64,
+            jitter_ms: arg_value("--net-jitter").unwrap_or(0.0) as u64,
+            loss: (arg_value("--net-loss").unwrap_or(0.0) / 100.0).min(1.0),
+            duplicate: (arg_value("--net-duplicate").unwrap_or(0.0) / 100.0).min(1.0),
+            bandwidth_kbps: arg_value("--net-bandwidth")
+                .map(|kbps| kbps as u32)
+                .filter(|kbps| *kbps > 0),
+        };
+        if conditions == NetworkConditions::default() {
+            None
+        } else {
+            Some(conditions)
+        }
+    }
+}
+
+impl Display for NetworkConditions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}ms latency, {}ms jitter, {:.1}% loss, {:.1}% duplicated",
+            self.latency_ms,
+            self.jitter_ms,
+            self.loss * 100.0,
+            self.duplicate * 100.0
+        )?;
+        match self.bandwidth_kbps {
+            Some(kbps) => write!(f, ", {}kbps bandwidth", kbps),
+            None => write!(f, ", unlimited bandwidth"),
+        }
+    }
+}
+
+/// ConditionedTransport wraps another transport and makes what it sends worse by the given conditions, what it receives is left alone.
+/// Payloads held back are sent whenever the transport is used, the turn based connections always wait on the other side so they go out on time.
+pub struct ConditionedTransport {
+    inner: Box<dyn Transport>,
+    conditions: NetworkConditions,
+    rng: StdRng,
+    /// Payloads waiting to be sent, with the time they are due, earliest first.
+    queue: VecDeque<(Instant, Channel, Vec<u8>)>,
+    /// When the last payload finishes going out over the limited bandwidth.
+    link_free_at: Instant,
+    /// When the last reliable payload is due, reliable payloads are never let overtake each other.
+    last_reliable_due: Instant,
+}
+
+impl ConditionedTransport {
+    pub fn new(inner: Box<dyn Transport>, conditions: NetworkConditions) -> Self {
+        ConditionedTransport::with_rng(inner, conditions, StdRng::from_entropy())
+    }
+
+    /// Same as new, but the random loss and jitter come from the given seed so a run can be repeated exactly.
+    pub fn with_seed(inner: Box<dyn Transport>, conditions: NetworkConditions, seed: u64) -> Self {
+        ConditionedTransport::with_rng(inner, conditions, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(inner: Box<dyn Transport>, conditions: NetworkConditions, rng: StdRng) -> Self {
+        let now = Instant::now();
+        ConditionedTransport {
+            inner,
+            conditions,
+            rng,
+            queue: VecDeque::new(),
+            link_free_at: now,
+            last_reliable_due: now,
+        }
+    }
+
+    /// How many payloads are held back waiting to be sent.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// The latency for one payload, with jitter added on.
+    fn latency(&mut self) -> Duration {
+        let jitter = self.conditions.jitter_ms as i64;
+        let latency = self.conditions.latency_ms as i64 + self.rng.gen_range(-jitter..=jitter);
+        Duration::from_millis(latency.max(0) as u64)
+    }
+
+    /// Puts a payload in the queue to be sent at the given time, keeping the queue in order.
+    fn schedule(&mut self, due: Instant, channel: Channel, payload: Vec<u8>) {
+        let index = self
+            .queue
+            .partition_point(|(queued_due, _, _)| *queued_due <= due);
+        self.queue.insert(index, (due, channel, payload));
+    }
+
+    /// Sends every payload that is due.
+    fn flush(&mut self) -> Result<(), TransportError> {
+        let now = Instant::now();
+        while self.queue.front().is_some_and(|(due, _, _)| *due <= now) {
+            let (_, channel, payload) = self.queue.pop_front().unwrap();
+            self.inner.send_on(channel, &payload)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for ConditionedTransport {
+    fn cookie(&self) -> Option<u64> {
+        self.inner.cookie()
+    }
+
+    fn set_cookie(&mut self, cookie: u64) {
+        self.inner.set_cookie(cookie)
+    }
+
+    fn send_on(&mut self, channel: Channel, payload: &[u8]) -> Result<(), TransportError> {
+        let now = Instant::now();
+        let reliable = channel == Channel::Reliable || self.inner.is_reliable();
+        let lost = self.rng.gen_bool(self.conditions.loss);
+        if lost && !reliable {
+            return self.flush();
+        }
+
+        // the payload has to wait for the ones before it to go out over the limited bandwidth, then it travels for the latency
+        if let Some(kbps) = self.conditions.bandwidth_kbps {
+            let send_time =
+                Duration::from_secs_f64(payload.len() as f64 * 8.0 / (kbps as f64 * 1000.0));
+            self.link_free_at = self.link_free_at.max(now) + send_time;
+        }
+        let sent_at = self.link_free_at.max(now);
+        let mut due = sent_at + self.latency();
+        if reliable {
+            if lost {
+                due += Duration::from_millis(LOSS_RESEND_DELAY_MS) + self.latency();
+            }
+            due = due.max(self.last_reliable_due);
+            self.last_reliable_due = due;
+        } else if self.rng.gen_bool(self.conditions.duplicate) {
+            let duplicate_due = sent_at + self.latency();
+            self.schedule(duplicate_due, channel, payload.to_vec());
+        }
+        self.schedule(due, channel, payload.to_vec());
+        self.flush()
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.flush()?;
+            let now = Instant::now();
+            // while payloads are waiting only wait on the transport a little at a time, so they can be sent when they are due
+            let wait = match self.queue.front() {
+                Some(_) => FLUSH_INTERVAL.min(deadline.saturating_duration_since(now)),
+                None => deadline.saturating_duration_since(now),
+            };
+            if let Some(payload) = self.inner.recv_timeout(wait)? {
+                return Ok(Some(payload));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn is_reliable(&self) -> bool {
+        self.inner.is_reliable()
+    }
+
+    fn turn_timeout(&self) -> Duration {
+        self.inner.turn_timeout()
+    }
+}
+
+/// Anything still held back is sent straight away when the connection is dropped, so a goodbye is not lost to the conditioner.
+impl Drop for ConditionedTransport {
+    fn drop(&mut self) {
+        while let Some((_, channel, payload)) = self.queue.pop_front() {
+            if self.inner.send_on(channel, &payload).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conditioner::{ConditionedTransport, NetworkConditions};
+    use crate::transport::{Channel, Transport, TransportError};
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// A transport that remembers everything sent on it, and never receives anything.
+    struct RecordingTransport {
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+        reliable: bool,
+    }
+
+    impl Transport for RecordingTransport {
+        fn send_on(&mut self, _channel: Channel, payload: &[u8]) -> Result<(), TransportError> {
+            self.sent.lock().unwrap().push(payload.to_vec());
+            Ok(())
+        }
+
+        fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+            sleep(timeout);
+            Ok(None)
+        }
+
+        fn is_reliable(&self) -> bool {
+            self.reliable
+        }
+    }
+
+    fn conditioned(
+        reliable: bool,
+        conditions: NetworkConditions,
+    ) -> (ConditionedTransport, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let inner = RecordingTransport {
+            sent: sent.clone(),
+            reliable,
+        };
+        (
+            ConditionedTransport::with_seed(Box::new(inner), conditions, 7),
+            sent,
+        )
+    }
+
+    #[test]
+    fn conditioner_latency_and_loss_test() {
+        let conditions = NetworkConditions {
+            latency_ms: 30,
+            ..NetworkConditions::default()
+        };
+        let (mut transport, sent) = conditioned(true, conditions);
+        transport.send_on(Channel::Unreliable, b"first").unwrap();
+        assert!(sent.lock().unwrap().is_empty()); // held back for the latency
+        transport.recv_timeout(Duration::from_millis(60)).unwrap();
+        assert_eq!(*sent.lock().unwrap(), vec![b"first".to_vec()]);
+
+        // everything is lost on udp, but reliable payloads only arrive late and still in order
+        let conditions = NetworkConditions {
+            loss: 1.0,
+            ..NetworkConditions::default()
+        };
+        let (mut transport, sent) = conditioned(false, conditions);
+        transport.send_on(Channel::Unreliable, b"lost").unwrap();
+        transport.send_on(Channel::Reliable, b"one").unwrap();
+        transport.send_on(Channel::Reliable, b"two").unwrap();
+        assert_eq!(transport.queued(), 2);
+        transport.recv_timeout(Duration::from_millis(400)).unwrap();
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![b"one".to_vec(), b"two".to_vec()]
+        );
+
+        // duplicates only happen to payloads that can be lost
+        let conditions = NetworkConditions {
+            duplicate: 1.0,
+            ..NetworkConditions::default()
+        };
+        let (mut transport, sent) = conditioned(false, conditions);
+        transport.send_on(Channel::Unreliable, b"twice").unwrap();
+        transport.send_on(Channel::Reliable, b"once").unwrap();
+        drop(transport); // anything still held back goes out when dropped
+        assert_eq!(sent.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn network_conditions_from_args_test() {
+        let args: Vec<String> = [
+            "server",
+            "--net-latency",
+            "80",
+            "--net-loss",
+            "2.5",
+            "--udp",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        assert_eq!(
+            NetworkConditions::from_args(&args),
+            Some(NetworkConditions {
+                latency_ms: 80,
+                loss: 0.025,
+                ..NetworkConditions::default()
+            })
+        );
+        let args = vec![
+            "server".to_string(),
+            "--net-jitter".to_string(),
+            "lots".to_string(),
+        ];
+        assert_eq!(NetworkConditions::from_args(&args), None);
+    }
+}
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// How long in milliseconds a side can go without sending anything before it sends a heartbeat, so the other side knows it is still there.
+/// This is also the longest a reliable connection waits on the other side to end its turn before carrying on.
+pub static HEARTBEAT_INTERVAL_MS: u64 = 1000;
+
+/// Default for how many unreadable packets in a row a connection tolerates before it is dropped, a readable packet resets the count. Servers can change theirs.
+pub static MAX_BAD_PACKETS: u32 = 5;
+
+/// DisconnectReason is why a connection ended, it is sent to the other side in a disconnect message so it can be shown to the player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// The player left on purpose.
+    Left,
+    /// Nothing was heard from the other side for longer than the idle timeout.
+    TimedOut,
+    /// Too many packets in a row could not be read, usually a version mismatch.
+    TooManyBadPackets,
+    /// The server removed the client, with the reason why.
+    Kicked(String),
+    /// The server is shutting down.
+    ServerClosing,
+    /// The socket broke, with the error it broke with.
+    ConnectionLost(String),
+}
+
+/// ConnectionState is where a connection is in its life, it only moves towards Disconnected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    /// Messages are arriving normally.
+    Connected,
+    /// Nothing has been heard for longer than the heartbeat interval, the other side may be gone or its messages held up.
+    Stalled,
+    /// The connection is over, nothing more should be sent except the disconnect message.
+    Disconnected(DisconnectReason),
+}
+
+/// ConnectionMonitor keeps track of when we last heard from the other side and how many bad packets it has sent,
+/// and decides when the connection should be dropped. Both the server and the client keep one per connection.
+#[derive(Debug, Clone)]
+pub struct ConnectionMonitor {
+    state: ConnectionState,
+    idle_timeout: Duration,
+    max_bad_packets: u32,
+    last_heard: Instant,
+    last_sent: Instant,
+    bad_packets: u32,
+}
+
+impl ConnectionMonitor {
+    /// A monitor for a connection that was just opened, which will time out after nothing is heard for idle_timeout,
+    /// and be dropped once max_bad_packets unreadable packets arrive in a row.
+    pub fn new(idle_timeout: Duration, max_bad_packets: u32, now: Instant) -> Self {
+        ConnectionMonitor {
+            state: ConnectionState::Connected,
+            idle_timeout,
+            max_bad_packets,
+            last_heard: now,
+            last_sent: now,
+            bad_packets: 0,
+        }
+    }
+
+    /// Call whenever a readable packet arrives.
+    pub fn heard(&mut self, now: Instant) {
+        self.last_heard = now;
+        self.bad_packets = 0;
+        if self.state == ConnectionState::Stalled {
+            self.state = ConnectionState::Connected;
+        }
+    }
+
+    /// Call whenever something is sent to the other side.
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = now;
+    }
+
+    /// Call whenever a packet arrives that can not be read, the connection is disconnected once too many arrive in a row.
+    /// A bad packet still shows the other side is there, so it counts as hearing from them.
+    pub fn bad_packet(&mut self, now: Instant) -> &ConnectionState {
+        self.last_heard = now;
+        if self.state == ConnectionState::Stalled {
+            self.state = ConnectionState::Connected;
+        }
+        self.bad_packets += 1;
+        if self.bad_packets >= self.max_bad_packets {
+            self.disconnect(DisconnectReason::TooManyBadPackets);
+        }
+        &self.state
+    }
+
+    /// Ends the connection for the given reason, if it has not already ended.
+    pub fn disconnect(&mut self, reason: DisconnectReason) {
+        if !self.is_disconnected() {
+            self.state = ConnectionState::Disconnected(reason);
+        }
+    }
+
+    /// Moves the connection to stalled or timed out depending on how long it has been since we heard from the other side, and returns the new state.
+    pub fn check(&mut self, now: Instant) -> &ConnectionState {
+        if !self.is_disconnected() {
+            let silence = now.saturating_duration_since(self.last_heard);
+            if silence > self.idle_timeout {
+                self.state = ConnectionState::Disconnected(DisconnectReason::TimedOut);
+            } else if silence > Duration::from_millis(HEARTBEAT_INTERVAL_MS) {
+                self.state = ConnectionState::Stalled;
+            }
+        }
+        &self.state
+    }
+
+    /// True if nothing has been sent for a heartbeat interval, so a heartbeat should be sent.
+    pub fn needs_heartbeat(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_sent)
+            >= Duration::from_millis(HEARTBEAT_INTERVAL_MS)
+    }
+
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self.state, ConnectionState::Disconnected(_))
+    }
+
+    /// Why the connection ended, None if it has not.
+    pub fn disconnect_reason(&self) -> Option<&DisconnectReason> {
+        match &self.state {
+            ConnectionState::Disconnected(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::Left => write!(f, "left the game"),
+            DisconnectReason::TimedOut => write!(f, "timed out"),
+            DisconnectReason::TooManyBadPackets => write!(f, "too many unreadable packets"),
+            DisconnectReason::Kicked(reason) => write!(f, "kicked: {}", reason),
+            DisconnectReason::ServerClosing => write!(f, "server is shutting down"),
+            DisconnectReason::ConnectionLost(e) => write!(f, "connection lost: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection::{
+        ConnectionMonitor, ConnectionState, DisconnectReason, HEARTBEAT_INTERVAL_MS,
+        MAX_BAD_PACKETS,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn connection_stalls_then_times_out_test() {
+        let start = Instant::now();
+        let mut monitor = ConnectionMonitor::new(Duration::from_secs(5), MAX_BAD_PACKETS, start);
+        assert_eq!(monitor.check(start), &ConnectionState::Connected);
+
+        let stalled_at = start + Duration::from_millis(HEARTBEAT_INTERVAL_MS * 2);
+        assert_eq!(monitor.check(stalled_at), &ConnectionState::Stalled);
+        assert!(monitor.needs_heartbeat(stalled_at));
+        monitor.heard(stalled_at);
+        assert_eq!(monitor.check(stalled_at), &ConnectionState::Connected);
+
+        let timed_out_at = stalled_at + Duration::from_secs(6);
+        assert_eq!(
+            monitor.check(timed_out_at),
+            &ConnectionState::Disconnected(DisconnectReason::TimedOut)
+        );
+        // once disconnected, hearing from them again does not bring the connection back
+        monitor.heard(timed_out_at);
+        assert!(monitor.is_disconnected());
+    }
+
+    #[test]
+    fn connection_tolerates_some_bad_packets_test() {
+        let now = Instant::now();
+        let mut monitor = ConnectionMonitor::new(Duration::from_secs(5), 3, now);
+        for _ in 0..2 {
+            monitor.bad_packet(now);
+        }
+        monitor.heard(now); // a good packet resets the count
+        for _ in 0..2 {
+            assert_eq!(monitor.bad_packet(now), &ConnectionState::Connected);
+        }
+        assert_eq!(
+            monitor.bad_packet(now),
+            &ConnectionState::Disconnected(DisconnectReason::TooManyBadPackets)
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use crate::clientstate::ClientState;
+use crate::gamestate::GameState;
+use crate::gametypes::GameType;
+use crate::gametypes::GameType::{PONG, TANK};
+use crate::pong::PongGameState;
+use crate::tank::{TankBullet, TankGameState};
+use crate::team::Team;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+
+/// How many sent snapshots are remembered to be used as a baseline, if the client has not acknowledged any of these it is sent a full snapshot instead.
+pub static SNAPSHOT_HISTORY_SIZE: usize = 32;
+
+/// Snapshot is a numbered copy of the game state sent to a client, either in full or as the changes from an older snapshot the client already has.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub sequence: u32,
+    pub body: SnapshotBody,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SnapshotBody {
+    /// The whole game state, sent when the client has no baseline we still remember.
+    Full(GameState),
+    /// Only what changed since the snapshot with the baseline sequence number.
+    Delta {
+        baseline: u32,
+        delta: GameStateDelta,
+    },
+}
+
+/// GameStateDelta is the difference between two game states, anything that did not change is left out.
+/// The time and vote fields are tiny so they are always sent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameStateDelta {
+    pub time: SystemTime,
+    pub vote_running: bool,
+    pub vote_start_time: Option<SystemTime>,
+    pub game_type: GameTypeDelta,
+    /// Every client that was added or changed, in full.
+    pub changed_clients: HashMap<String, ClientState>,
+    pub removed_clients: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum GameTypeDelta {
+    /// The game mode switched, so there is nothing to diff against.
+    Replaced(GameType),
+    Pong(PongDelta),
+    Tank(TankDelta),
+}
+
+/// PongDelta holds only the pong fields that changed, None meaning the field is the same as the baseline.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PongDelta {
+    pub ball_x: Option<f32>,
+    pub ball_y: Option<f32>,
+    pub ball_xvel: Option<f32>,
+    pub ball_yvel: Option<f32>,
+    pub red_points: Option<i32>,
+    pub blue_points: Option<i32>,
+    pub ball_last_team_hit: Option<Team>,
+}
+
+/// TankDelta holds the tank scores that changed, and the bullets that changed by their index in the bullet list.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TankDelta {
+    pub red_score: Option<i32>,
+    pub blue_score: Option<i32>,
+    /// The bullet list is cut or extended to this length before the changed bullets are applied.
+    pub bullet_count: u32,
+    pub changed_bullets: Vec<(u32, TankBullet)>,
+}
+
+/// Returns the new value if it differs from the old one.
+fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<T> {
+    if old == new {
+        None
+    } else {
+        Some(new.clone())
+    }
+}
+
+impl PongDelta {
+    pub fn between(old: &PongGameState, new: &PongGameState) -> Self {
+        PongDelta {
+            ball_x: changed(&old.ball_x, &new.ball_x),
+            ball_y: changed(&old.ball_y, &new.ball_y),
+            ball_xvel: changed(&old.ball_xvel, &new.ball_xvel),
+            ball_yvel: changed(&old.ball_yvel, &new.ball_yvel),
+            red_points: changed(&old.red_points, &new.red_points),
+            blue_points: changed(&old.blue_points, &new.blue_points),
+            ball_last_team_hit: changed(&old.ball_last_team_hit, &new.ball_last_team_hit),
+        }
+    }
+
+    pub fn apply(&self, old: &PongGameState) -> PongGameState {
+        PongGameState {
+            ball_x: self.ball_x.unwrap_or(old.ball_x),
+            ball_y: self.ball_y.unwrap_or(old.ball_y),
+            ball_xvel: self.ball_xvel.unwrap_or(old.ball_xvel),
+            ball_yvel: self.ball_yvel.unwrap_or(old.ball_yvel),
+            red_points: self.red_points.unwrap_or(old.red_points),
+            blue_points: self.blue_points.unwrap_or(old.blue_points),
+            ball_last_team_hit: self
+                .ball_last_team_hit
+                .clone()
+                .unwrap_or_else(|| old.ball_last_team_hit.clone()),
+        }
+    }
+}
+
+impl TankDelta {
+    pub fn between(old: &TankGameState, new: &TankGameState) -> Self {
+        TankDelta {
+            red_score: changed(&old.red_score, &new.red_score),
+            blue_score: changed(&old.blue_score, &new.blue_score),
+            bullet_count: new.bullets.len() as u32,
+            changed_bullets: new
+                .bullets
+                .iter()
+                .enumerate()
+                .filter(|(index, bullet)| old.bullets.get(*index) != Some(bullet))
+                .map(|(index, bullet)| (index as u32, bullet.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn apply(&self, old: &TankGameState) -> TankGameState {
+        let mut bullets = old.bullets.clone();
+        bullets.truncate(self.bullet_count as usize);
+        for (index, bullet) in &self.changed_bullets {
+            let index = *index as usize;
+            if index < bullets.len() {
+                bullets[index] = bullet.clone();
+            } else {
+                bullets.push(bullet.clone()); // changed bullets are in order, so anything past the end is new
+            }
+        }
+        TankGameState {
+            red_score: self.red_score.unwrap_or(old.red_score),
+            blue_score: self.blue_score.unwrap_or(old.blue_score),
+            bullets,
+        }
+    }
+}
+
+impl GameStateDelta {
+    /// Works out everything that changed going from the old game state to the new one.
+    pub fn between(old: &GameState, new: &GameState) -> Self {
+        let game_type = match (&old.game_type, &new.game_type) {
+            (PONG(old_pgs), PONG(new_pgs)) => {
+                GameTypeDelta::Pong(PongDelta::between(old_pgs, new_pgs))
+            }
+            (TANK(old_tgs), TANK(new_tgs)) => {
+                GameTypeDelta::Tank(TankDelta::between(old_tgs, new_tgs))
+            }
+            (_, game_type) => GameTypeDelta::Replaced(game_type.clone()),
+        };
+
+        GameStateDelta {
+            time: new.time,
+            vote_running: new.vote_running,
+            vote_start_time: new.vote_start_time,
+            game_type,
+            changed_clients: new
+                .client_list
+                .iter()
+                .filter(|(uuid, client)| old.client_list.get(*uuid) != Some(client))
+                .map(|(uuid, client)| (uuid.clone(), client.clone()))
+                .collect(),
+            removed_clients: old
+                .client_list
+                .keys()
+                .filter(|uuid| !new.client_list.contains_key(*uuid))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Rebuilds the new game state from the old one it was made against.
+    pub fn apply(&self, old: &GameState) -> GameState {
+        let game_type = match (&self.game_type, &old.game_type) {
+            (GameTypeDelta::Replaced(game_type), _) => game_type.clone(),
+            (GameTypeDelta::Pong(delta), PONG(pgs)) => PONG(delta.apply(pgs)),
+            (GameTypeDelta::Tank(delta), TANK(tgs)) => TANK(delta.apply(tgs)),
+            // a delta against a different game type can only come from a mismatched baseline, start the mode fresh rather than panic
+            (GameTypeDelta::Pong(delta), _) => PONG(delta.apply(&PongGameState::default())),
+            (GameTypeDelta::Tank(delta), _) => TANK(delta.apply(&TankGameState::default())),
+        };
+
+        let mut client_list = old.client_list.clone();
+        for uuid in &self.removed_clients {
+            client_list.remove(uuid);
+        }
+        for (uuid, client) in &self.changed_clients {
+            client_list.insert(uuid.clone(), client.clone());
+        }
+
+        GameState {
+            time: self.time,
+            game_type,
+            client_list,
+            vote_running: self.vote_running,
+            vote_start_time: self.vote_start_time,
+        }
+    }
+}
+
+/// SnapshotHistory is the last few snapshots by sequence number, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotHistory {
+    states: VecDeque<(u32, GameState)>,
+}
+
+impl SnapshotHistory {
+    pub fn push(&mut self, sequence: u32, gs: GameState) {
+        self.states.push_back((sequence, gs));
+        while self.states.len() > SNAPSHOT_HISTORY_SIZE {
+            self.states.pop_front();
+        }
+    }
+
+    pub fn get(&self, sequence: u32) -> Option<&GameState> {
+        self.states
+            .iter()
+            .find(|(s, _)| *s == sequence)
+            .map(|(_, gs)| gs)
+    }
+
+    pub fn latest_sequence(&self) -> Option<u32> {
+        self.states.back().map(|(s, _)| *s)
+    }
+}
+
+/// SnapshotEncoder is kept by the server for each client, it numbers snapshots and diffs them against the newest one the client acknowledged.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotEncoder {
+    next_sequence: u32,
+    sent: SnapshotHistory,
+    acked: Option<u32>,
+}
+
+impl SnapshotEncoder {
+    /// Records that the client has the snapshot with the given sequence, older acks arriving late are ignored.
+    /// So are acks for snapshots we never sent or have already forgotten, a client could otherwise ack far ahead and never get a delta again.
+    pub fn ack(&mut self, sequence: u32) {
+        if sequence >= self.next_sequence || self.sent.get(sequence).is_none() {
+            return;
+        }
+        if self.acked.is_none_or(|acked| sequence > acked) {
+            self.acked = Some(sequence);
+        }
+    }
+
+    /// Turns the game state into the next snapshot, as a delta if the client has acknowledged a snapshot we still remember.
+    pub fn encode(&mut self, gs: &GameState) -> Snapshot {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let body = match self
+            .acked
+            .and_then(|acked| self.sent.get(acked).map(|baseline| (acked, baseline)))
+        {
+            Some((baseline, old)) => SnapshotBody::Delta {
+                baseline,
+                delta: GameStateDelta::between(old, gs),
+            },
+            None => SnapshotBody::Full(gs.clone()), // client is too far behind, or has not acked anything yet
+        };
+
+        self.sent.push(sequence, gs.clone());
+        Snapshot { sequence, body }
+    }
+}
+
+/// SnapshotDecoder is kept by the client, it rebuilds game states from snapshots and tracks which one should be acknowledged.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDecoder {
+    received: SnapshotHistory,
+}
+
+impl SnapshotDecoder {
+    /// Rebuilds the game state from the snapshot, None if it is older than the newest one received or its baseline has been forgotten.
+    pub fn decode(&mut self, snapshot: Snapshot) -> Option<GameState> {
+        if self
+            .received
+            .latest_sequence()
+            .is_some_and(|latest| snapshot.sequence <= latest)
+        {
+            return None; // out of order, we already have something newer
+        }
+
+        let gs = match snapshot.body {
+            SnapshotBody::Full(gs) => gs,
+            SnapshotBody::Delta { baseline, delta } => delta.apply(self.received.get(baseline)?),
+        };
+        self.received.push(snapshot.sequence, gs.clone());
+        Some(gs)
+    }
+
+    /// The sequence of the newest snapshot received, which is what should be acknowledged to the server.
+    pub fn latest_sequence(&self) -> Option<u32> {
+        self.received.latest_sequence()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientstate::ClientState;
+    use crate::codec::Codec;
+    use crate::delta::{SnapshotBody, SnapshotDecoder, SnapshotEncoder, SNAPSHOT_HISTORY_SIZE};
+    use crate::gamestate::GameState;
+    use crate::gametypes::GameType::{PONG, TANK};
+    use crate::tank::{TankBullet, TankGameState};
+    use crate::team::Team;
+
+    fn game_state_with_clients(count: usize) -> GameState {
+        let mut gs = GameState::default();
+        for i in 0..count {
+            gs.client_list
+                .insert(format!("client{}", i), ClientState::default());
+        }
+        gs
+    }
+
+    #[test]
+    fn delta_round_trip_test() {
+        let mut encoder = SnapshotEncoder::default();
+        let mut decoder = SnapshotDecoder::default();
+
+        let mut gs = game_state_with_clients(3);
+        let first = encoder.encode(&gs);
+        assert!(matches!(first.body, SnapshotBody::Full(_)));
+        decoder.decode(first).unwrap();
+        encoder.ack(decoder.latest_sequence().unwrap());
+
+        if let PONG(pgs) = &mut gs.game_type {
+            pgs.ball_x += 1.0;
+        }
+        gs.client_list.remove("client0");
+        gs.client_list.get_mut("client1").unwrap().mouse_pos = (4.0, 2.0);
+        let second = encoder.encode(&gs);
+        match &second.body {
+            SnapshotBody::Delta { delta, .. } => {
+                assert_eq!(delta.changed_clients.len(), 1);
+                assert_eq!(delta.removed_clients, vec!["client0".to_string()]);
+            }
+            SnapshotBody::Full(_) => panic!("expected a delta"),
+        }
+        let decoded = decoder.decode(second).unwrap();
+        assert_eq!(decoded.client_list, gs.client_list);
+        match (decoded.game_type, &gs.game_type) {
+            (PONG(decoded), PONG(pgs)) => assert_eq!(&decoded, pgs),
+            _ => panic!("decoded game type was not pong"),
+        }
+    }
+
+    #[test]
+    fn delta_tank_bullets_test() {
+        let bullet = |x: f32| TankBullet {
+            x,
+            y: 0.0,
+            x_vel: 1.0,
+            y_vel: 1.0,
+            bounce_count: 0,
+            team: Team::RedTeam,
+            lag_compensation: 0.0,
+        };
+        let tgs = TankGameState {
+            bullets: vec![bullet(1.0), bullet(2.0), bullet(3.0)],
+            ..TankGameState::default()
+        };
+        let mut gs = GameState {
+            game_type: TANK(tgs),
+            ..GameState::default()
+        };
+
+        let mut encoder = SnapshotEncoder::default();
+        let mut decoder = SnapshotDecoder::default();
+        decoder.decode(encoder.encode(&gs)).unwrap();
+        encoder.ack(0);
+
+        if let TANK(tgs) = &mut gs.game_type {
+            tgs.bullets.remove(2);
+            tgs.bullets[1].x = 5.0;
+            tgs.bullets.push(bullet(6.0));
+        }
+        let decoded = decoder.decode(encoder.encode(&gs)).unwrap();
+        match (decoded.game_type, &gs.game_type) {
+            (TANK(decoded), TANK(tgs)) => assert_eq!(&decoded, tgs),
+            _ => panic!("decoded game type was not tank"),
+        }
+    }
+
+    #[test]
+    fn delta_smaller_than_full_test() {
+        let gs = game_state_with_clients(16);
+        let mut encoder = SnapshotEncoder::default();
+        let full = encoder.encode(&gs);
+        encoder.ack(full.sequence);
+        let delta = encoder.encode(&gs);
+        assert!(matches!(delta.body, SnapshotBody::Delta { .. }));
+
+        let full_size = Codec::Bincode.encode(&full).unwrap().len();
+        let delta_size = Codec::Bincode.encode(&delta).unwrap().len();
+        assert!(delta_size * 10 < full_size);
+    }
+
+    #[test]
+    fn delta_falls_back_to_full_test() {
+        let gs = game_state_with_clients(1);
+        let mut encoder = SnapshotEncoder::default();
+        encoder.encode(&gs);
+        encoder.ack(0);
+        for _ in 0..SNAPSHOT_HISTORY_SIZE {
+            encoder.encode(&gs);
+        }
+        // the acked snapshot has been forgotten, so the client must be sent everything again
+        assert!(matches!(encoder.encode(&gs).body, SnapshotBody::Full(_)));
+    }
+
+    #[test]
+    fn delta_ignores_bad_ack_test() {
+        let gs = game_state_with_clients(1);
+        let mut encoder = SnapshotEncoder::default();
+        encoder.encode(&gs);
+        encoder.ack(0);
+
+        // acks for snapshots that were never sent do not move the baseline
+        encoder.ack(u32::MAX);
+        encoder.ack(5);
+        match encoder.encode(&gs).body {
+            SnapshotBody::Delta { baseline, .. } => assert_eq!(baseline, 0),
+            SnapshotBody::Full(_) => panic!("expected a delta"),
+        }
+
+        // a bad ack before anything was sent does not stop the real ack from counting
+        let mut encoder = SnapshotEncoder::default();
+        encoder.ack(u32::MAX);
+        encoder.encode(&gs);
+        encoder.ack(0);
+        assert!(matches!(
+            encoder.encode(&gs).body,
+            SnapshotBody::Delta { .. }
+        ));
+    }
+}
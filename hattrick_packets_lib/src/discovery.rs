@@ -0,0 +1,205 @@
+use crate::handshake::PROTOCOL_VERSION;
+use crate::transport::is_timeout;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// The udp port servers listen on for discovery queries, clients broadcast their query to this port on the local network.
+pub static DISCOVERY_PORT: u16 = 8113;
+
+/// How long in milliseconds a client waits for servers to answer its discovery query.
+pub static DISCOVERY_TIMEOUT_MS: u64 = 500;
+
+/// Largest discovery datagram in bytes, announcements are small so anything bigger is not one of ours.
+const MAX_DISCOVERY_DATAGRAM_SIZE: usize = 2048;
+
+/// DiscoveryQuery is broadcast by clients looking for servers, every server that hears it answers with a ServerAnnouncement.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryQuery {
+    pub protocol_version: u32,
+}
+
+/// ServerAnnouncement is a servers answer to a discovery query, it has everything the server list shows about the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServerAnnouncement {
+    pub name: String,
+    /// The game mode being played right now, like pong.
+    pub game_mode: String,
+    /// How many players are connected, not counting the ai.
+    pub player_count: u32,
+    /// The most players the server will let connect at once.
+    pub capacity: u32,
+    /// The port to connect to for the game, the address is wherever the announcement came from.
+    pub port: u16,
+    /// The servers protocol version, servers that do not match ours will reject us so they are shown as incompatible.
+    pub protocol_version: u32,
+}
+
+impl ServerAnnouncement {
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.player_count >= self.capacity
+    }
+}
+
+/// DiscoveredServer is a server that answered our discovery query, with the address to connect to it on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub announcement: ServerAnnouncement,
+}
+
+/// Asks every server on the local network, and on this machine, to announce itself, returning every server that answered in time.
+/// A server on this machine hears both the broadcast and the query to localhost, so its localhost answer is dropped if it also answered over the network.
+pub fn discover_lan_servers(timeout: Duration) -> std::io::Result<Vec<DiscoveredServer>> {
+    let servers = discover_servers(
+        &[
+            SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, DISCOVERY_PORT)),
+        ],
+        timeout,
+    )?;
+    Ok(servers
+        .iter()
+        .filter(|server| {
+            !server.address.ip().is_loopback()
+                || !servers.iter().any(|other| {
+                    !other.address.ip().is_loopback() && other.announcement == server.announcement
+                })
+        })
+        .cloned()
+        .collect())
+}
+
+/// Sends a discovery query to each of the given addresses, and collects the answers that arrive before the timeout.
+/// A server that answers more than once, say to both the broadcast and to localhost, is only listed once for each address it answered from.
+pub fn discover_servers(
+    targets: &[SocketAddr],
+    timeout: Duration,
+) -> std::io::Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    let query = serde_json::to_vec(&DiscoveryQuery {
+        protocol_version: PROTOCOL_VERSION,
+    })?;
+    for target in targets {
+        let _ = socket.send_to(&query, target); // a network without broadcast should not stop us asking localhost
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut found: HashMap<SocketAddr, ServerAnnouncement> = HashMap::new();
+    let mut buffer = [0; MAX_DISCOVERY_DATAGRAM_SIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => break,
+            Err(e) => return Err(e),
+        };
+        if let Ok(announcement) = serde_json::from_slice::<ServerAnnouncement>(&buffer[..len]) {
+            found.insert(SocketAddr::new(from.ip(), announcement.port), announcement);
+        } // anything that is not an announcement is someone elses traffic, ignore it
+    }
+
+    let mut servers: Vec<DiscoveredServer> = found
+        .into_iter()
+        .map(|(address, announcement)| DiscoveredServer {
+            address,
+            announcement,
+        })
+        .collect();
+    servers.sort_by(|a, b| {
+        a.announcement
+            .name
+            .cmp(&b.announcement.name)
+            .then(a.address.cmp(&b.address))
+    });
+    Ok(servers)
+}
+
+/// DiscoveryResponder is the servers side of discovery, it waits for queries and answers each one with an announcement.
+pub struct DiscoveryResponder {
+    socket: UdpSocket,
+}
+
+impl DiscoveryResponder {
+    /// Starts listening for discovery queries on the given port, 0 picks any free port.
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        Ok(DiscoveryResponder {
+            socket: UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// How long answer_next waits for a query before giving up with a timeout error, None waits forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// Waits for the next discovery query and answers it with the announcement made by the given function, which is only called once a query arrives.
+    /// Datagrams that are not discovery queries are ignored.
+    pub fn answer_next(&self, announce: impl Fn() -> ServerAnnouncement) -> std::io::Result<()> {
+        let mut buffer = [0; MAX_DISCOVERY_DATAGRAM_SIZE];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut buffer)?;
+            if serde_json::from_slice::<DiscoveryQuery>(&buffer[..len]).is_ok() {
+                let announcement = serde_json::to_vec(&announce())?;
+                self.socket.send_to(&announcement, from)?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discovery::{discover_servers, DiscoveryResponder, ServerAnnouncement};
+    use crate::handshake::PROTOCOL_VERSION;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn discovery_finds_server_test() {
+        let responder = DiscoveryResponder::bind(0).unwrap();
+        let port = responder.local_addr().unwrap().port();
+        let announcement = ServerAnnouncement {
+            name: "test server".to_string(),
+            game_mode: "pong".to_string(),
+            player_count: 3,
+            capacity: 16,
+            port: 8111,
+            protocol_version: PROTOCOL_VERSION,
+        };
+        let answer = announcement.clone();
+        let server = thread::spawn(move || responder.answer_next(|| answer.clone()).unwrap());
+
+        let servers = discover_servers(
+            &[SocketAddr::from((Ipv4Addr::LOCALHOST, port))],
+            Duration::from_millis(500),
+        )
+        .unwrap();
+        server.join().unwrap();
+
+        assert_eq!(servers.len(), 1);
+        // the address to join on is where the answer came from, with the game port from the announcement
+        assert_eq!(
+            servers[0].address,
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 8111))
+        );
+        assert_eq!(servers[0].announcement, announcement);
+        assert!(servers[0].announcement.is_compatible());
+        assert!(!servers[0].announcement.is_full());
+    }
+}
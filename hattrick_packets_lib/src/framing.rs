@@ -0,0 +1,236 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// Number of bytes used by the length prefix at the start of every frame, the length is stored as a big endian u32.
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+/// Largest payload size in bytes that a frame is allowed to carry, anything larger is treated as a broken or malicious stream.
+pub static MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Size of the chunk we read from a stream at a time when waiting on a frame.
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// FrameError is every way that sending or receiving a frame can fail.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The underlying stream returned an error, this includes read and write timeouts.
+    Io(std::io::Error),
+    /// A frame was larger than the maximum frame size, size is the length of the frame that was attempted.
+    TooLarge { size: usize, max: usize },
+    /// The stream was closed by the other side, possibly in the middle of a frame.
+    Closed,
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "io error: {}", e),
+            FrameError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "frame of {} bytes exceeds maximum of {} bytes",
+                    size, max
+                )
+            }
+            FrameError::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+/// Prefixes the given payload with its length, producing a single frame ready to be written to a stream.
+pub fn encode_frame(payload: &[u8], max_frame_size: usize) -> Result<Vec<u8>, FrameError> {
+    if payload.len() > max_frame_size {
+        return Err(FrameError::TooLarge {
+            size: payload.len(),
+            max: max_frame_size,
+        });
+    }
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// FrameDecoder is a streaming decoder, bytes are pushed into it as they arrive in whatever size chunks the stream gives us,
+/// and whole frames are pulled out of it once they are complete. Partial frames are kept until the rest of their bytes arrive.
+#[derive(Debug, Clone)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        FrameDecoder::new(MAX_FRAME_SIZE)
+    }
+}
+
+impl FrameDecoder {
+    pub fn new(max_frame_size: usize) -> Self {
+        FrameDecoder {
+            buffer: vec![],
+            max_frame_size,
+        }
+    }
+
+    /// Add newly received bytes to the end of the decoders buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Number of bytes buffered that have not been returned as a frame yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the next complete frame payload if one has fully arrived, Ok(None) if more bytes are needed.
+    /// An error is returned if the length prefix claims a frame larger than the maximum frame size, the stream can not be recovered after this.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameError> {
+        if self.buffer.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let mut header = [0; FRAME_HEADER_SIZE];
+        header.copy_from_slice(&self.buffer[..FRAME_HEADER_SIZE]);
+        let length = u32::from_be_bytes(header) as usize;
+
+        if length > self.max_frame_size {
+            return Err(FrameError::TooLarge {
+                size: length,
+                max: self.max_frame_size,
+            });
+        }
+
+        if self.buffer.len() < FRAME_HEADER_SIZE + length {
+            return Ok(None);
+        }
+
+        let payload = self.buffer[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + length].to_vec();
+        self.buffer.drain(..FRAME_HEADER_SIZE + length);
+        Ok(Some(payload))
+    }
+}
+
+/// FramedStream wraps a stream (usually a TcpStream) and sends and receives whole length-prefixed frames over it.
+/// Because received bytes are kept in a FrameDecoder, a read timeout in the middle of a frame does not lose the bytes already read.
+#[derive(Debug)]
+pub struct FramedStream<S> {
+    stream: S,
+    decoder: FrameDecoder,
+    max_frame_size: usize,
+}
+
+impl<S: Read + Write> FramedStream<S> {
+    pub fn new(stream: S) -> Self {
+        FramedStream::with_max_frame_size(stream, MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(stream: S, max_frame_size: usize) -> Self {
+        FramedStream {
+            stream,
+            decoder: FrameDecoder::new(max_frame_size),
+            max_frame_size,
+        }
+    }
+
+    /// Writes the payload as a single frame and flushes the stream.
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), FrameError> {
+        let frame = encode_frame(payload, self.max_frame_size)?;
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Blocks until a whole frame has been received and returns its payload.
+    pub fn recv(&mut self) -> Result<Vec<u8>, FrameError> {
+        let mut buf = [0; READ_CHUNK_SIZE];
+        loop {
+            if let Some(frame) = self.decoder.next_frame()? {
+                return Ok(frame);
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(FrameError::Closed),
+                Ok(n) => self.decoder.push(&buf[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(FrameError::Io(e)),
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{encode_frame, FrameDecoder, FrameError, FramedStream, MAX_FRAME_SIZE};
+    use std::io::Cursor;
+
+    #[test]
+    fn decoder_partial_frames_test() {
+        let frame = encode_frame(b"hello world", MAX_FRAME_SIZE).unwrap();
+        let mut decoder = FrameDecoder::default();
+        decoder.push(&frame[..3]);
+        assert!(decoder.next_frame().unwrap().is_none());
+        decoder.push(&frame[3..9]);
+        assert!(decoder.next_frame().unwrap().is_none());
+        decoder.push(&frame[9..]);
+        assert_eq!(decoder.next_frame().unwrap().unwrap(), b"hello world");
+        assert_eq!(decoder.buffered_len(), 0);
+    }
+
+    #[test]
+    fn decoder_merged_frames_test() {
+        let mut bytes = encode_frame(b"first", MAX_FRAME_SIZE).unwrap();
+        bytes.extend(encode_frame(b"", MAX_FRAME_SIZE).unwrap());
+        bytes.extend(encode_frame(b"third", MAX_FRAME_SIZE).unwrap());
+        let mut decoder = FrameDecoder::default();
+        decoder.push(&bytes);
+        assert_eq!(decoder.next_frame().unwrap().unwrap(), b"first");
+        assert_eq!(decoder.next_frame().unwrap().unwrap(), b"");
+        assert_eq!(decoder.next_frame().unwrap().unwrap(), b"third");
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn decoder_too_large_test() {
+        let frame = encode_frame(&[0; 64], MAX_FRAME_SIZE).unwrap();
+        let mut decoder = FrameDecoder::new(16);
+        decoder.push(&frame);
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(FrameError::TooLarge { size: 64, max: 16 })
+        ));
+        assert!(matches!(
+            encode_frame(&[0; 64], 16),
+            Err(FrameError::TooLarge { size: 64, max: 16 })
+        ));
+    }
+
+    #[test]
+    fn framed_stream_large_message_test() {
+        let payload = vec![7; 20_000]; // larger than the old fixed 8192 byte buffer
+        let mut writer = FramedStream::new(Cursor::new(vec![]));
+        writer.send(&payload).unwrap();
+        writer.send(b"after").unwrap();
+
+        let bytes = writer.get_ref().get_ref().clone();
+        let mut reader = FramedStream::new(Cursor::new(bytes));
+        assert_eq!(reader.recv().unwrap(), payload);
+        assert_eq!(reader.recv().unwrap(), b"after");
+        assert!(matches!(reader.recv(), Err(FrameError::Closed)));
+    }
+}
@@ -9,9 +9,14 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::time::SystemTime;
 
+/// Default for how long in seconds the vote for the next game mode runs, servers can change it in their game rules.
 pub static VOTE_TIME: f32 = 10.0;
 pub static MAX_VOTE_NUM: i32 = 2;
 
+/// Default for how many times a second each client is sent a snapshot, servers can change it and tell each client theirs in the welcome.
+/// Clients send exactly one input per snapshot, so this is also how many inputs a second the game is tuned for.
+pub static SNAPSHOT_RATE: u32 = 30;
+
 /// GameState holds the game type, system time, and list of players. This is the single struct that is sent to each client every frame of gameplay.
 /// Examples of things that go in GameState are things that need to be known by literally all clients, and the server, at the same time for gameplay to work properly.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -47,7 +52,9 @@ impl Default for GameState {
 }
 
 impl GameState {
-    pub fn try_conclude_vote(&mut self) {
+    /// Starts the vote timer if it has not been started, and if the vote time has passed, switches to the game type with the most votes.
+    /// vote_time is how long in seconds the vote runs for. Returns the game type that won when the vote concludes, otherwise None.
+    pub fn try_conclude_vote(&mut self, vote_time: f32) -> Option<GameType> {
         if self.vote_running {
             // println!("vote running");
             if let Some(vote_start_time) = self.vote_start_time {
@@ -62,7 +69,7 @@ impl GameState {
                     .duration_since(vote_start_time)
                     .unwrap()
                     .as_secs_f32()
-                    >= VOTE_TIME
+                    >= vote_time
                 {
                     let possible_outcomes = vec![
                         PONG(PongGameState::default()),
@@ -91,7 +98,7 @@ impl GameState {
 
                     println!("won game type: {:?}, {}", voted_game_type, _vote_count);
 
-                    self.game_type = voted_game_type;
+                    self.game_type = voted_game_type.clone();
                     self.vote_start_time = None;
                     for client in self.client_list.values_mut() {
                         // probably not the best way to reset all the game modes, but I am happy with it for now.
@@ -99,10 +106,12 @@ impl GameState {
                         client.tank_client_state = TankClientState::default();
                         client.pong_client_state = PongClientState::default();
                     }
+                    return Some(voted_game_type);
                 }
             } else {
                 self.vote_start_time = Some(SystemTime::now());
             }
         }
+        None
     }
 }
@@ -1,6 +1,7 @@
 use crate::pong::PongGameState;
 use crate::tank::TankGameState;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 
 /// GameType is the game mode that is being played, for example pong, each game mode contains a struct within the enumeration that stores the games data like any objects the game should render
 /// On top of that, the GameType is to be pattern matched for each frame, allowing the workflow of adding new game types to be really easy :)
@@ -10,6 +11,16 @@ pub enum GameType {
     TANK(TankGameState),
 }
 
+/// The name of the game mode, as shown to players.
+impl Display for GameType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameType::PONG(_) => write!(f, "pong"),
+            GameType::TANK(_) => write!(f, "tank"),
+        }
+    }
+}
+
 // GameTypeClient is a enum for client states to hold onto that contain the given game type as well as the variables that are specific to that game type.
 // #[derive(Serialize, Deserialize, Debug, Clone)]
 // pub enum GameTypeClient {
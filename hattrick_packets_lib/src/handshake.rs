@@ -0,0 +1,225 @@
+use crate::codec::Codec;
+use crate::rules::GameRules;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Version of the wire protocol, this must be bumped every time a packet changes shape so mismatched clients and servers refuse each other instead of failing to parse.
+pub static PROTOCOL_VERSION: u32 = 17;
+
+/// The handshake is always sent as json, no matter the codec that gets picked, so that any version of the game can read it.
+pub static HANDSHAKE_CODEC: Codec = Codec::Json;
+
+/// Capability is an optional protocol feature that the client asks for during the handshake, the server answers with the capabilities it accepted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Capability {
+    /// Use the given codec for every packet after the handshake.
+    Codec(Codec),
+    /// Compress what the server sends after the handshake, see the compression module. The server may turn this down.
+    Compression,
+}
+
+/// ClientHello is the first packet a client sends after connecting, before any game packets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientHello {
+    pub protocol_version: u32,
+    /// Version of the client build, only used for logging on the server.
+    pub build_version: String,
+    pub capabilities: Vec<Capability>,
+    /// The session token from the last time we were welcomed by this server, so a reconnecting player carries on as the same player.
+    #[serde(default)]
+    pub session_token: Option<String>,
+}
+
+/// ServerWelcome is sent in response to a ClientHello that the server has accepted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerWelcome {
+    pub protocol_version: u32,
+    pub build_version: String,
+    /// The capabilities from the hello that the server accepted, anything not in here should not be used by the client.
+    pub capabilities: Vec<Capability>,
+    /// The id the server knows this client by, which is their key in the game states client list.
+    pub client_id: String,
+    /// Secret that the client sends in its hello when it reconnects, to get the same client id and game state back. It should never be shown to other players.
+    pub session_token: String,
+    /// True if the session token in the hello was accepted, and the client is carrying on as the player it was before.
+    pub resumed: bool,
+    /// The rules the server is running the game with, the client needs them to predict its own movement the same way the server moves it.
+    pub rules: GameRules,
+    /// How many snapshots a second the server sends, and so how many inputs a second it expects. The client steps its prediction by one snapshot for each input.
+    pub snapshot_rate: u32,
+    /// Random number the client has to send back with every udp datagram after the handshake, None over tcp and websockets.
+    /// Only a client that really has the address the hello came from gets to see it, so the server knows the address was not faked.
+    pub udp_cookie: Option<u64>,
+}
+
+/// RejectReason is why the server refused a client during the handshake, it is displayed to the player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    IncompatibleProtocol {
+        server_version: u32,
+        client_version: u32,
+    },
+    MalformedHello(String),
+    /// The server already has as many players as it allows.
+    ServerFull {
+        capacity: u32,
+    },
+    /// The server is shutting down, and is not letting anyone new in.
+    ShuttingDown,
+}
+
+/// HandshakeResponse is the servers answer to a ClientHello.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum HandshakeResponse {
+    Welcome(ServerWelcome),
+    Rejected(RejectReason),
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::IncompatibleProtocol {
+                server_version,
+                client_version,
+            } => write!(
+                f,
+                "incompatible game version, server protocol is {} and client protocol is {}",
+                server_version, client_version
+            ),
+            RejectReason::MalformedHello(e) => write!(f, "server could not read hello: {}", e),
+            RejectReason::ServerFull { capacity } => {
+                write!(f, "server is full, it allows {} players", capacity)
+            }
+            RejectReason::ShuttingDown => write!(f, "server is shutting down"),
+        }
+    }
+}
+
+impl ClientHello {
+    /// Creates a hello for this protocol version, build_version should be the version of the crate sending it.
+    pub fn new(build_version: &str, capabilities: Vec<Capability>) -> Self {
+        ClientHello {
+            protocol_version: PROTOCOL_VERSION,
+            build_version: build_version.to_string(),
+            capabilities,
+            session_token: None,
+        }
+    }
+
+    /// Checks if the server can accept this hello, without answering it.
+    pub fn check(&self) -> Result<(), RejectReason> {
+        if self.protocol_version != PROTOCOL_VERSION {
+            return Err(RejectReason::IncompatibleProtocol {
+                server_version: PROTOCOL_VERSION,
+                client_version: self.protocol_version,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ServerWelcome {
+    /// Returns the codec that was accepted during the handshake, json if none was asked for.
+    pub fn codec(&self) -> Codec {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability {
+                Capability::Codec(codec) => Some(*codec),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// True if the server agreed to compress what it sends us.
+    pub fn compression(&self) -> bool {
+        self.capabilities.contains(&Capability::Compression)
+    }
+}
+
+/// Decides if the server should accept the given hello, returning the response to be sent back to the client.
+/// client_id and session_token are what the client will have if it is accepted, resumed is true if the hellos session token was accepted.
+/// rules are the servers game rules, which are sent to the client.
+pub fn respond_to_hello(
+    hello: &ClientHello,
+    build_version: &str,
+    client_id: &str,
+    session_token: &str,
+    resumed: bool,
+    rules: &GameRules,
+    snapshot_rate: u32,
+) -> HandshakeResponse {
+    if let Err(reason) = hello.check() {
+        return HandshakeResponse::Rejected(reason);
+    }
+
+    HandshakeResponse::Welcome(ServerWelcome {
+        protocol_version: PROTOCOL_VERSION,
+        build_version: build_version.to_string(),
+        capabilities: hello.capabilities.clone(), // every capability is supported at the moment
+        client_id: client_id.to_string(),
+        session_token: session_token.to_string(),
+        resumed,
+        rules: *rules,
+        snapshot_rate,
+        udp_cookie: None, // filled in by the server for udp connections
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::gamestate::SNAPSHOT_RATE;
+    use crate::handshake::{
+        respond_to_hello, Capability, ClientHello, HandshakeResponse, RejectReason,
+        PROTOCOL_VERSION,
+    };
+    use crate::rules::GameRules;
+
+    #[test]
+    fn hello_accepted_test() {
+        let hello = ClientHello::new("0.0.0", vec![Capability::Codec(Codec::Bincode)]);
+        match respond_to_hello(
+            &hello,
+            "0.0.1",
+            "client",
+            "token",
+            false,
+            &GameRules::default(),
+            SNAPSHOT_RATE,
+        ) {
+            HandshakeResponse::Welcome(welcome) => {
+                assert_eq!(welcome.protocol_version, PROTOCOL_VERSION);
+                assert_eq!(welcome.codec(), Codec::Bincode);
+                assert_eq!(welcome.client_id, "client");
+                assert_eq!(welcome.session_token, "token");
+                assert_eq!(welcome.snapshot_rate, SNAPSHOT_RATE);
+            }
+            HandshakeResponse::Rejected(reason) => panic!("hello was rejected: {}", reason),
+        }
+    }
+
+    #[test]
+    fn hello_version_mismatch_test() {
+        let mut hello = ClientHello::new("0.0.0", vec![]);
+        hello.protocol_version = PROTOCOL_VERSION + 1;
+        assert!(hello.check().is_err());
+        match respond_to_hello(
+            &hello,
+            "0.0.1",
+            "client",
+            "token",
+            false,
+            &GameRules::default(),
+            SNAPSHOT_RATE,
+        ) {
+            HandshakeResponse::Welcome(_) => panic!("mismatched hello was accepted"),
+            HandshakeResponse::Rejected(reason) => assert_eq!(
+                reason,
+                RejectReason::IncompatibleProtocol {
+                    server_version: PROTOCOL_VERSION,
+                    client_version: PROTOCOL_VERSION + 1,
+                }
+            ),
+        }
+    }
+}
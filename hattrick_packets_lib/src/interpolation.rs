@@ -0,0 +1,283 @@
+use crate::gamestate::{GameState, SNAPSHOT_RATE};
+use crate::gametypes::GameType::{PONG, TANK};
+use crate::{GAME_HEIGHT, GAME_WIDTH};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Shortest delay in milliseconds that remote entities are drawn in the past.
+pub static MIN_INTERPOLATION_DELAY_MS: u64 = 50;
+
+/// Longest delay in milliseconds that remote entities are drawn in the past, no matter how bad the jitter gets.
+pub static MAX_INTERPOLATION_DELAY_MS: u64 = 250;
+
+/// How far in milliseconds past the newest snapshot entities keep moving when snapshots are late, after this they freeze until the next one arrives.
+pub static MAX_EXTRAPOLATION_MS: u64 = 100;
+
+/// How many snapshots the buffer holds, at the default snapshot rate this is about a second.
+pub static INTERPOLATION_BUFFER_SIZE: usize = 32;
+
+/// SnapshotBuffer holds the last few snapshots from the server along with when they were sent, so the client can draw everyone a little in the past and smoothly move them between snapshots.
+/// The delay adapts to the jitter of the connection, the worse the jitter the further in the past we draw, so there is almost always a snapshot on either side of the render time.
+#[derive(Debug, Clone)]
+pub struct SnapshotBuffer {
+    /// Snapshots by server time in seconds, oldest first.
+    snapshots: VecDeque<(f64, GameState)>,
+    /// Local clock minus server clock in seconds, estimated from the fastest snapshots to arrive.
+    clock_offset: Option<f64>,
+    /// Average of how much later than the fastest snapshot each snapshot arrives, in seconds.
+    jitter: f64,
+    /// How far in the past to draw in seconds.
+    delay: f64,
+    /// How many snapshots a second the server sends, from its welcome.
+    snapshot_rate: u32,
+}
+
+impl Default for SnapshotBuffer {
+    fn default() -> Self {
+        SnapshotBuffer::new(SNAPSHOT_RATE)
+    }
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+/// Interpolates between two angles in degrees the short way around.
+fn lerp_degrees(from: f32, to: f32, t: f32) -> f32 {
+    let difference = (to - from + 180.0).rem_euclid(360.0) - 180.0;
+    from + difference * t
+}
+
+impl SnapshotBuffer {
+    /// An empty buffer for a server that sends snapshot_rate snapshots a second.
+    pub fn new(snapshot_rate: u32) -> Self {
+        SnapshotBuffer {
+            snapshots: VecDeque::new(),
+            clock_offset: None,
+            jitter: 0.0,
+            delay: MIN_INTERPOLATION_DELAY_MS as f64 / 1000.0,
+            snapshot_rate,
+        }
+    }
+
+    /// Adds a snapshot that arrived at the given time, snapshots older than the newest one are ignored.
+    pub fn push(&mut self, gs: GameState, received_at: SystemTime) {
+        let server_time = seconds(gs.time);
+        if let Some((newest, _)) = self.snapshots.back() {
+            if server_time <= *newest {
+                return;
+            }
+        }
+
+        let sample = seconds(received_at) - server_time;
+        let offset = match self.clock_offset {
+            // a faster snapshot than any before means we had the offset too high, otherwise drift slowly towards the sample in case the clocks drift apart
+            Some(offset) if sample > offset => offset + (sample - offset) * 0.01,
+            _ => sample,
+        };
+        self.clock_offset = Some(offset);
+        self.jitter += ((sample - offset) - self.jitter) * 0.1;
+
+        // aim to be one and a half snapshots behind plus enough to cover the jitter, moving there slowly so the render time never jumps
+        let target = (1.5 / self.snapshot_rate as f64 + self.jitter * 2.0).clamp(
+            MIN_INTERPOLATION_DELAY_MS as f64 / 1000.0,
+            MAX_INTERPOLATION_DELAY_MS as f64 / 1000.0,
+        );
+        self.delay += (target - self.delay) * 0.05;
+
+        self.snapshots.push_back((server_time, gs));
+        while self.snapshots.len() > INTERPOLATION_BUFFER_SIZE {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// The delay in seconds that remote entities are currently drawn in the past.
+    pub fn delay(&self) -> f64 {
+        self.delay
+    }
+
+    /// Forgets every snapshot, for when we move to a new game on the same server.
+    pub fn clear(&mut self) {
+        *self = SnapshotBuffer::new(self.snapshot_rate);
+    }
+
+    /// The server time that is being drawn at the given local time, None if no snapshots have arrived.
+    pub fn render_time(&self, now: SystemTime) -> Option<SystemTime> {
+        let render_time = self.render_seconds(now)?;
+        Some(UNIX_EPOCH + Duration::from_secs_f64(render_time.max(0.0)))
+    }
+
+    fn render_seconds(&self, now: SystemTime) -> Option<f64> {
+        Some(seconds(now) - self.clock_offset? - self.delay)
+    }
+
+    /// Builds the game state to draw at the given local time, None if no snapshots have arrived.
+    /// Everything that is not a position, like scores and who is connected, comes from the newer of the two snapshots used.
+    pub fn sample(&self, now: SystemTime) -> Option<GameState> {
+        let render_time = self.render_seconds(now)?;
+
+        let newer_index = self
+            .snapshots
+            .iter()
+            .position(|(time, _)| *time >= render_time);
+
+        let (older, newer) = match newer_index {
+            Some(0) => return self.snapshots.front().map(|(_, gs)| gs.clone()), // render time is before anything we have
+            Some(index) => (&self.snapshots[index - 1], &self.snapshots[index]),
+            None if self.snapshots.len() >= 2 => {
+                // snapshots are late, keep entities moving the way they were going for a little while
+                let len = self.snapshots.len();
+                (&self.snapshots[len - 2], &self.snapshots[len - 1])
+            }
+            None => return self.snapshots.back().map(|(_, gs)| gs.clone()),
+        };
+
+        let max_time = newer.0 + MAX_EXTRAPOLATION_MS as f64 / 1000.0;
+        let render_time = render_time.min(max_time);
+        let t = ((render_time - older.0) / (newer.0 - older.0)) as f32;
+        Some(interpolate(
+            &older.1,
+            &newer.1,
+            t,
+            (render_time - newer.0) as f32,
+        ))
+    }
+}
+
+/// Moves everything in newer towards where it was in older, t is 0 at older and 1 at newer, going past 1 extrapolates.
+/// since_newer is the render time minus the time of newer in seconds, used for things that carry their own velocity.
+fn interpolate(older: &GameState, newer: &GameState, t: f32, since_newer: f32) -> GameState {
+    let mut gs = newer.clone();
+
+    for (uuid, client) in gs.client_list.iter_mut() {
+        let old_client = match older.client_list.get(uuid) {
+            None => continue, // just joined, nothing to move from
+            Some(old_client) => old_client,
+        };
+
+        let pcs = &mut client.pong_client_state;
+        pcs.paddle_x = lerp(old_client.pong_client_state.paddle_x, pcs.paddle_x, t);
+
+        let tcs = &mut client.tank_client_state;
+        let old_tcs = &old_client.tank_client_state;
+        // a tank that moved further than it can in a snapshot was respawned, so dont slide it across the map
+        let respawned = (tcs.tank_x - old_tcs.tank_x).abs() + (tcs.tank_y - old_tcs.tank_y).abs()
+            > GAME_WIDTH / 4.0;
+        if !respawned {
+            tcs.tank_x = lerp(old_tcs.tank_x, tcs.tank_x, t);
+            tcs.tank_y = lerp(old_tcs.tank_y, tcs.tank_y, t);
+            tcs.rotation = lerp_degrees(old_tcs.rotation, tcs.rotation, t);
+        }
+
+        client.mouse_pos = (
+            lerp(old_client.mouse_pos.0, client.mouse_pos.0, t),
+            lerp(old_client.mouse_pos.1, client.mouse_pos.1, t),
+        );
+    }
+
+    match (&older.game_type, &mut gs.game_type) {
+        (PONG(old_pgs), PONG(pgs)) => {
+            // the ball bouncing between snapshots would make a straight line between them cut the corner, but it is close enough.
+            // a ball that moved too far was reset after a point, so it is not moved.
+            let reset = (pgs.ball_x - old_pgs.ball_x).abs() + (pgs.ball_y - old_pgs.ball_y).abs()
+                > GAME_HEIGHT / 4.0;
+            if !reset {
+                pgs.ball_x = lerp(old_pgs.ball_x, pgs.ball_x, t);
+                pgs.ball_y = lerp(old_pgs.ball_y, pgs.ball_y, t);
+            }
+        }
+        (_, TANK(tgs)) => {
+            // bullets have no ids to match them between snapshots, but they do carry their velocity
+            for bullet in tgs.bullets.iter_mut() {
+                bullet.x += bullet.x_vel * since_newer;
+                bullet.y += bullet.y_vel * since_newer;
+            }
+        }
+        _ => {} // the game mode changed, nothing to move from
+    }
+
+    gs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientstate::ClientState;
+    use crate::gamestate::GameState;
+    use crate::gametypes::GameType::PONG;
+    use crate::interpolation::{lerp_degrees, SnapshotBuffer, MAX_EXTRAPOLATION_MS};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn snapshot_at(ms: u64, paddle_x: f32, ball_x: f32) -> GameState {
+        let mut gs = GameState {
+            time: UNIX_EPOCH + Duration::from_millis(ms),
+            ..GameState::default()
+        };
+        let mut client = ClientState::default();
+        client.pong_client_state.paddle_x = paddle_x;
+        gs.client_list.insert("other".to_string(), client);
+        if let PONG(pgs) = &mut gs.game_type {
+            pgs.ball_x = ball_x;
+        }
+        gs
+    }
+
+    fn paddle_x(gs: &GameState) -> f32 {
+        gs.client_list["other"].pong_client_state.paddle_x
+    }
+
+    #[test]
+    fn interpolation_between_snapshots_test() {
+        let mut buffer = SnapshotBuffer::default();
+        // snapshots arrive instantly, so the local and server clocks line up
+        for (ms, x) in [(1000, 0.0), (1100, 100.0), (1200, 200.0)] {
+            buffer.push(
+                snapshot_at(ms, x, x),
+                UNIX_EPOCH + Duration::from_millis(ms),
+            );
+        }
+
+        let render_at =
+            |ms: f64| UNIX_EPOCH + Duration::from_secs_f64((ms / 1000.0) + buffer.delay());
+        let gs = buffer.sample(render_at(1050.0)).unwrap();
+        assert!((paddle_x(&gs) - 50.0).abs() < 0.01);
+        let gs = buffer.sample(render_at(1150.0)).unwrap();
+        match &gs.game_type {
+            PONG(pgs) => assert!((pgs.ball_x - 150.0).abs() < 0.01),
+            _ => panic!("game type was not pong"),
+        }
+
+        // late snapshots extrapolate, but only for so long
+        let gs = buffer.sample(render_at(1250.0)).unwrap();
+        assert!((paddle_x(&gs) - 250.0).abs() < 0.01);
+        let gs = buffer.sample(render_at(5000.0)).unwrap();
+        assert!((paddle_x(&gs) - (200.0 + MAX_EXTRAPOLATION_MS as f32)).abs() < 0.01);
+    }
+
+    #[test]
+    fn interpolation_ignores_old_snapshots_test() {
+        let mut buffer = SnapshotBuffer::default();
+        assert!(buffer.sample(UNIX_EPOCH).is_none());
+        buffer.push(
+            snapshot_at(2000, 10.0, 0.0),
+            UNIX_EPOCH + Duration::from_millis(2000),
+        );
+        buffer.push(
+            snapshot_at(1000, 99.0, 0.0),
+            UNIX_EPOCH + Duration::from_millis(2010),
+        );
+        let gs = buffer.sample(UNIX_EPOCH + Duration::from_secs(10)).unwrap();
+        assert_eq!(paddle_x(&gs), 10.0);
+    }
+
+    #[test]
+    fn lerp_degrees_short_way_test() {
+        assert!((lerp_degrees(350.0, 10.0, 0.5) - 360.0).abs() < 0.01);
+        assert!((lerp_degrees(10.0, 350.0, 0.5) - 0.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::clientstate::ClientState;
+use crate::interpolation::MAX_INTERPOLATION_DELAY_MS;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+/// Default for the furthest in milliseconds that hit checks will be rewound for a laggy shooter, anyone slower than this has to lead their shots.
+/// Higher values are fairer to the shooter, but the more a target can be hit after they have already moved out of the way on their screen.
+/// Servers can change it in their tank rules.
+pub static MAX_LAG_COMPENSATION_MS: u64 = 200;
+
+/// Where every tank was on a single tick, x and y by client uuid.
+pub type TankPositions = HashMap<String, (f32, f32)>;
+
+/// TankHistory is where every tank was on each of the last few ticks, so hit checks can be done against where a shooter saw them.
+#[derive(Debug, Default)]
+pub struct TankHistory {
+    /// Tank positions for each tick, oldest first.
+    ticks: VecDeque<(SystemTime, TankPositions)>,
+}
+
+impl TankHistory {
+    /// Records where every tank is at the given time, and forgets anything older than max_lag_compensation_ms.
+    pub fn record(
+        &mut self,
+        time: SystemTime,
+        client_list: &HashMap<String, ClientState>,
+        max_lag_compensation_ms: u64,
+    ) {
+        self.ticks.push_back((
+            time,
+            client_list
+                .iter()
+                .map(|(uuid, client)| {
+                    (
+                        uuid.clone(),
+                        (
+                            client.tank_client_state.tank_x,
+                            client.tank_client_state.tank_y,
+                        ),
+                    )
+                })
+                .collect(),
+        ));
+
+        let oldest = time - Duration::from_millis(max_lag_compensation_ms);
+        while self.ticks.len() > 1 && self.ticks.front().unwrap().0 < oldest {
+            self.ticks.pop_front();
+        }
+    }
+
+    /// Returns where every tank was at the first tick at or after the given time, or the oldest tick we have if the time is older than that.
+    pub fn positions_at(&self, time: SystemTime) -> Option<&TankPositions> {
+        self.ticks
+            .iter()
+            .find(|(tick_time, _)| *tick_time >= time)
+            .or(self.ticks.front())
+            .map(|(_, positions)| positions)
+    }
+}
+
+/// Works out how far in seconds to rewind hit checks for a client from the time they were viewing when they sent their input, and their round trip time.
+/// A client can not really be further behind than its round trip plus the longest interpolation delay, so a view time older than that is not trusted.
+/// Clients that do not send a view time are not interpolating, so they see everyone a round trip late. It is never more than max_lag_compensation_ms.
+pub fn lag_compensation_for(
+    view_time: Option<SystemTime>,
+    rtt_ms: u32,
+    max_lag_compensation_ms: u64,
+) -> f32 {
+    let rtt = Duration::from_millis(rtt_ms as u64);
+    let behind = match view_time {
+        None => rtt,
+        Some(view_time) => SystemTime::now()
+            .duration_since(view_time)
+            .unwrap_or_default()
+            .min(rtt + Duration::from_millis(MAX_INTERPOLATION_DELAY_MS)),
+    };
+    behind
+        .min(Duration::from_millis(max_lag_compensation_ms))
+        .as_secs_f32()
+}
@@ -1,14 +1,32 @@
 use crate::gamestate::GameState;
-use std::ops::Deref;
 
 pub mod clientinfo;
 pub mod clientstate;
+pub mod codec;
+pub mod compression;
+pub mod conditioner;
+pub mod connection;
+pub mod delta;
+pub mod discovery;
+pub mod framing;
 pub mod gamestate;
 pub mod gametypes;
+pub mod handshake;
+pub mod interpolation;
 pub mod keystate;
+pub mod lag_compensation;
+pub mod message;
+pub mod ping;
 pub mod pong;
+pub mod prediction;
+pub mod room;
+pub mod rules;
 pub mod tank;
 pub mod team;
+pub mod transport;
+pub mod udp;
+pub mod validation;
+pub mod websocket;
 
 pub static GAME_WIDTH: f32 = 600.0;
 pub static GAME_HEIGHT: f32 = 600.0;
@@ -30,7 +48,7 @@ pub trait Magnitude {
 
 impl Magnitude for (f32, f32) {
     fn mag(&self) -> f32 {
-        let v: (f32, f32) = *self.deref();
+        let v: (f32, f32) = *self;
 
         (v.0.powi(2) + v.1.powi(2)).sqrt()
     }
@@ -46,8 +64,8 @@ pub fn get_vote_count_for_number(number: u8, game_state: &GameState) -> u32 {
     // TODO: could eventually make this run for every number, but for now I dont think I need this.
     let count = game_state
         .client_list
-        .iter()
-        .map(|(_, client)| client.vote_number) // map each client to their vote number, that all we care about
+        .values()
+        .map(|client| client.vote_number) // map each client to their vote number, that all we care about
         .fold(0, |count, vote_number| {
             // here we reduce the iter to the count respective to that number.
             if vote_number == number {
@@ -183,6 +201,10 @@ mod tests {
                 pong_client_state: Default::default(),
                 tank_client_state: Default::default(),
                 vote_number: 1,
+                last_input_sequence: 0,
+                rtt_ms: 0,
+                lag_compensation: 0.0,
+                team_changed_at: None,
             },
         );
         gs.client_list.insert(
@@ -195,6 +217,10 @@ mod tests {
                 pong_client_state: Default::default(),
                 tank_client_state: Default::default(),
                 vote_number: 2,
+                last_input_sequence: 0,
+                rtt_ms: 0,
+                lag_compensation: 0.0,
+                team_changed_at: None,
             },
         );
         gs.client_list.insert(
@@ -207,6 +233,10 @@ mod tests {
                 pong_client_state: Default::default(),
                 tank_client_state: Default::default(),
                 vote_number: 1,
+                last_input_sequence: 0,
+                rtt_ms: 0,
+                lag_compensation: 0.0,
+                team_changed_at: None,
             },
         );
         let vote_count_one = get_vote_count_for_number(1, &gs);
@@ -0,0 +1,180 @@
+use crate::clientinfo::ClientInfo;
+use crate::connection::DisconnectReason;
+use crate::delta::Snapshot;
+use crate::gametypes::GameType;
+use crate::ping::Pong;
+use crate::room::{RoomId, RoomInfo, RoomRejectReason};
+use crate::team::{Team, TeamRejectReason};
+use crate::transport::Channel;
+use serde::{Deserialize, Serialize};
+
+/// Longest chat message in characters that the server will relay to other clients.
+pub static MAX_CHAT_LENGTH: usize = 128;
+
+/// ServerMessage is every packet the server can send to a client after the handshake.
+/// Each turn of the connection the server sends any number of non-snapshot messages, always followed by exactly one Snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerMessage {
+    /// The current state of the game, usually as the changes since a snapshot the client acknowledged, this ends the servers turn.
+    Snapshot(Snapshot),
+    /// Something happened in the game that the client should be told about once, rather than every frame.
+    Event(GameEvent),
+    /// A non fatal problem with something the client sent, the connection stays open.
+    Error(String),
+    /// The connection is over, the reason is shown to the player and the connection is closed after this message.
+    Disconnect(DisconnectReason),
+    /// Sent when the server has had nothing else to send for a while, so the client knows it is still there.
+    Heartbeat,
+    /// The servers answer to a team request it refused, accepted requests show up as a TeamChanged event.
+    TeamRejected(TeamRejectReason),
+    /// Asks the client to answer with a pong, so the server can measure its round trip time.
+    Ping(u32),
+    /// The answer to a ping from the client.
+    Pong(Pong),
+    /// The rooms open on the server, the answer to ListRooms.
+    RoomList(Vec<RoomInfo>),
+    /// The client is now in the given room, sent when they first connect and whenever they change room.
+    RoomJoined(RoomInfo),
+    /// The servers answer to a room request it refused.
+    RoomRejected(RoomRejectReason),
+    /// The server is shutting down, everyone is disconnected once the given number of seconds have passed.
+    ShuttingDown { seconds: u32 },
+}
+
+/// GameEvent is a one off event that is delivered to clients alongside snapshots.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum GameEvent {
+    /// A chat line from the player with the given name.
+    Chat { name: String, message: String },
+    /// A game mode vote finished, and the given game type is now being played.
+    VoteConcluded(GameType),
+    /// The player with the given name was moved to a team, either because they asked or to balance the teams.
+    TeamChanged { name: String, team: Team },
+}
+
+/// ClientMessage is every packet a client can send to the server after the handshake.
+/// Each turn of the connection the client sends any number of non-input messages, always followed by exactly one Input.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientMessage {
+    /// The clients input for this frame, this ends the clients turn.
+    Input(ClientInfo),
+    /// A chat line to be relayed to every client.
+    Chat(String),
+    /// The clients vote for the next game mode, 0 meaning no vote.
+    Vote(u8),
+    /// Asks the server to move the client to a team, the server decides if they can.
+    TeamRequest(Team),
+    /// The sequence of the newest snapshot the client has, the server sends later snapshots as changes from it.
+    SnapshotAck(u32),
+    /// Asks the server to answer with a pong, so the client can measure its round trip time and how far off its clock is.
+    Ping(u32),
+    /// The answer to a ping from the server.
+    Pong(Pong),
+    /// The client is leaving, the server can remove them immediately instead of waiting for the socket to error.
+    Disconnect(DisconnectReason),
+    /// Sent when the client has had nothing else to send for a while, so the server knows it is still there.
+    Heartbeat,
+    /// Asks the server for the rooms that are open.
+    ListRooms,
+    /// Opens a new room with the given name, and moves the client into it.
+    CreateRoom(String),
+    /// Moves the client into the room with the given id.
+    JoinRoom(RoomId),
+    /// Moves the client back to the lobby.
+    LeaveRoom,
+}
+
+impl ServerMessage {
+    /// Returns true if this message ends the servers turn.
+    pub fn ends_turn(&self) -> bool {
+        matches!(
+            self,
+            ServerMessage::Snapshot(_) | ServerMessage::Disconnect(_)
+        )
+    }
+
+    /// Snapshots are sent every frame so losing one does not matter, everything else must arrive.
+    /// Pings are also unreliable, a resent ping would measure the resend rather than the network, and a heartbeat is only useful if it arrives on time.
+    pub fn channel(&self) -> Channel {
+        match self {
+            ServerMessage::Snapshot(_)
+            | ServerMessage::Ping(_)
+            | ServerMessage::Pong(_)
+            | ServerMessage::Heartbeat => Channel::Unreliable,
+            _ => Channel::Reliable,
+        }
+    }
+}
+
+impl ClientMessage {
+    /// Returns true if this message ends the clients turn.
+    pub fn ends_turn(&self) -> bool {
+        matches!(self, ClientMessage::Input(_) | ClientMessage::Disconnect(_))
+    }
+
+    /// Input and acks are sent every frame so losing one does not matter, everything else must arrive.
+    /// Pings are also unreliable, a resent ping would measure the resend rather than the network, and a heartbeat is only useful if it arrives on time.
+    pub fn channel(&self) -> Channel {
+        match self {
+            ClientMessage::Input(_)
+            | ClientMessage::SnapshotAck(_)
+            | ClientMessage::Ping(_)
+            | ClientMessage::Pong(_)
+            | ClientMessage::Heartbeat => Channel::Unreliable,
+            _ => Channel::Reliable,
+        }
+    }
+}
+
+impl GameEvent {
+    /// A line of text describing the event, used for the clients chat log.
+    pub fn log_line(&self) -> String {
+        match self {
+            GameEvent::Chat { name, message } => format!("{}: {}", name, message),
+            GameEvent::VoteConcluded(game_type) => {
+                format!("vote finished, now playing {}", game_type)
+            }
+            GameEvent::TeamChanged { name, team } => format!("{} joined the {}", name, team),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientinfo::ClientInfo;
+    use crate::codec::Codec;
+    use crate::delta::SnapshotEncoder;
+    use crate::gamestate::GameState;
+    use crate::message::{ClientMessage, GameEvent, ServerMessage};
+
+    #[test]
+    fn message_round_trip_test() {
+        for codec in [Codec::Json, Codec::Bincode] {
+            let messages = vec![
+                ServerMessage::Event(GameEvent::Chat {
+                    name: "test".to_string(),
+                    message: "hello".to_string(),
+                }),
+                ServerMessage::Snapshot(SnapshotEncoder::default().encode(&GameState::default())),
+            ];
+            for message in messages {
+                let decoded: ServerMessage =
+                    codec.decode(&codec.encode(&message).unwrap()).unwrap();
+                assert_eq!(decoded.ends_turn(), message.ends_turn());
+            }
+
+            let decoded: ClientMessage = codec
+                .decode(
+                    &codec
+                        .encode(&ClientMessage::Input(ClientInfo::default()))
+                        .unwrap(),
+                )
+                .unwrap();
+            assert!(decoded.ends_turn());
+            let decoded: ClientMessage = codec
+                .decode(&codec.encode(&ClientMessage::Vote(2)).unwrap())
+                .unwrap();
+            assert!(matches!(decoded, ClientMessage::Vote(2)));
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often in milliseconds each side of a connection pings the other.
+pub static PING_INTERVAL_MS: u64 = 1000;
+
+/// Most pings that can be waiting on a pong at once, pings that were lost are forgotten once this many newer ones have been sent.
+pub static MAX_OUTSTANDING_PINGS: usize = 8;
+
+/// Pong is the answer to a ping, it carries when the ping arrived and when the pong was sent by the clock of whoever answered.
+/// The time between the two is taken off the round trip, as it was spent waiting for our turn to send, not on the network.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Pong {
+    pub id: u32,
+    pub received_at: SystemTime,
+    pub sent_at: SystemTime,
+}
+
+impl Pong {
+    /// Answers the ping with the given id, this should be called as soon as the ping arrives.
+    pub fn reply_to(id: u32) -> Pong {
+        let now = SystemTime::now();
+        Pong {
+            id,
+            received_at: now,
+            sent_at: now,
+        }
+    }
+
+    /// Returns the pong with its send time set to now, this should be called right before it is sent.
+    pub fn stamped(&self) -> Pong {
+        Pong {
+            sent_at: SystemTime::now(),
+            ..self.clone()
+        }
+    }
+
+    /// How long the pong waited between the ping arriving and being sent back.
+    fn held(&self) -> Duration {
+        self.sent_at
+            .duration_since(self.received_at)
+            .unwrap_or_default()
+    }
+}
+
+/// PingTracker sends a ping every so often and times the pongs that come back, giving the round trip time of the connection,
+/// how much it jitters, and how far apart our clock and the other sides clock are.
+/// Only our own clock is used to time the round trip, so it is correct no matter how wrong the other sides clock is.
+#[derive(Debug, Clone, Default)]
+pub struct PingTracker {
+    next_id: u32,
+    last_ping: Option<Instant>,
+    /// Pings waiting on a pong, with when they were sent by both our clocks.
+    outstanding: VecDeque<(u32, Instant, SystemTime)>,
+    /// Smoothed round trip time in seconds.
+    rtt: Option<f64>,
+    /// Smoothed difference between each round trip and the average round trip, in seconds.
+    jitter: f64,
+    /// The other sides clock minus ours, in seconds.
+    clock_offset: Option<f64>,
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+impl PingTracker {
+    /// Returns the id of a ping to send if it has been long enough since the last one, None if it is not time yet.
+    pub fn ping(&mut self, now: Instant) -> Option<u32> {
+        if self
+            .last_ping
+            .is_some_and(|last| now.duration_since(last) < Duration::from_millis(PING_INTERVAL_MS))
+        {
+            return None;
+        }
+        self.last_ping = Some(now);
+        self.next_id = self.next_id.wrapping_add(1);
+        self.outstanding
+            .push_back((self.next_id, now, SystemTime::now()));
+        while self.outstanding.len() > MAX_OUTSTANDING_PINGS {
+            self.outstanding.pop_front();
+        }
+        Some(self.next_id)
+    }
+
+    /// Times a pong that arrived at the given time, returning the round trip it measured.
+    /// None is returned for pongs to pings we never sent or have already forgotten.
+    pub fn pong(&mut self, pong: &Pong, now: Instant) -> Option<Duration> {
+        let index = self
+            .outstanding
+            .iter()
+            .position(|(id, ..)| *id == pong.id)?;
+        let (_, sent, sent_wall) = self.outstanding.remove(index).unwrap();
+        let elapsed = now.duration_since(sent);
+        let sample = elapsed.saturating_sub(pong.held()).as_secs_f64();
+
+        let rtt = match self.rtt {
+            None => sample,
+            Some(rtt) => {
+                self.jitter += ((sample - rtt).abs() - self.jitter) / 16.0;
+                rtt + (sample - rtt) / 8.0
+            }
+        };
+
+        // the pong arrived halfway through the round trip as far as we can tell, a slow round trip could have been slow either way so it counts for less
+        let our_middle = seconds(sent_wall) + elapsed.as_secs_f64() / 2.0;
+        let their_middle = seconds(pong.received_at) + pong.held().as_secs_f64() / 2.0;
+        let offset_sample = their_middle - our_middle;
+        self.clock_offset = Some(match self.clock_offset {
+            None => offset_sample,
+            Some(offset) if sample <= rtt => offset + (offset_sample - offset) / 4.0,
+            Some(offset) => offset + (offset_sample - offset) / 16.0,
+        });
+
+        self.rtt = Some(rtt);
+        Some(Duration::from_secs_f64(sample))
+    }
+
+    /// The smoothed round trip time, None until the first pong arrives.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt.map(Duration::from_secs_f64)
+    }
+
+    /// How much the round trip time varies from one ping to the next.
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter)
+    }
+
+    /// The other sides clock minus ours in seconds, None until the first pong arrives.
+    pub fn clock_offset(&self) -> Option<f64> {
+        self.clock_offset
+    }
+
+    /// Turns a time by our clock into the same moment by the other sides clock, as well as we know it.
+    pub fn remote_time(&self, local: SystemTime) -> SystemTime {
+        let remote = seconds(local) + self.clock_offset.unwrap_or(0.0);
+        UNIX_EPOCH + Duration::from_secs_f64(remote.max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ping::{PingTracker, Pong, PING_INTERVAL_MS};
+    use std::time::{Duration, Instant, SystemTime};
+
+    #[test]
+    fn ping_measures_round_trip_and_offset_test() {
+        let mut tracker = PingTracker::default();
+        let start = Instant::now();
+        let id = tracker.ping(start).unwrap();
+        assert!(tracker.ping(start).is_none()); // too soon for another
+        assert!(tracker.rtt().is_none());
+
+        // the other side is an hour ahead, got the ping 20ms after we sent it, and held it 30ms before answering
+        let received_at = SystemTime::now() + Duration::from_secs(3600) + Duration::from_millis(20);
+        let pong = Pong {
+            id,
+            received_at,
+            sent_at: received_at + Duration::from_millis(30),
+        };
+        let rtt = tracker
+            .pong(&pong, start + Duration::from_millis(70))
+            .unwrap();
+        assert_eq!(rtt, Duration::from_millis(40));
+        assert!((tracker.clock_offset().unwrap() - 3600.0).abs() < 0.05);
+
+        // a pong for a ping that was already answered is ignored
+        assert!(tracker
+            .pong(&pong, start + Duration::from_millis(80))
+            .is_none());
+
+        let later = start + Duration::from_millis(PING_INTERVAL_MS);
+        let id = tracker.ping(later).unwrap();
+        let pong = Pong::reply_to(id);
+        tracker.pong(&pong, later + Duration::from_millis(60));
+        assert!(tracker.rtt().unwrap() > Duration::from_millis(40));
+        assert!(tracker.jitter() > Duration::ZERO);
+    }
+}
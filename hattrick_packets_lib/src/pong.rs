@@ -1,4 +1,6 @@
+use crate::clientinfo::ClientInfo;
 use crate::clientstate::ClientState;
+use crate::rules::PongRules;
 use crate::team::Team;
 use crate::team::Team::{BlueTeam, RedTeam};
 use crate::{GAME_HEIGHT, GAME_WIDTH};
@@ -7,6 +9,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+// the scoring, paddle speed, ball velocity and power hit statics are only the defaults, servers can change them in their game rules. See the rules module.
 pub static PONG_PADDLE_WIDTH: f32 = 100.0;
 pub static PONG_PADDLE_HEIGHT: f32 = 10.0;
 pub static PONG_BALL_RADIUS: f32 = 15.0;
@@ -16,14 +19,14 @@ pub static BLUE_TEAM_PADDLE_Y: f32 = 10.0;
 pub static RED_TEAM_PADDLE_Y: f32 = 550.0;
 pub static PONG_POINTS_TO_WIN: i32 = 10;
 
-pub static PADDLE_MOVE_SPEED: f32 = 0.5; // 0.5 seems to feel pretty good a the moment
+pub static PADDLE_MOVE_SPEED: f32 = 300.0; // units per second, 300 seems to feel pretty good a the moment
 
 pub static POWER_HIT_MODIFIER: f32 = 1.5; // velocity multiplier for added velocity on each paddle bounce for ball
 pub static POWER_HIT_LOCK_TIME: f32 = 1.0; // how long to lock a paddle in place when a power hit is initiated
 pub static POWER_HIT_COOLDOWN: f32 = 2.0;
 
 /// PongGameState is an example game type struct that holds all the data for the game mode, it should contain anything related to the game-type of its parent, in this case Pong.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PongGameState {
     pub ball_x: f32,
     pub ball_y: f32,
@@ -61,7 +64,7 @@ pub fn get_pong_paddle_width(client_list: &HashMap<String, ClientState>, team: &
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PongClientState {
     pub paddle_x: f32,
     pub paddle_y: f32,
@@ -152,7 +155,11 @@ impl PongGameState {
 
     /// Steps the pong game state using the client list, checkins the ball collision on each players paddle. Function does not move ball, nor paddles.
     /// Instead the function changes the velocities of the ball in accordance.
-    pub fn step_game_state(&mut self, client_list: &HashMap<String, ClientState>) {
+    pub fn step_game_state(
+        &mut self,
+        client_list: &HashMap<String, ClientState>,
+        rules: &PongRules,
+    ) {
         let ball_radius = PONG_BALL_RADIUS;
 
         for client in client_list {
@@ -187,20 +194,20 @@ impl PongGameState {
 
                 // variables used to randomly add some amount of velocity when a bounce happens on a paddle.
                 let rand_xvel_change: f32 =
-                    rand::thread_rng().gen_range(PONG_BALL_VEL_ADD_MIN..PONG_BALL_VEL_ADD_MAX); // generate a random new x velocity change for when a bounce needs to occur
+                    rand::thread_rng().gen_range(rules.ball_vel_add_min..rules.ball_vel_add_max); // generate a random new x velocity change for when a bounce needs to occur
                 let rand_yvel_change: f32 =
-                    rand::thread_rng().gen_range(PONG_BALL_VEL_ADD_MIN..PONG_BALL_VEL_ADD_MAX); // generate a random new y velocity change for when a bounce needs to occur
+                    rand::thread_rng().gen_range(rules.ball_vel_add_min..rules.ball_vel_add_max); // generate a random new y velocity change for when a bounce needs to occur
 
                 // this statement adds the correct direction of velocity, it adds new velocity in the direction of travel already.
                 if self.ball_xvel > 0.0 {
                     // if ball hits paddle, add a random amount of x velocity to the ball, in the direction it is currently traveling
                     if cs.key_state.space_bar {
-                        self.ball_xvel += rand_xvel_change * POWER_HIT_MODIFIER;
+                        self.ball_xvel += rand_xvel_change * rules.power_hit_modifier;
                     } else {
                         self.ball_xvel += rand_xvel_change;
                     }
                 } else if cs.key_state.space_bar {
-                    self.ball_xvel -= rand_xvel_change * POWER_HIT_MODIFIER;
+                    self.ball_xvel -= rand_xvel_change * rules.power_hit_modifier;
                 } else {
                     self.ball_xvel -= rand_xvel_change;
                 }
@@ -230,6 +237,76 @@ impl PongGameState {
     }
 }
 
+impl PongClientState {
+    /// Moves the paddle towards the clients mouse, and starts a power hit if they pressed space, returning the new paddle state. This is one inputs worth of movement.
+    /// The server runs this for every input it gets, and the client runs it ahead of the server to predict where its own paddle is.
+    /// team is the team the server has the client on, and rules and snapshot_rate are the ones the server sent in its welcome.
+    /// now is the time by the servers clock, since time_of_power_hit is always a server time. The client passes its best guess of the servers clock, not its own.
+    pub fn apply_input(
+        &self,
+        info: &ClientInfo,
+        team: &Team,
+        paddle_width: f32,
+        rules: &PongRules,
+        snapshot_rate: u32,
+        now: SystemTime,
+    ) -> PongClientState {
+        let paddle_y = {
+            // set the clients y coordinate based on their team, top for blue, bottom for red
+            match team {
+                BlueTeam => BLUE_TEAM_PADDLE_Y,
+                RedTeam => RED_TEAM_PADDLE_Y,
+            }
+        };
+
+        // subtract half of the paddle width from the mouse position so we can center it on the players mouse,
+        // since drawing for this game lib draws from top left
+        let paddle_half_width = paddle_width / 2.0;
+        let middle_of_paddle = info.mouse_pos.0 - paddle_half_width;
+
+        // clients send one input per snapshot, so each input moves the paddle a snapshots worth of time, without going past the mouse.
+        let paddle_step = (rules.paddle_move_speed / snapshot_rate as f32)
+            .min((middle_of_paddle - self.paddle_x).abs());
+
+        let time_since_last_power_hit = now
+            .duration_since(self.time_of_power_hit)
+            .unwrap_or_default()
+            .as_secs_f32();
+
+        // only move paddle if the difference in its x position and the mouse x position is larger than a specific amount (probably needs tuning).
+        // also only move the paddle if the time we last power hit is greater or equal to the lock time, so that a power hit locks the paddle in place
+        let paddle_x = if (middle_of_paddle - self.paddle_x).abs() > paddle_half_width / 10.0
+            && time_since_last_power_hit >= rules.power_hit_lock_time
+        {
+            if middle_of_paddle < self.paddle_x {
+                // mouse is to the left of the paddle at the moment
+                // TODO: maybe slow paddle move speed by 20% when the power move time is < the cool down? unsure if good idea or not.
+                self.paddle_x - paddle_step
+            } else {
+                // mouse is to the right of the paddle at the moment
+                self.paddle_x + paddle_step
+            }
+        } else {
+            // if we dont move the paddle at all, just give it its previous value.
+            self.paddle_x
+        };
+
+        // power hit time is either updated to now or the previous depending on if the client is pressing space
+        let time_of_power_hit =
+            if info.key_state.space_bar && time_since_last_power_hit >= rules.power_hit_cooldown {
+                now
+            } else {
+                self.time_of_power_hit
+            };
+
+        PongClientState {
+            paddle_x: paddle_x.clamp(0.0, GAME_WIDTH - PONG_PADDLE_WIDTH),
+            paddle_y,
+            time_of_power_hit,
+        }
+    }
+}
+
 impl Default for PongClientState {
     fn default() -> Self {
         PongClientState {
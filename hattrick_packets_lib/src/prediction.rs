@@ -0,0 +1,182 @@
+use crate::clientinfo::ClientInfo;
+use crate::clientstate::ClientState;
+use crate::gamestate::GameState;
+use crate::gametypes::GameType::{PONG, TANK};
+use crate::pong::get_pong_paddle_width;
+use crate::rules::GameRules;
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// Most inputs that are kept waiting for the server to apply them, about two seconds worth. If the server falls further behind than this the oldest are forgotten.
+pub static MAX_PENDING_INPUTS: usize = 64;
+
+/// Predictor is kept by the client so its own paddle or tank moves as soon as it presses a key, instead of a round trip later.
+/// Every input sent is numbered and kept until the server says it has applied it, when a snapshot arrives the inputs the server has not seen yet are replayed on top of it.
+/// If the prediction was wrong, the snapshot corrects it.
+#[derive(Debug, Clone, Default)]
+pub struct Predictor {
+    last_sequence: u32,
+    pending: VecDeque<ClientInfo>,
+}
+
+impl Predictor {
+    /// Gives the input the next sequence number and keeps it until the server has applied it, the returned input is what should be sent.
+    pub fn track(&mut self, mut info: ClientInfo) -> ClientInfo {
+        self.last_sequence += 1;
+        info.input_sequence = self.last_sequence;
+        self.pending.push_back(info.clone());
+        while self.pending.len() > MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+        info
+    }
+
+    /// Number of inputs sent that the server has not applied yet.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Replaces our own client in the game state with where we predict it to be,
+    /// by replaying every input the server has not applied yet on top of the servers version of it. rules and snapshot_rate are the ones the server sent in its welcome,
+    /// and server_now is the time right now by the servers clock as well as we know it, so power hits are timed the same way the server times them.
+    pub fn reconcile(
+        &mut self,
+        gs: &mut GameState,
+        client_id: &str,
+        rules: &GameRules,
+        snapshot_rate: u32,
+        server_now: SystemTime,
+    ) {
+        let mut predicted = match gs.client_list.get(client_id) {
+            None => return,
+            Some(client) => client.clone(),
+        };
+
+        self.pending
+            .retain(|info| info.input_sequence > predicted.last_input_sequence); // the server already has these
+        for info in &self.pending {
+            predict_input(&mut predicted, info, gs, rules, snapshot_rate, server_now);
+        }
+
+        gs.client_list.insert(client_id.to_string(), predicted);
+    }
+}
+
+/// Runs the same movement code the server does for a single input.
+fn predict_input(
+    client: &mut ClientState,
+    info: &ClientInfo,
+    gs: &GameState,
+    rules: &GameRules,
+    snapshot_rate: u32,
+    server_now: SystemTime,
+) {
+    match &gs.game_type {
+        PONG(_) => {
+            let paddle_width = get_pong_paddle_width(&gs.client_list, &client.team_id);
+            client.pong_client_state = client.pong_client_state.apply_input(
+                info,
+                &client.team_id,
+                paddle_width,
+                &rules.pong,
+                snapshot_rate,
+                server_now,
+            );
+        }
+        TANK(_) => {
+            // the server moves tanks every tick with the newest keys it has, each input is held for a snapshots worth of time.
+            client.tank_client_state.step_movement(
+                &info.key_state,
+                1.0 / snapshot_rate as f32,
+                &rules.tank,
+            );
+        }
+    }
+    client.mouse_pos = info.mouse_pos;
+    client.key_state = info.key_state.clone();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientinfo::ClientInfo;
+    use crate::clientstate::ClientState;
+    use crate::gamestate::{GameState, SNAPSHOT_RATE};
+    use crate::pong::PONG_PADDLE_WIDTH;
+    use crate::prediction::Predictor;
+    use crate::rules::GameRules;
+    use crate::team::Team::RedTeam;
+    use std::time::{Duration, SystemTime};
+
+    fn input_at(mouse_x: f32) -> ClientInfo {
+        ClientInfo {
+            mouse_pos: (mouse_x, 0.0),
+            ..ClientInfo::default()
+        }
+    }
+
+    #[test]
+    fn prediction_replays_unapplied_inputs_test() {
+        let mut gs = GameState::default();
+        gs.client_list.insert(
+            "me".to_string(),
+            ClientState {
+                team_id: RedTeam,
+                ..ClientState::default()
+            },
+        );
+
+        let rules = GameRules::default();
+        let now = SystemTime::now();
+        let mut predictor = Predictor::default();
+        let first = predictor.track(input_at(500.0));
+        predictor.track(input_at(500.0));
+        assert_eq!(first.input_sequence, 1);
+
+        // the server has not applied anything yet, so both inputs move the paddle
+        let mut predicted = gs.clone();
+        predictor.reconcile(&mut predicted, "me", &rules, SNAPSHOT_RATE, now);
+        let two_inputs = predicted.client_list["me"].pong_client_state.paddle_x;
+        assert!(two_inputs > 0.0);
+        assert_eq!(predictor.pending_count(), 2);
+
+        // the server applied the first input, only the second is replayed on top of the servers paddle
+        let server_client = gs.client_list.get_mut("me").unwrap();
+        server_client.pong_client_state = server_client.pong_client_state.apply_input(
+            &first,
+            &RedTeam,
+            PONG_PADDLE_WIDTH,
+            &rules.pong,
+            SNAPSHOT_RATE,
+            now,
+        );
+        server_client.last_input_sequence = 1;
+        predictor.reconcile(&mut gs, "me", &rules, SNAPSHOT_RATE, now);
+        assert_eq!(predictor.pending_count(), 1);
+        assert_eq!(gs.client_list["me"].pong_client_state.paddle_x, two_inputs);
+    }
+
+    #[test]
+    fn prediction_power_hit_uses_server_clock_test() {
+        // the server power hit a moment ago by its clock, which is an hour behind ours
+        let rules = GameRules::default();
+        let server_now = SystemTime::now() - Duration::from_secs(3600);
+        let mut gs = GameState::default();
+        let mut client = ClientState {
+            team_id: RedTeam,
+            ..ClientState::default()
+        };
+        client.pong_client_state.time_of_power_hit = server_now - Duration::from_millis(10);
+        gs.client_list.insert("me".to_string(), client);
+
+        // replayed inputs are timed by the servers clock, so the paddle is still locked in place
+        let mut predictor = Predictor::default();
+        predictor.track(input_at(500.0));
+        predictor.reconcile(&mut gs, "me", &rules, SNAPSHOT_RATE, server_now);
+        assert_eq!(gs.client_list["me"].pong_client_state.paddle_x, 0.0);
+
+        // once the lock time has passed by the servers clock it moves again
+        let later = server_now + Duration::from_secs_f32(rules.pong.power_hit_lock_time);
+        predictor.reconcile(&mut gs, "me", &rules, SNAPSHOT_RATE, later);
+        assert!(gs.client_list["me"].pong_client_state.paddle_x > 0.0);
+    }
+}
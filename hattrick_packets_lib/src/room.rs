@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Rooms are numbered by the server in the order they are opened.
+pub type RoomId = u32;
+
+/// The room every player is put in when they connect, and goes back to when they leave a room. It is never closed.
+pub static LOBBY_ROOM_ID: RoomId = 0;
+
+/// The name of the lobby room.
+pub static LOBBY_ROOM_NAME: &str = "Lobby";
+
+/// Longest room name in characters.
+pub static MAX_ROOM_NAME_LENGTH: usize = 32;
+
+/// Most rooms a server has open at once, including the lobby. Each room runs its own game, so this keeps players from opening more than the server can run.
+pub static MAX_ROOMS: usize = 16;
+
+/// RoomInfo is what the room list shows about a room.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomInfo {
+    pub id: RoomId,
+    pub name: String,
+    /// The game mode being played in the room right now, like pong.
+    pub game_mode: String,
+    /// How many players are in the room, not counting the ai.
+    pub player_count: u32,
+}
+
+/// RoomRejectReason is why the server refused to create or join a room, it is displayed to the player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum RoomRejectReason {
+    /// There is no room with the given id, it may have been closed since the room list was sent.
+    NotFound(RoomId),
+    /// Room names must be between 1 and MAX_ROOM_NAME_LENGTH characters, and can not have control characters.
+    InvalidName,
+    /// The server already has as many rooms open as it allows.
+    TooManyRooms { max: usize },
+    /// The player is already in the room they asked to join.
+    AlreadyInRoom,
+}
+
+impl Display for RoomRejectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomRejectReason::NotFound(id) => write!(f, "room {} does not exist", id),
+            RoomRejectReason::InvalidName => write!(
+                f,
+                "room names must be between 1 and {} characters",
+                MAX_ROOM_NAME_LENGTH
+            ),
+            RoomRejectReason::TooManyRooms { max } => {
+                write!(f, "the server already has {} rooms open", max)
+            }
+            RoomRejectReason::AlreadyInRoom => write!(f, "you are already in that room"),
+        }
+    }
+}
+
+/// Checks a name for a new room, returning it with the whitespace trimmed off the ends.
+pub fn check_room_name(name: &str) -> Result<String, RoomRejectReason> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.chars().count() > MAX_ROOM_NAME_LENGTH
+        || name.chars().any(|c| c.is_control())
+    {
+        return Err(RoomRejectReason::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::room::{check_room_name, RoomRejectReason, MAX_ROOM_NAME_LENGTH};
+
+    #[test]
+    fn check_room_name_test() {
+        assert_eq!(check_room_name("  my room "), Ok("my room".to_string()));
+        assert_eq!(check_room_name("   "), Err(RoomRejectReason::InvalidName));
+        assert_eq!(
+            check_room_name(&"a".repeat(MAX_ROOM_NAME_LENGTH + 1)),
+            Err(RoomRejectReason::InvalidName)
+        );
+        assert_eq!(
+            check_room_name("bad\nname"),
+            Err(RoomRejectReason::InvalidName)
+        );
+    }
+}
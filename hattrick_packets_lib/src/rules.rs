@@ -0,0 +1,203 @@
+use crate::gamestate::VOTE_TIME;
+use crate::lag_compensation::MAX_LAG_COMPENSATION_MS;
+use crate::pong::{
+    PADDLE_MOVE_SPEED, PONG_BALL_VEL_ADD_MAX, PONG_BALL_VEL_ADD_MIN, PONG_POINTS_TO_WIN,
+    POWER_HIT_COOLDOWN, POWER_HIT_LOCK_TIME, POWER_HIT_MODIFIER,
+};
+use crate::tank::{
+    TANK_ACCEL, TANK_BULLET_BOUNCE_COUNT_MAX, TANK_BULLET_VELOCITY, TANK_FRICTION, TANK_MAX_SPEED,
+    TANK_SHOT_COOL_DOWN, TANK_TURN_SPEED, TANK_WIN_SCORE,
+};
+use serde::{Deserialize, Serialize};
+
+/// GameRules is everything about how the game modes play that the server can be configured with, the defaults are the statics in each game modes module.
+/// The server sends its rules to each client in the welcome, as the client runs the same movement code to predict its own paddle or tank.
+/// Anything left out when reading the rules from a config file keeps its default.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameRules {
+    /// How long in seconds the vote for the next game mode runs once a round has been won.
+    pub vote_time: f32,
+    pub pong: PongRules,
+    pub tank: TankRules,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PongRules {
+    /// Points a team needs to win a round of pong.
+    pub points_to_win: i32,
+    /// How fast a paddle follows the mouse, in units per second.
+    pub paddle_move_speed: f32,
+    /// Least velocity added to the ball each time it bounces off a paddle.
+    pub ball_vel_add_min: f32,
+    /// Most velocity added to the ball each time it bounces off a paddle.
+    pub ball_vel_add_max: f32,
+    /// Multiplier on the velocity added to the ball when it is hit with a power hit.
+    pub power_hit_modifier: f32,
+    /// How long in seconds a paddle is locked in place after a power hit.
+    pub power_hit_lock_time: f32,
+    /// How long in seconds a paddle must wait between power hits.
+    pub power_hit_cooldown: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TankRules {
+    /// Hits a team needs to win a round of tanks.
+    pub win_score: i32,
+    pub max_speed: f32,
+    pub accel: f32,
+    /// How fast a tank turns, in degrees per second.
+    pub turn_speed: f32,
+    /// How much of a tanks velocity is kept each millisecond, from 0 to 1.
+    pub friction: f32,
+    /// How long in seconds a tank must wait between shots.
+    pub shot_cool_down: f64,
+    /// How fast a bullet travels, in units per second.
+    pub bullet_velocity: f32,
+    /// How many times a bullet bounces off the walls before it is removed.
+    pub bullet_bounce_count_max: i32,
+    /// The furthest in milliseconds that hit checks are rewound to where a laggy shooter saw the other tanks, 0 to turn lag compensation off.
+    pub max_lag_compensation_ms: u64,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            vote_time: VOTE_TIME,
+            pong: PongRules::default(),
+            tank: TankRules::default(),
+        }
+    }
+}
+
+impl Default for PongRules {
+    fn default() -> Self {
+        PongRules {
+            points_to_win: PONG_POINTS_TO_WIN,
+            paddle_move_speed: PADDLE_MOVE_SPEED,
+            ball_vel_add_min: PONG_BALL_VEL_ADD_MIN,
+            ball_vel_add_max: PONG_BALL_VEL_ADD_MAX,
+            power_hit_modifier: POWER_HIT_MODIFIER,
+            power_hit_lock_time: POWER_HIT_LOCK_TIME,
+            power_hit_cooldown: POWER_HIT_COOLDOWN,
+        }
+    }
+}
+
+impl Default for TankRules {
+    fn default() -> Self {
+        TankRules {
+            win_score: TANK_WIN_SCORE,
+            max_speed: TANK_MAX_SPEED,
+            accel: TANK_ACCEL,
+            turn_speed: TANK_TURN_SPEED,
+            friction: TANK_FRICTION,
+            shot_cool_down: TANK_SHOT_COOL_DOWN,
+            bullet_velocity: TANK_BULLET_VELOCITY,
+            bullet_bounce_count_max: TANK_BULLET_BOUNCE_COUNT_MAX,
+            max_lag_compensation_ms: MAX_LAG_COMPENSATION_MS,
+        }
+    }
+}
+
+/// Returns an error naming the rule if the value is not a number that is at least zero.
+fn check_not_negative(name: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("{} must be zero or more, not {}", name, value));
+    }
+    Ok(())
+}
+
+/// Returns an error naming the rule if the value is not a number above zero.
+fn check_positive(name: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("{} must be more than zero, not {}", name, value));
+    }
+    Ok(())
+}
+
+impl GameRules {
+    /// Checks that every rule makes sense, returning what is wrong with the first one that does not.
+    /// The server refuses to start with rules that would break the game, like a round that can never be won.
+    pub fn validate(&self) -> Result<(), String> {
+        check_not_negative("vote_time", self.vote_time as f64)?;
+
+        let pong = &self.pong;
+        check_positive("pong.points_to_win", pong.points_to_win as f64)?;
+        check_positive("pong.paddle_move_speed", pong.paddle_move_speed as f64)?;
+        check_not_negative("pong.ball_vel_add_min", pong.ball_vel_add_min as f64)?;
+        check_not_negative("pong.ball_vel_add_max", pong.ball_vel_add_max as f64)?;
+        if pong.ball_vel_add_min >= pong.ball_vel_add_max {
+            return Err(format!(
+                "pong.ball_vel_add_min ({}) must be less than pong.ball_vel_add_max ({})",
+                pong.ball_vel_add_min, pong.ball_vel_add_max
+            ));
+        } // the added velocity is picked from between the two
+        check_not_negative("pong.power_hit_modifier", pong.power_hit_modifier as f64)?;
+        check_not_negative("pong.power_hit_lock_time", pong.power_hit_lock_time as f64)?;
+        check_not_negative("pong.power_hit_cooldown", pong.power_hit_cooldown as f64)?;
+
+        let tank = &self.tank;
+        check_positive("tank.win_score", tank.win_score as f64)?;
+        check_positive("tank.max_speed", tank.max_speed as f64)?;
+        check_positive("tank.accel", tank.accel as f64)?;
+        check_not_negative("tank.turn_speed", tank.turn_speed as f64)?;
+        check_positive("tank.friction", tank.friction as f64)?;
+        if tank.friction > 1.0 {
+            return Err(format!(
+                "tank.friction must be 1 or less, not {}",
+                tank.friction
+            ));
+        } // above 1 tanks would speed up on their own
+        check_not_negative("tank.shot_cool_down", tank.shot_cool_down)?;
+        check_positive("tank.bullet_velocity", tank.bullet_velocity as f64)?;
+        check_positive(
+            "tank.bullet_bounce_count_max",
+            tank.bullet_bounce_count_max as f64,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rules::GameRules;
+
+    #[test]
+    fn game_rules_validate_test() {
+        assert_eq!(GameRules::default().validate(), Ok(()));
+
+        let mut rules = GameRules::default();
+        rules.pong.points_to_win = 0;
+        assert!(rules.validate().unwrap_err().contains("pong.points_to_win"));
+
+        let mut rules = GameRules::default();
+        rules.pong.ball_vel_add_min = rules.pong.ball_vel_add_max;
+        assert!(rules.validate().is_err());
+
+        let mut rules = GameRules::default();
+        rules.tank.friction = 1.5;
+        assert!(rules.validate().unwrap_err().contains("tank.friction"));
+
+        let rules = GameRules {
+            vote_time: f32::NAN,
+            ..GameRules::default()
+        };
+        assert!(rules.validate().is_err());
+    }
+
+    #[test]
+    fn game_rules_partial_test() {
+        // anything left out keeps its default
+        let rules: GameRules =
+            serde_json::from_str(r#"{"vote_time": 5.0, "tank": {"win_score": 3}}"#).unwrap();
+        assert_eq!(rules.vote_time, 5.0);
+        assert_eq!(rules.tank.win_score, 3);
+        assert_eq!(rules.tank.max_speed, GameRules::default().tank.max_speed);
+        assert_eq!(rules.pong, GameRules::default().pong);
+
+        assert!(serde_json::from_str::<GameRules>(r#"{"vote_tme": 5.0}"#).is_err());
+    }
+}
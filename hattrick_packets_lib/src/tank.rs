@@ -1,12 +1,17 @@
 use crate::clientstate::ClientState;
+use crate::keystate::KeyState;
+use crate::lag_compensation::TankHistory;
+use crate::rules::TankRules;
 use crate::team::Team;
-use crate::{distance, GAME_HEIGHT, GAME_WIDTH};
+use crate::team::Team::{BlueTeam, RedTeam};
+use crate::{distance, round_digits, two_point_angle, GAME_HEIGHT, GAME_WIDTH};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // keep toying with different values, have not found something i like quite yet.
+// the movement, shooting and scoring statics are only the defaults, servers can change them in their game rules. See the rules module.
 pub static TANK_MAX_SPEED: f32 = 60.0;
 pub static TANK_ACCEL: f32 = 500.0;
 pub static TANK_TURN_SPEED: f32 = 45.0;
@@ -34,14 +39,14 @@ pub static TANK_BULLET_RADIUS: f32 = 5.0;
 //  and checks its point distance from each tank on the map on each frame to find if it is to hit a tank.
 //  It also has a function called step, which moves its location based on its xvel and yvel.
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct TankGameState {
     pub red_score: i32,
     pub blue_score: i32,
     pub bullets: Vec<TankBullet>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TankClientState {
     pub rotation: f32,
     pub tank_x: f32,
@@ -51,7 +56,7 @@ pub struct TankClientState {
     pub last_shot_time: SystemTime,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TankBullet {
     pub x: f32,
     pub y: f32,
@@ -59,6 +64,9 @@ pub struct TankBullet {
     pub y_vel: f32,
     pub bounce_count: i32,
     pub team: Team,
+    /// The lag compensation of the tank that fired this bullet, in seconds. Only the server uses this, so it is never sent.
+    #[serde(skip)]
+    pub lag_compensation: f32,
 }
 
 impl TankBullet {
@@ -81,6 +89,65 @@ impl TankBullet {
     }
 }
 
+impl TankClientState {
+    /// Turns, accelerates and moves the tank from the given keys, difference is the time to step in seconds.
+    /// The server runs this every tick, and the client runs it ahead of the server to predict where its own tank is, with the rules the server sent it.
+    pub fn step_movement(&mut self, key_state: &KeyState, difference: f32, rules: &TankRules) {
+        let x_ratio = {
+            let rad = self.rotation.to_radians();
+            if rad.cos().is_nan() {
+                0.0
+            } else {
+                rad.cos()
+            }
+        };
+
+        let y_ratio = {
+            let rad = self.rotation.to_radians();
+            if rad.sin().is_nan() {
+                0.0
+            } else {
+                rad.sin()
+            }
+        };
+
+        let current_speed = (self.tank_x_vel.powi(2) + self.tank_y_vel.powi(2)).sqrt();
+
+        if key_state.d_key {
+            self.rotation += rules.turn_speed * difference;
+        }
+        if key_state.a_key {
+            self.rotation -= rules.turn_speed * difference;
+        }
+        if key_state.w_key && current_speed < rules.max_speed {
+            self.tank_x_vel += (rules.accel * x_ratio) * difference;
+            self.tank_y_vel += (rules.accel * y_ratio) * difference;
+        }
+        if key_state.s_key && -current_speed > -rules.max_speed {
+            self.tank_x_vel -= (rules.accel * x_ratio) * difference;
+            self.tank_y_vel -= (rules.accel * y_ratio) * difference;
+        }
+
+        // friction was tuned for a game loop that ran about every millisecond, so apply it once for every millisecond stepped.
+        let friction = rules.friction.powf(difference * 1000.0);
+        self.tank_x_vel *= friction;
+        self.tank_y_vel *= friction;
+
+        if self.tank_x_vel.abs() < 0.05 && self.tank_y_vel.abs() < 0.05 {
+            self.tank_x_vel = 0.0;
+            self.tank_y_vel = 0.0;
+        } // if velocity is very small, make it 0 so there is no slow drifting for tanks.
+
+        self.tank_x += self.tank_x_vel * difference;
+        self.tank_y += self.tank_y_vel * difference;
+
+        round_digits(&mut self.tank_x_vel, 4);
+        round_digits(&mut self.tank_y_vel, 4);
+        round_digits(&mut self.tank_x, 4);
+        round_digits(&mut self.tank_y, 4);
+    }
+}
+
 impl Default for TankClientState {
     fn default() -> Self {
         TankClientState {
@@ -95,11 +162,111 @@ impl Default for TankClientState {
 }
 
 impl TankGameState {
+    /// Steps the tank game by difference seconds, moving every tank from its keys, firing bullets for tanks that shoot, moving the bullets,
+    /// and scoring a point for each bullet that hits a tank on the other team. clients is changed in place, as tanks move and respawn when they are hit.
+    /// Hits are checked against where tanks are now, see step_with_history for checking them against where the shooter saw them.
+    pub fn step(
+        &mut self,
+        clients: &mut HashMap<String, ClientState>,
+        difference: f32,
+        rules: &TankRules,
+    ) {
+        self.step_with_history(
+            clients,
+            difference,
+            rules,
+            &mut TankHistory::default(),
+            SystemTime::now(),
+        );
+    }
+
+    /// The same as step, but bullets are checked against where the tanks were when their shooter fired, going by each bullets lag compensation.
+    /// A bullet is never rewound further than the rules max_lag_compensation_ms.
+    /// history is where tanks were on previous ticks, the tanks positions after moving are recorded into it at now. The server keeps one history for each game.
+    pub fn step_with_history(
+        &mut self,
+        clients: &mut HashMap<String, ClientState>,
+        difference: f32,
+        rules: &TankRules,
+        history: &mut TankHistory,
+        now: SystemTime,
+    ) {
+        for client in clients.values_mut() {
+            // move first, so bullets are fired from where the tank is at the end of the tick
+            client
+                .tank_client_state
+                .step_movement(&client.key_state, difference, rules);
+
+            let last_shot_diff = now
+                .duration_since(client.tank_client_state.last_shot_time)
+                .unwrap_or_default()
+                .as_secs_f64();
+
+            if client.key_state.space_bar && last_shot_diff > rules.shot_cool_down {
+                client.tank_client_state.last_shot_time = now;
+                self.bullets.push(fire_bullet(client, rules));
+            } // shoot bullet from a tank
+        } // input handling for clients
+
+        for bullet in &mut self.bullets {
+            bullet.step(&difference);
+        } // do physics for bullets
+
+        self.remove_dead_bullets(rules); // remove all dead bullets from the game state
+
+        history.record(now, clients, rules.max_lag_compensation_ms);
+        let max_rewind = Duration::from_millis(rules.max_lag_compensation_ms);
+
+        // bad practice cloning happening here, respawning needs to know where everyone was before anyone was hit.
+        let copy_client_list = clients.clone();
+        let copy_bullets_list = self.bullets.clone();
+        for bullet in std::mem::take(&mut self.bullets) {
+            // rewind the tanks to where the shooter saw them when they fired.
+            let rewind = Duration::from_secs_f32(bullet.lag_compensation).min(max_rewind);
+            let rewound_positions = history.positions_at(now - rewind);
+            let hit = clients
+                .iter()
+                .find(|(uuid, client)| {
+                    let (tank_x, tank_y) = rewound_positions
+                        .and_then(|positions| positions.get(*uuid))
+                        .copied()
+                        .unwrap_or((
+                            client.tank_client_state.tank_x,
+                            client.tank_client_state.tank_y,
+                        )); // tanks that were not around back then are checked where they are now
+                    distance(bullet.x, bullet.y, tank_x, tank_y)
+                        < TANK_BULLET_RADIUS + (TANK_WIDTH + TANK_HEIGHT) / 2.0
+                        && bullet.team != client.team_id
+                })
+                .map(|(uuid, _)| uuid.clone());
+
+            match hit {
+                Some(uuid) => {
+                    let client = clients.get_mut(&uuid).unwrap();
+                    match client.team_id {
+                        RedTeam => {
+                            self.blue_score += 1;
+                        }
+                        BlueTeam => {
+                            self.red_score += 1;
+                        }
+                    }
+                    respawn_tank(
+                        &mut client.tank_client_state,
+                        &copy_bullets_list,
+                        &copy_client_list,
+                    );
+                } // a bullet only ever hits one tank, and is gone once it has
+                None => self.bullets.push(bullet),
+            }
+        } // check for bullet collision on clients, and remove bullet if collision occurs.
+    }
+
     /// Removes all bullets in the game state that have >= the bounce limit each
-    pub fn remove_dead_bullets(&mut self) {
+    pub fn remove_dead_bullets(&mut self, rules: &TankRules) {
         for index in 0..self.bullets.len() {
             if let Some(bullet) = self.bullets.get(index) {
-                if bullet.bounce_count >= TANK_BULLET_BOUNCE_COUNT_MAX {
+                if bullet.bounce_count >= rules.bullet_bounce_count_max {
                     self.bullets.remove(index);
                 }
             }
@@ -107,6 +274,33 @@ impl TankGameState {
     }
 }
 
+/// Makes a bullet leaving the middle of the clients tank, heading towards their mouse.
+fn fire_bullet(client: &ClientState, rules: &TankRules) -> TankBullet {
+    // the bullet is spawned from TANK_WIDTH or TANK_HEIGHT /2 because we want to spawn the bullet from the middle of the tank, not the top left corner
+    // which is where its x and y coordinates lie.
+    let x = client.tank_client_state.tank_x + (TANK_WIDTH / 2.0);
+    let y = client.tank_client_state.tank_y + (TANK_HEIGHT / 2.0);
+    let rad = two_point_angle((x, y), client.mouse_pos).to_radians();
+
+    TankBullet {
+        x,
+        y,
+        x_vel: if rad.cos().is_nan() {
+            0.0
+        } else {
+            rad.cos() * rules.bullet_velocity
+        },
+        y_vel: if rad.sin().is_nan() {
+            0.0
+        } else {
+            rad.sin() * rules.bullet_velocity
+        },
+        bounce_count: 0,
+        team: client.team_id.clone(),
+        lag_compensation: client.lag_compensation,
+    }
+}
+
 /// respawn_tank takes in a mutable TankClientState, and
 pub fn respawn_tank(
     tank_client_state: &mut TankClientState,
@@ -114,7 +308,6 @@ pub fn respawn_tank(
     _clients: &HashMap<String, ClientState>,
 ) {
     let position: (f32, f32, f32) = (0..10) // generate 10 random positions to potentially respawn the player
-        .into_iter()
         .map(|_| {
             // map with _ because we dont care about the individual numbers.
             // rand x and y values
@@ -123,8 +316,8 @@ pub fn respawn_tank(
 
             // from those random x and y values, generate the distance to the closest tank in the game
             let closest_tank_dist = _clients
-                .iter()
-                .map(|(_, client_state)| {
+                .values()
+                .map(|client_state| {
                     // map only each client state, as we dont care  about their uuid s
                     distance(
                         client_state.tank_client_state.tank_x,
@@ -160,3 +353,172 @@ pub fn respawn_tank(
     // set their last shot time to unix epoch so they can shoot immediately no matter what
     tank_client_state.last_shot_time = UNIX_EPOCH;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::clientstate::ClientState;
+    use crate::lag_compensation::{TankHistory, MAX_LAG_COMPENSATION_MS};
+    use crate::rules::TankRules;
+    use crate::tank::{TankBullet, TankClientState, TankGameState};
+    use crate::team::Team;
+    use crate::team::Team::{BlueTeam, RedTeam};
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn tank_at(team: Team, x: f32, y: f32) -> ClientState {
+        ClientState {
+            team_id: team,
+            tank_client_state: TankClientState {
+                rotation: 0.0,
+                tank_x: x,
+                tank_y: y,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn bullet_at(team: Team, x: f32, y: f32) -> TankBullet {
+        TankBullet {
+            x,
+            y,
+            x_vel: 0.0,
+            y_vel: 0.0,
+            bounce_count: 0,
+            team,
+            lag_compensation: 0.0,
+        }
+    }
+
+    #[test]
+    fn tank_step_movement_test() {
+        let mut tgs = TankGameState::default();
+        let mut clients = HashMap::new();
+        let mut client = tank_at(RedTeam, 100.0, 100.0);
+        client.key_state.w_key = true;
+        clients.insert("red".to_string(), client);
+
+        tgs.step(&mut clients, 0.1, &TankRules::default());
+
+        // facing right, so only x changes
+        let tank = &clients["red"].tank_client_state;
+        assert!(tank.tank_x > 100.0);
+        assert_eq!(tank.tank_y, 100.0);
+        assert!(tgs.bullets.is_empty());
+    }
+
+    #[test]
+    fn tank_step_shoot_test() {
+        let rules = TankRules::default();
+        let mut tgs = TankGameState::default();
+        let mut clients = HashMap::new();
+        let mut client = tank_at(BlueTeam, 100.0, 100.0);
+        client.key_state.space_bar = true;
+        client.mouse_pos = (500.0, 110.0);
+        clients.insert("blue".to_string(), client);
+
+        tgs.step(&mut clients, 0.01, &rules);
+        assert_eq!(tgs.bullets.len(), 1);
+        assert_eq!(tgs.bullets[0].team, BlueTeam);
+        assert!(tgs.bullets[0].x_vel > 0.0);
+        assert!((tgs.bullets[0].y_vel).abs() < 0.01);
+
+        // still cooling down, so holding space does not shoot again
+        tgs.step(&mut clients, 0.01, &rules);
+        assert_eq!(tgs.bullets.len(), 1);
+    }
+
+    #[test]
+    fn tank_step_hit_test() {
+        let rules = TankRules::default();
+        let mut tgs = TankGameState::default();
+        let mut clients = HashMap::new();
+        clients.insert("red".to_string(), tank_at(RedTeam, 300.0, 300.0));
+        clients.insert("blue".to_string(), tank_at(BlueTeam, 100.0, 100.0));
+
+        // bullets dont hit their own team
+        tgs.bullets.push(bullet_at(RedTeam, 300.0, 300.0));
+        tgs.step(&mut clients, 0.01, &rules);
+        assert_eq!(tgs.bullets.len(), 1);
+        assert_eq!((tgs.red_score, tgs.blue_score), (0, 0));
+
+        tgs.bullets = vec![bullet_at(BlueTeam, 300.0, 300.0)];
+        tgs.step(&mut clients, 0.01, &rules);
+        assert!(tgs.bullets.is_empty());
+        assert_eq!((tgs.red_score, tgs.blue_score), (0, 1));
+        assert_eq!(clients["red"].tank_client_state.tank_x_vel, 0.0);
+    }
+
+    #[test]
+    fn tank_step_dead_bullets_test() {
+        let rules = TankRules::default();
+        let mut tgs = TankGameState::default();
+        let mut clients = HashMap::new();
+        clients.insert("red".to_string(), tank_at(RedTeam, 300.0, 300.0));
+
+        let mut bullet = bullet_at(BlueTeam, 50.0, 50.0);
+        bullet.bounce_count = rules.bullet_bounce_count_max;
+        tgs.bullets.push(bullet);
+        tgs.step(&mut clients, 0.01, &rules);
+        assert!(tgs.bullets.is_empty());
+        assert_eq!(tgs.blue_score, 0);
+    }
+
+    #[test]
+    fn tank_step_with_history_test() {
+        let rules = TankRules::default();
+        let now = SystemTime::now();
+        let mut history = TankHistory::default();
+        let mut clients = HashMap::new();
+        clients.insert("red".to_string(), tank_at(RedTeam, 100.0, 100.0));
+        history.record(
+            now - Duration::from_millis(100),
+            &clients,
+            rules.max_lag_compensation_ms,
+        );
+        clients.get_mut("red").unwrap().tank_client_state.tank_x = 400.0;
+
+        // a shooter with no lag missed, the tank has moved on
+        let mut tgs = TankGameState::default();
+        tgs.bullets.push(bullet_at(BlueTeam, 100.0, 100.0));
+        tgs.step_with_history(&mut clients, 0.01, &rules, &mut history, now);
+        assert_eq!(tgs.blue_score, 0);
+
+        // a shooter seeing the tank 100ms late hit it where they saw it
+        tgs.bullets = vec![TankBullet {
+            lag_compensation: 0.1,
+            ..bullet_at(BlueTeam, 100.0, 100.0)
+        }];
+        tgs.step_with_history(&mut clients, 0.01, &rules, &mut history, now);
+        assert_eq!(tgs.blue_score, 1);
+        assert!(tgs.bullets.is_empty());
+        assert_eq!(clients["red"].tank_client_state.last_shot_time, UNIX_EPOCH);
+    }
+    #[test]
+    fn tank_step_lag_compensation_limit_test() {
+        // the shooter saw the tank 100ms ago, but the server only rewinds 50ms
+        let rules = TankRules {
+            max_lag_compensation_ms: 50,
+            ..TankRules::default()
+        };
+        let now = SystemTime::now();
+        let mut history = TankHistory::default();
+        let mut clients = HashMap::new();
+        clients.insert("red".to_string(), tank_at(RedTeam, 100.0, 100.0));
+        history.record(
+            now - Duration::from_millis(100),
+            &clients,
+            MAX_LAG_COMPENSATION_MS,
+        );
+        clients.get_mut("red").unwrap().tank_client_state.tank_x = 400.0;
+
+        let mut tgs = TankGameState::default();
+        tgs.bullets.push(TankBullet {
+            lag_compensation: 0.1,
+            ..bullet_at(BlueTeam, 100.0, 100.0)
+        });
+        tgs.step_with_history(&mut clients, 0.01, &rules, &mut history, now);
+        assert_eq!(tgs.blue_score, 0);
+        assert_eq!(tgs.bullets.len(), 1);
+    }
+}
@@ -1,8 +1,239 @@
+use crate::clientstate::ClientState;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime};
 
-/// Team is the team selection enumeration that the player can choose, at the moment, it is stored on the client and sent to server. This might be changed later :)
+/// How long in milliseconds a player has to wait after changing team before the server lets them change again.
+pub static TEAM_CHANGE_COOL_DOWN_MS: u64 = 5000;
+
+/// Team is the team selection enumeration, the server decides which team each player is on, players can only ask to change.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum Team {
     RedTeam,
     BlueTeam,
 }
+
+/// TeamRejectReason is why the server refused a players request to change team, it is displayed to the player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TeamRejectReason {
+    /// The player changed team too recently, and can ask again after the given number of milliseconds.
+    CoolingDown { remaining_ms: u64 },
+    /// Teams are being balanced, and the change would leave one team with more than one extra player.
+    WouldUnbalance,
+}
+
+impl Team {
+    /// The team playing against this one.
+    pub fn other(&self) -> Team {
+        match self {
+            Team::RedTeam => Team::BlueTeam,
+            Team::BlueTeam => Team::RedTeam,
+        }
+    }
+}
+
+impl Display for Team {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Team::RedTeam => write!(f, "red team"),
+            Team::BlueTeam => write!(f, "blue team"),
+        }
+    }
+}
+
+impl Display for TeamRejectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TeamRejectReason::CoolingDown { remaining_ms } => write!(
+                f,
+                "you changed team too recently, try again in {:.1}s",
+                *remaining_ms as f32 / 1000.0
+            ),
+            TeamRejectReason::WouldUnbalance => write!(f, "that team already has more players"),
+        }
+    }
+}
+
+/// Counts the players on each team, returned as (red, blue).
+pub fn team_sizes(client_list: &HashMap<String, ClientState>) -> (usize, usize) {
+    let red = client_list
+        .values()
+        .filter(|client| client.team_id == Team::RedTeam)
+        .count();
+    (red, client_list.len() - red)
+}
+
+/// The team with fewer players, where a new player should go to keep the teams even. Blue if they are even.
+pub fn smaller_team(client_list: &HashMap<String, ClientState>) -> Team {
+    let (red, blue) = team_sizes(client_list);
+    if red < blue {
+        Team::RedTeam
+    } else {
+        Team::BlueTeam
+    }
+}
+
+/// Decides if the given player can move to the given team right now, balanced is true if the server keeps the teams even.
+/// Asking for the team the player is already on is always fine, and changes nothing.
+pub fn check_team_request(
+    client_list: &HashMap<String, ClientState>,
+    uuid: &str,
+    team: &Team,
+    balanced: bool,
+    now: SystemTime,
+) -> Result<(), TeamRejectReason> {
+    let client = match client_list.get(uuid) {
+        None => return Ok(()),
+        Some(client) => client,
+    };
+    if &client.team_id == team {
+        return Ok(());
+    }
+
+    if let Some(changed_at) = client.team_changed_at {
+        let since = now.duration_since(changed_at).unwrap_or_default();
+        let cool_down = Duration::from_millis(TEAM_CHANGE_COOL_DOWN_MS);
+        if since < cool_down {
+            return Err(TeamRejectReason::CoolingDown {
+                remaining_ms: (cool_down - since).as_millis() as u64,
+            });
+        }
+    }
+
+    if balanced {
+        let (red, blue) = team_sizes(client_list);
+        let (joining, leaving) = match team {
+            Team::RedTeam => (red, blue),
+            Team::BlueTeam => (blue, red),
+        };
+        if joining >= leaving {
+            return Err(TeamRejectReason::WouldUnbalance);
+        } // after the move the team joined can be at most one bigger than the team left
+    }
+
+    Ok(())
+}
+
+/// Moves players from the bigger team to the smaller one until the teams differ by at most one player, returning who was moved.
+/// The players who changed team most recently are moved first, as they have been on their team the least time.
+pub fn balance_teams(
+    client_list: &mut HashMap<String, ClientState>,
+    now: SystemTime,
+) -> Vec<String> {
+    let (red, blue) = team_sizes(client_list);
+    let (bigger, moves) = if red > blue {
+        (Team::RedTeam, (red - blue) / 2)
+    } else {
+        (Team::BlueTeam, (blue - red) / 2)
+    };
+
+    let mut candidates: Vec<(String, Option<SystemTime>)> = client_list
+        .iter()
+        .filter(|(_, client)| client.team_id == bigger)
+        .map(|(uuid, client)| (uuid.clone(), client.team_changed_at))
+        .collect();
+    // newest change first, players who never changed count as the oldest, ties broken by id so the result does not depend on hash order
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let moved: Vec<String> = candidates
+        .into_iter()
+        .take(moves)
+        .map(|(uuid, _)| uuid)
+        .collect();
+    for uuid in &moved {
+        if let Some(client) = client_list.get_mut(uuid) {
+            client.team_id = bigger.other();
+            client.team_changed_at = Some(now);
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientstate::ClientState;
+    use crate::team::Team::{BlueTeam, RedTeam};
+    use crate::team::{
+        balance_teams, check_team_request, team_sizes, Team, TeamRejectReason,
+        TEAM_CHANGE_COOL_DOWN_MS,
+    };
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    fn clients(teams: &[Team]) -> HashMap<String, ClientState> {
+        teams
+            .iter()
+            .enumerate()
+            .map(|(index, team)| {
+                (
+                    format!("client{}", index),
+                    ClientState {
+                        team_id: team.clone(),
+                        ..ClientState::default()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn team_request_cool_down_test() {
+        let now = SystemTime::now();
+        let mut client_list = clients(&[BlueTeam, RedTeam]);
+        assert_eq!(
+            check_team_request(&client_list, "client0", &RedTeam, false, now),
+            Ok(())
+        );
+
+        client_list.get_mut("client0").unwrap().team_changed_at = Some(now);
+        assert!(matches!(
+            check_team_request(&client_list, "client0", &RedTeam, false, now),
+            Err(TeamRejectReason::CoolingDown { .. })
+        ));
+        // asking for the team you are on is never refused
+        assert_eq!(
+            check_team_request(&client_list, "client0", &BlueTeam, false, now),
+            Ok(())
+        );
+        let later = now + Duration::from_millis(TEAM_CHANGE_COOL_DOWN_MS);
+        assert_eq!(
+            check_team_request(&client_list, "client0", &RedTeam, false, later),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn team_request_balance_test() {
+        let now = SystemTime::now();
+        let client_list = clients(&[BlueTeam, BlueTeam, RedTeam]);
+        // 2 v 1 to 1 v 2 is as even as it was
+        assert_eq!(
+            check_team_request(&client_list, "client0", &RedTeam, true, now),
+            Ok(())
+        );
+        // 2 v 1 to 3 v 0 is not
+        assert_eq!(
+            check_team_request(&client_list, "client2", &BlueTeam, true, now),
+            Err(TeamRejectReason::WouldUnbalance)
+        );
+        assert_eq!(
+            check_team_request(&client_list, "client2", &BlueTeam, false, now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn balance_teams_test() {
+        let now = SystemTime::now();
+        let mut client_list = clients(&[BlueTeam, BlueTeam, BlueTeam, BlueTeam, BlueTeam, RedTeam]);
+        client_list.get_mut("client3").unwrap().team_changed_at =
+            Some(now - Duration::from_secs(10));
+        client_list.get_mut("client4").unwrap().team_changed_at = Some(now);
+
+        let moved = balance_teams(&mut client_list, now);
+        assert_eq!(moved, vec!["client4".to_string(), "client3".to_string()]);
+        assert_eq!(team_sizes(&client_list), (3, 3));
+        assert!(balance_teams(&mut client_list, now).is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::connection::HEARTBEAT_INTERVAL_MS;
+use crate::framing::{FrameError, FramedStream};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Default for how long in milliseconds a connection can go without receiving anything before it is considered dead, servers can change theirs.
+pub static IDLE_TIMEOUT_MS: u64 = 5000;
+
+/// How long in milliseconds to wait on the other side to end its turn when packets can be lost, after this we carry on without them.
+pub static UNRELIABLE_TURN_TIMEOUT_MS: u64 = 50;
+
+/// TransportKind is which kind of socket a connection is made over, tcp is the default.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Udp,
+    /// Websockets are for browser builds, which can not open plain sockets, they connect to the servers websocket port.
+    WebSocket,
+}
+
+/// Channel is how a payload should be delivered, transports that are always reliable ignore this.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// The payload may be lost or dropped if a newer payload arrives first, used for things sent every frame like input and snapshots.
+    Unreliable,
+    /// The payload is resent until it is acknowledged, and delivered in the order it was sent, used for control messages.
+    Reliable,
+}
+
+/// TransportError is every way sending or receiving over a transport can fail, any of these should end the connection.
+#[derive(Debug)]
+pub enum TransportError {
+    Frame(FrameError),
+    Io(std::io::Error),
+    /// A payload was too large to fit in a single datagram.
+    DatagramTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The websocket library failed for a reason other than the socket underneath it.
+    #[cfg(not(target_arch = "wasm32"))]
+    WebSocket(Box<tungstenite::Error>),
+    /// The other side has stopped acknowledging reliable payloads, and too many are waiting to be resent.
+    Unacknowledged {
+        max: usize,
+    },
+    Closed,
+}
+
+/// Transport is a connection that whole payloads can be sent and received over, no matter the socket underneath.
+pub trait Transport: Send {
+    /// Sends a payload on the given channel.
+    fn send_on(&mut self, channel: Channel, payload: &[u8]) -> Result<(), TransportError>;
+
+    /// Waits up to the given timeout for the next payload, Ok(None) is returned if nothing arrived in time.
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError>;
+
+    /// True if every payload sent is guaranteed to arrive, in which case the other side can always be waited on.
+    fn is_reliable(&self) -> bool;
+
+    /// How long to wait on the other side to end its turn before carrying on without them.
+    /// A reliable transport only waits a heartbeat interval at a time, so a stalled connection does not hold up a whole idle timeout every turn.
+    fn turn_timeout(&self) -> Duration {
+        if self.is_reliable() {
+            Duration::from_millis(HEARTBEAT_INTERVAL_MS)
+        } else {
+            Duration::from_millis(UNRELIABLE_TURN_TIMEOUT_MS)
+        }
+    }
+
+    /// The cookie a server side connection was given to send to the client in its welcome, None for connections that do not need one.
+    /// Only udp has one, since a udp source address can be faked and the cookie coming back is what shows the client can hear us.
+    fn cookie(&self) -> Option<u64> {
+        None
+    }
+
+    /// Gives a client side connection the cookie from the servers welcome, to send back with everything after the handshake. Does nothing unless the connection needs one.
+    fn set_cookie(&mut self, _cookie: u64) {}
+}
+
+impl Display for TransportKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportKind::Tcp => write!(f, "TCP"),
+            TransportKind::Udp => write!(f, "UDP"),
+            TransportKind::WebSocket => write!(f, "WebSocket"),
+        }
+    }
+}
+
+impl Display for TransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Frame(e) => write!(f, "{}", e),
+            TransportError::Io(e) => write!(f, "io error: {}", e),
+            TransportError::DatagramTooLarge { size, max } => write!(
+                f,
+                "payload of {} bytes does not fit in a datagram of {} bytes",
+                size, max
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            TransportError::WebSocket(e) => write!(f, "websocket error: {}", e),
+            TransportError::Unacknowledged { max } => write!(
+                f,
+                "the other side stopped acknowledging, {} reliable payloads are waiting",
+                max
+            ),
+            TransportError::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<FrameError> for TransportError {
+    fn from(e: FrameError) -> Self {
+        TransportError::Frame(e)
+    }
+}
+
+impl From<std::io::Error> for TransportError {
+    fn from(e: std::io::Error) -> Self {
+        TransportError::Io(e)
+    }
+}
+
+/// Returns true if the io error is a read timing out rather than the connection breaking.
+pub fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Tcp is always reliable and ordered, so every channel is sent the same way.
+impl Transport for FramedStream<TcpStream> {
+    fn send_on(&mut self, _channel: Channel, payload: &[u8]) -> Result<(), TransportError> {
+        self.send(payload)?;
+        Ok(())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+        // a zero duration read timeout is an error, so always wait at least a millisecond
+        self.get_ref()
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        match self.recv() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(FrameError::Io(e)) if is_timeout(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn is_reliable(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,640 @@
+use crate::handshake::{ClientHello, HANDSHAKE_CODEC};
+use crate::transport::{is_timeout, Channel, Transport, TransportError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+/// Largest datagram in bytes we will send, payloads that do not fit are an error as we do not split them up.
+pub static MAX_DATAGRAM_SIZE: usize = 65_000;
+
+/// How long in milliseconds to wait on an acknowledgement before a reliable payload is sent again.
+pub static RELIABLE_RESEND_INTERVAL_MS: u64 = 100;
+
+/// How far ahead of the next reliable payload we are waiting on a payload can be and still be held, anything further ahead is dropped and has to be resent.
+/// Without this a peer could make us hold on to as many payloads as it likes.
+pub static RELIABLE_WINDOW: u32 = 256;
+
+/// Most reliable payloads that can be waiting on an acknowledgement, sending another one fails so the connection is closed.
+/// A peer that never acknowledges anything would otherwise have every reliable payload kept for it forever.
+pub static MAX_UNACKED_RELIABLE: usize = 256;
+
+/// Most addresses a UdpListener keeps that have sent a hello but not sent back the cookie from their welcome, hellos from new addresses are ignored while this many are waiting.
+/// Udp source addresses are easy to fake, and every address accepted gets its own connection, so this stops a flood of hellos from piling them up.
+pub static MAX_PENDING_UDP_PEERS: usize = 32;
+
+/// Datagram is the packet sent over udp, every datagram carries an acknowledgement of the reliable payloads its sender has received.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Datagram {
+    /// The next reliable sequence number the sender is expecting, meaning every reliable payload before it has arrived.
+    pub ack: u32,
+    /// The cookie of the connection, see ServerWelcome. Clients send None until the welcome arrives.
+    pub cookie: Option<u64>,
+    pub body: DatagramBody,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DatagramBody {
+    Unreliable {
+        sequence: u32,
+        payload: Vec<u8>,
+    },
+    Reliable {
+        sequence: u32,
+        payload: Vec<u8>,
+    },
+    /// Sent when we have received a reliable payload and have nothing else to send the ack with.
+    AckOnly,
+}
+
+/// ReliableEndpoint is one side of the udp reliability layer, it does no io itself, datagrams are made with wrap and fed in with receive.
+/// The datagrams it makes have no cookie, the transport sending them adds it.
+/// Reliable payloads are kept until they are acknowledged and resent, and delivered in order on the receiving side.
+/// Unreliable payloads are delivered as they arrive, unless a newer one has already been delivered.
+#[derive(Debug, Default)]
+pub struct ReliableEndpoint {
+    next_reliable_sequence: u32,
+    /// Reliable payloads that have not been acknowledged yet, with the time they were last sent.
+    unacked: VecDeque<(u32, Vec<u8>, Instant)>,
+    next_expected_reliable: u32,
+    /// Reliable payloads that arrived before an earlier payload, held until the gap is filled.
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    next_unreliable_sequence: u32,
+    newest_unreliable_received: Option<u32>,
+}
+
+impl ReliableEndpoint {
+    /// Turns a payload into a datagram to be sent, reliable payloads are remembered until they are acknowledged.
+    pub fn wrap(&mut self, channel: Channel, payload: &[u8]) -> Datagram {
+        let body = match channel {
+            Channel::Unreliable => {
+                let sequence = self.next_unreliable_sequence;
+                self.next_unreliable_sequence += 1;
+                DatagramBody::Unreliable {
+                    sequence,
+                    payload: payload.to_vec(),
+                }
+            }
+            Channel::Reliable => {
+                let sequence = self.next_reliable_sequence;
+                self.next_reliable_sequence += 1;
+                self.unacked
+                    .push_back((sequence, payload.to_vec(), Instant::now()));
+                DatagramBody::Reliable {
+                    sequence,
+                    payload: payload.to_vec(),
+                }
+            }
+        };
+        Datagram {
+            ack: self.next_expected_reliable,
+            cookie: None,
+            body,
+        }
+    }
+
+    /// A datagram that only acknowledges what we have received.
+    pub fn ack(&self) -> Datagram {
+        Datagram {
+            ack: self.next_expected_reliable,
+            cookie: None,
+            body: DatagramBody::AckOnly,
+        }
+    }
+
+    /// Processes a received datagram, returning every payload that is now ready to be handed to the game, in order.
+    pub fn receive(&mut self, datagram: Datagram) -> Vec<Vec<u8>> {
+        // everything before the ack has arrived on the other side, so we can forget about it
+        self.unacked
+            .retain(|(sequence, _, _)| *sequence >= datagram.ack);
+
+        match datagram.body {
+            DatagramBody::Unreliable { sequence, payload } => {
+                match self.newest_unreliable_received {
+                    Some(newest) if sequence <= newest => vec![], // stale or duplicate, a newer one was already delivered
+                    _ => {
+                        self.newest_unreliable_received = Some(sequence);
+                        vec![payload]
+                    }
+                }
+            }
+            DatagramBody::Reliable { sequence, payload } => {
+                if sequence >= self.next_expected_reliable
+                    && sequence - self.next_expected_reliable < RELIABLE_WINDOW
+                {
+                    self.out_of_order.insert(sequence, payload);
+                } // anything lower is a resend of something we already have, anything past the window is resent once we catch up
+
+                let mut ready = vec![];
+                while let Some(payload) = self.out_of_order.remove(&self.next_expected_reliable) {
+                    ready.push(payload);
+                    self.next_expected_reliable += 1;
+                }
+                ready
+            }
+            DatagramBody::AckOnly => vec![],
+        }
+    }
+
+    /// Returns every unacknowledged reliable payload that has waited longer than the resend interval, as datagrams ready to be sent again.
+    pub fn resends(&mut self, now: Instant) -> Vec<Datagram> {
+        let ack = self.next_expected_reliable;
+        self.unacked
+            .iter_mut()
+            .filter(|(_, _, sent)| {
+                now.duration_since(*sent) >= Duration::from_millis(RELIABLE_RESEND_INTERVAL_MS)
+            })
+            .map(|(sequence, payload, sent)| {
+                *sent = now;
+                Datagram {
+                    ack,
+                    cookie: None,
+                    body: DatagramBody::Reliable {
+                        sequence: *sequence,
+                        payload: payload.clone(),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Number of reliable payloads still waiting on an acknowledgement.
+    pub fn unacked_count(&self) -> usize {
+        self.unacked.len()
+    }
+}
+
+/// Where a udp transport gets its datagrams from.
+enum DatagramSource {
+    /// Read straight from the socket, used by clients which have the socket to themselves.
+    Socket,
+    /// Forwarded by a UdpListener, used on the server where one socket is shared by every client.
+    Listener(Receiver<Vec<u8>>),
+}
+
+/// UdpTransport is a connection to a single peer over udp, with the reliability layer on top.
+pub struct UdpTransport {
+    socket: Arc<UdpSocket>,
+    peer: SocketAddr,
+    source: DatagramSource,
+    endpoint: ReliableEndpoint,
+    ready: VecDeque<Vec<u8>>,
+    /// Sent with every datagram. The listener makes one up for each connection it accepts, and clients are given it in the servers welcome.
+    cookie: Option<u64>,
+    /// Held while the transport is around, so a UdpListener can tell once it has been dropped. None for clients.
+    _alive: Option<Arc<()>>,
+}
+
+impl UdpTransport {
+    /// Creates a transport that talks to the given address from a new socket on any free local port.
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, TransportError> {
+        let peer = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or(TransportError::Closed)?;
+        let local: SocketAddr = if peer.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+        Ok(UdpTransport {
+            socket: Arc::new(socket),
+            peer,
+            source: DatagramSource::Socket,
+            endpoint: ReliableEndpoint::default(),
+            ready: VecDeque::new(),
+            cookie: None,
+            _alive: None,
+        })
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    fn send_datagram(&self, mut datagram: Datagram) -> Result<(), TransportError> {
+        datagram.cookie = self.cookie;
+        let bytes = bincode::serialize(&datagram).map_err(|e| {
+            TransportError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })?;
+        if bytes.len() > MAX_DATAGRAM_SIZE {
+            return Err(TransportError::DatagramTooLarge {
+                size: bytes.len(),
+                max: MAX_DATAGRAM_SIZE,
+            });
+        }
+        match self.source {
+            DatagramSource::Socket => self.socket.send(&bytes)?,
+            DatagramSource::Listener(_) => self.socket.send_to(&bytes, self.peer)?,
+        };
+        Ok(())
+    }
+
+    /// Reads one raw datagram, waiting at most the given timeout.
+    fn read_datagram(&self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+        let timeout = timeout.max(Duration::from_millis(1));
+        match &self.source {
+            DatagramSource::Socket => {
+                self.socket.set_read_timeout(Some(timeout))?;
+                let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+                match self.socket.recv(&mut buf) {
+                    Ok(n) => {
+                        buf.truncate(n);
+                        Ok(Some(buf))
+                    }
+                    Err(e) if is_timeout(&e) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            DatagramSource::Listener(receiver) => match receiver.recv_timeout(timeout) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(TransportError::Closed),
+            },
+        }
+    }
+
+    fn send_resends(&mut self) -> Result<(), TransportError> {
+        for datagram in self.endpoint.resends(Instant::now()) {
+            self.send_datagram(datagram)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_on(&mut self, channel: Channel, payload: &[u8]) -> Result<(), TransportError> {
+        self.send_resends()?;
+        if channel == Channel::Reliable && self.endpoint.unacked_count() >= MAX_UNACKED_RELIABLE {
+            return Err(TransportError::Unacknowledged {
+                max: MAX_UNACKED_RELIABLE,
+            });
+        }
+        let datagram = self.endpoint.wrap(channel, payload);
+        self.send_datagram(datagram)
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(payload) = self.ready.pop_front() {
+                return Ok(Some(payload));
+            }
+            self.send_resends()?;
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            // wake up at least every resend interval so unacknowledged payloads keep getting resent while we wait
+            let wait = (deadline - now).min(Duration::from_millis(RELIABLE_RESEND_INTERVAL_MS));
+            let bytes = match self.read_datagram(wait)? {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+
+            let datagram = match bincode::deserialize::<Datagram>(&bytes) {
+                Ok(datagram) => datagram,
+                Err(_) => continue, // garbage datagrams are ignored
+            };
+            let was_reliable = matches!(datagram.body, DatagramBody::Reliable { .. });
+            self.ready.extend(self.endpoint.receive(datagram));
+            if was_reliable {
+                self.send_datagram(self.endpoint.ack())?;
+            }
+        }
+    }
+
+    fn is_reliable(&self) -> bool {
+        false
+    }
+
+    fn cookie(&self) -> Option<u64> {
+        match self.source {
+            DatagramSource::Listener(_) => self.cookie,
+            DatagramSource::Socket => None,
+        }
+    }
+
+    fn set_cookie(&mut self, cookie: u64) {
+        self.cookie = Some(cookie);
+    }
+}
+
+/// A peer the listener forwards datagrams to.
+struct UdpPeer {
+    sender: Sender<Vec<u8>>,
+    /// Gone once the peers transport has been dropped.
+    alive: Weak<()>,
+    /// Sent to the peer in its welcome, only the real owner of the address gets to see it.
+    cookie: u64,
+    /// False until the peer sends its cookie back, which shows it can hear us and is not a faked address.
+    established: bool,
+}
+
+/// Returns true if the datagram is the first reliable payload of a connection holding a client hello, which is how every connection starts.
+fn is_hello_datagram(bytes: &[u8]) -> bool {
+    match bincode::deserialize::<Datagram>(bytes) {
+        Ok(Datagram {
+            body:
+                DatagramBody::Reliable {
+                    sequence: 0,
+                    payload,
+                },
+            ..
+        }) => HANDSHAKE_CODEC.decode::<ClientHello>(&payload).is_ok(),
+        _ => false,
+    }
+}
+
+/// Returns true if the datagram carries the given cookie.
+fn has_cookie(bytes: &[u8], cookie: u64) -> bool {
+    bincode::deserialize::<Datagram>(bytes).is_ok_and(|datagram| datagram.cookie == Some(cookie))
+}
+
+/// UdpListener lets a server accept udp "connections", each new address that sends us a hello becomes a UdpTransport.
+/// Datagrams from addresses that already have a transport are forwarded to it while waiting for new ones.
+pub struct UdpListener {
+    socket: Arc<UdpSocket>,
+    peers: HashMap<SocketAddr, UdpPeer>,
+}
+
+impl UdpListener {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+        Ok(UdpListener {
+            socket: Arc::new(UdpSocket::bind(address)?),
+            peers: HashMap::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// How long accept waits for a datagram before giving up with a timeout error, None waits forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// Blocks until a hello arrives from a new address, returning a transport for that address with the hello already queued.
+    /// Datagrams from addresses we already have are forwarded to their transport, and anything else from a new address is ignored.
+    /// A transport that has been dropped frees up its address, so the same address can connect again.
+    pub fn accept(&mut self) -> std::io::Result<UdpTransport> {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            self.peers.retain(|_, peer| peer.alive.strong_count() > 0); // forget peers whose transport is gone
+            let (n, address) = self.socket.recv_from(&mut buf)?;
+            let mut bytes = buf[..n].to_vec();
+
+            if let Some(peer) = self.peers.get_mut(&address) {
+                if !peer.established && has_cookie(&bytes, peer.cookie) {
+                    peer.established = true;
+                } // anyone can send from a faked address, but only the real peer knows the cookie
+                match peer.sender.send(bytes) {
+                    Ok(()) => continue,
+                    Err(unsent) => bytes = unsent.0,
+                }
+                self.peers.remove(&address); // the transport for this address is gone, treat this as a new connection
+            }
+
+            if !is_hello_datagram(&bytes) {
+                continue;
+            } // a stray datagram from an old connection, or garbage
+            if self.pending_count() >= MAX_PENDING_UDP_PEERS {
+                continue;
+            } // the real clients among them will resend their hello
+
+            let (sender, receiver) = channel();
+            let _ = sender.send(bytes);
+            let alive = Arc::new(());
+            let cookie = rand::random();
+            self.peers.insert(
+                address,
+                UdpPeer {
+                    sender,
+                    alive: Arc::downgrade(&alive),
+                    cookie,
+                    established: false,
+                },
+            );
+            return Ok(UdpTransport {
+                socket: Arc::clone(&self.socket),
+                peer: address,
+                source: DatagramSource::Listener(receiver),
+                endpoint: ReliableEndpoint::default(),
+                ready: VecDeque::new(),
+                cookie: Some(cookie),
+                _alive: Some(alive),
+            });
+        }
+    }
+
+    /// Number of peers that have sent a hello but not sent back their cookie yet.
+    pub fn pending_count(&self) -> usize {
+        self.peers.values().filter(|peer| !peer.established).count()
+    }
+
+    /// Number of peers datagrams are being forwarded to.
+    pub fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handshake::{ClientHello, HANDSHAKE_CODEC};
+    use crate::transport::{Channel, Transport, TransportError};
+    use crate::udp::{
+        ReliableEndpoint, UdpListener, UdpTransport, MAX_PENDING_UDP_PEERS, MAX_UNACKED_RELIABLE,
+        RELIABLE_RESEND_INTERVAL_MS, RELIABLE_WINDOW,
+    };
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn hello() -> Vec<u8> {
+        HANDSHAKE_CODEC
+            .encode(&ClientHello::new("0.0.0", vec![]))
+            .unwrap()
+    }
+
+    #[test]
+    fn reliable_in_order_test() {
+        let mut sender = ReliableEndpoint::default();
+        let mut receiver = ReliableEndpoint::default();
+        let first = sender.wrap(Channel::Reliable, b"first");
+        let second = sender.wrap(Channel::Reliable, b"second");
+        let third = sender.wrap(Channel::Reliable, b"third");
+
+        // second and third arrive before first, they should be held until first arrives
+        assert!(receiver.receive(second.clone()).is_empty());
+        assert!(receiver.receive(third).is_empty());
+        assert_eq!(
+            receiver.receive(first),
+            vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]
+        );
+        // a resent duplicate is not delivered again
+        assert!(receiver.receive(second).is_empty());
+
+        assert_eq!(sender.unacked_count(), 3);
+        sender.receive(receiver.ack());
+        assert_eq!(sender.unacked_count(), 0);
+    }
+
+    #[test]
+    fn reliable_window_test() {
+        let mut sender = ReliableEndpoint::default();
+        let mut receiver = ReliableEndpoint::default();
+        let datagrams: Vec<_> = (0..=RELIABLE_WINDOW)
+            .map(|_| sender.wrap(Channel::Reliable, b"payload"))
+            .collect();
+
+        // the last one is too far ahead to be held, so it is dropped rather than kept
+        assert!(receiver
+            .receive(datagrams[RELIABLE_WINDOW as usize].clone())
+            .is_empty());
+        let delivered: usize = datagrams[..RELIABLE_WINDOW as usize]
+            .iter()
+            .map(|datagram| receiver.receive(datagram.clone()).len())
+            .sum();
+        assert_eq!(delivered, RELIABLE_WINDOW as usize);
+
+        // once we have caught up the resend is delivered
+        assert_eq!(
+            receiver
+                .receive(datagrams[RELIABLE_WINDOW as usize].clone())
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn udp_unacknowledged_limit_test() {
+        // nobody is listening on the other end, so nothing is ever acknowledged
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut client = UdpTransport::connect(silent.local_addr().unwrap()).unwrap();
+        for _ in 0..MAX_UNACKED_RELIABLE {
+            client.send_on(Channel::Reliable, b"control").unwrap();
+        }
+        client.send_on(Channel::Unreliable, b"input").unwrap();
+        assert!(matches!(
+            client.send_on(Channel::Reliable, b"control"),
+            Err(TransportError::Unacknowledged { max }) if max == MAX_UNACKED_RELIABLE
+        ));
+    }
+
+    #[test]
+    fn unreliable_drops_stale_test() {
+        let mut sender = ReliableEndpoint::default();
+        let mut receiver = ReliableEndpoint::default();
+        let old = sender.wrap(Channel::Unreliable, b"old");
+        let new = sender.wrap(Channel::Unreliable, b"new");
+        assert_eq!(receiver.receive(new), vec![b"new".to_vec()]);
+        assert!(receiver.receive(old).is_empty());
+        assert_eq!(sender.unacked_count(), 0);
+    }
+
+    #[test]
+    fn reliable_resend_test() {
+        let mut sender = ReliableEndpoint::default();
+        sender.wrap(Channel::Reliable, b"lost");
+        assert!(sender.resends(Instant::now()).is_empty());
+        let later = Instant::now() + Duration::from_millis(RELIABLE_RESEND_INTERVAL_MS);
+        assert_eq!(sender.resends(later).len(), 1);
+        assert!(sender.resends(later).is_empty()); // just resent, so not due again yet
+    }
+
+    #[test]
+    fn udp_transport_loopback_test() {
+        let mut listener = UdpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let mut transport = listener.accept().unwrap();
+            let hello = transport
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap()
+                .unwrap();
+            transport.send_on(Channel::Reliable, &hello).unwrap();
+            // keep forwarding datagrams to the transport so the client ack arrives
+            thread::spawn(move || {
+                let _ = listener.accept();
+            });
+            let _ = transport.recv_timeout(Duration::from_millis(200));
+        });
+
+        let mut client = UdpTransport::connect(address).unwrap();
+        client.send_on(Channel::Reliable, &hello()).unwrap();
+        let reply = client.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(reply, Some(hello()));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn udp_listener_needs_hello_test() {
+        let mut listener = UdpListener::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // garbage, and payloads that are not a hello, do not make a peer
+        let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+        stranger.send_to(b"garbage", address).unwrap();
+        let mut client = UdpTransport::connect(address).unwrap();
+        client.send_on(Channel::Reliable, b"not a hello").unwrap();
+        assert!(listener.accept().is_err());
+        assert_eq!(listener.peer_count(), 0);
+
+        let mut client = UdpTransport::connect(address).unwrap();
+        client.send_on(Channel::Reliable, &hello()).unwrap();
+        let transport = listener.accept().unwrap();
+        assert_eq!(listener.pending_count(), 1);
+
+        // the peer is forgotten once its transport is dropped
+        drop(transport);
+        assert!(listener.accept().is_err());
+        assert_eq!(listener.peer_count(), 0);
+    }
+
+    #[test]
+    fn udp_listener_pending_limit_test() {
+        let mut listener = UdpListener::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let mut clients = vec![];
+        let mut transports = vec![];
+        for _ in 0..MAX_PENDING_UDP_PEERS {
+            let mut client = UdpTransport::connect(address).unwrap();
+            client.send_on(Channel::Reliable, &hello()).unwrap();
+            transports.push(listener.accept().unwrap());
+            clients.push(client);
+        }
+        let mut client = UdpTransport::connect(address).unwrap();
+        client.send_on(Channel::Reliable, &hello()).unwrap();
+        assert!(listener.accept().is_err());
+        assert_eq!(listener.peer_count(), MAX_PENDING_UDP_PEERS);
+
+        // sending from the address is not enough, a faked address could do that
+        clients[0].send_on(Channel::Unreliable, b"input").unwrap();
+        clients[1].set_cookie(transports[1].cookie().unwrap().wrapping_add(1));
+        clients[1].send_on(Channel::Unreliable, b"input").unwrap();
+        assert!(listener.accept().is_err());
+        assert_eq!(listener.pending_count(), MAX_PENDING_UDP_PEERS);
+
+        // a peer that sends back the cookie from its welcome is no longer pending, which makes room
+        clients[0].set_cookie(transports[0].cookie().unwrap());
+        clients[0].send_on(Channel::Unreliable, b"input").unwrap();
+        assert!(listener.accept().is_err());
+        assert_eq!(listener.pending_count(), MAX_PENDING_UDP_PEERS - 1);
+        client.send_on(Channel::Reliable, &hello()).unwrap(); // a real client resends its hello
+        assert!(listener.accept().is_ok());
+    }
+}
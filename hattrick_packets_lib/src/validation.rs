@@ -0,0 +1,181 @@
+use crate::clientinfo::ClientInfo;
+use crate::gamestate::MAX_VOTE_NUM;
+use crate::{GAME_HEIGHT, GAME_WIDTH};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// Most messages a client can send each second, anything over this is dropped. A client normally sends a few messages for every snapshot it gets.
+/// A client can save up to a seconds worth of messages, so a short burst after a lag spike is fine.
+pub static MAX_CLIENT_MESSAGES_PER_SECOND: u32 = 240;
+
+/// How many violations a client can make within the violation window before it is kicked.
+pub static MAX_VIOLATIONS: u32 = 30;
+
+/// How long in milliseconds violations are counted for, the count starts again from 0 after this long.
+pub static VIOLATION_WINDOW_MS: u64 = 10_000;
+
+/// Violation is something a client sent that the server would not accept, the message it was in is dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// The mouse position was NaN or infinite, which can not be clamped to anything sensible.
+    MousePosNotFinite,
+    /// A vote for a number that is not on the ballot.
+    VoteOutOfRange(u8),
+    /// The client sent more than MAX_CLIENT_MESSAGES_PER_SECOND.
+    TooManyMessages,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MousePosNotFinite => write!(f, "mouse position is not a number"),
+            Violation::VoteOutOfRange(vote) => {
+                write!(f, "vote {} is not between 0 and {}", vote, MAX_VOTE_NUM)
+            }
+            Violation::TooManyMessages => write!(f, "sending messages too fast"),
+        }
+    }
+}
+
+/// Cleans up a clients input before the server uses it. Mouse positions outside the game are clamped to its edges,
+/// as the mouse can legitimately leave the window, but a mouse position that is not a number is rejected.
+pub fn sanitize_client_info(info: ClientInfo) -> Result<ClientInfo, Violation> {
+    let (x, y) = info.mouse_pos;
+    if !x.is_finite() || !y.is_finite() {
+        return Err(Violation::MousePosNotFinite);
+    }
+    Ok(ClientInfo {
+        mouse_pos: (x.clamp(0.0, GAME_WIDTH), y.clamp(0.0, GAME_HEIGHT)),
+        ..info
+    })
+}
+
+/// Checks a vote is for one of the options, 0 means not voting.
+pub fn check_vote(vote_number: u8) -> Result<u8, Violation> {
+    if vote_number as i32 > MAX_VOTE_NUM {
+        return Err(Violation::VoteOutOfRange(vote_number));
+    }
+    Ok(vote_number)
+}
+
+/// MessageLimiter rate limits a single clients messages, and counts the violations they make so the server knows when to kick them.
+#[derive(Debug, Clone)]
+pub struct MessageLimiter {
+    /// How many more messages the client can send right now, this refills over time up to a seconds worth.
+    allowance: f64,
+    last_message: Instant,
+    violations: u32,
+    window_start: Instant,
+}
+
+impl MessageLimiter {
+    pub fn new(now: Instant) -> Self {
+        MessageLimiter {
+            allowance: MAX_CLIENT_MESSAGES_PER_SECOND as f64,
+            last_message: now,
+            violations: 0,
+            window_start: now,
+        }
+    }
+
+    /// Call whenever a message arrives, returns an error if the client is over its rate and the message should be dropped.
+    pub fn allow_message(&mut self, now: Instant) -> Result<(), Violation> {
+        let rate = MAX_CLIENT_MESSAGES_PER_SECOND as f64;
+        let elapsed = now
+            .saturating_duration_since(self.last_message)
+            .as_secs_f64();
+        self.allowance = (self.allowance + elapsed * rate).min(rate);
+        self.last_message = now;
+        if self.allowance < 1.0 {
+            return Err(Violation::TooManyMessages);
+        }
+        self.allowance -= 1.0;
+        Ok(())
+    }
+
+    /// Counts a violation, returns true once the client has made too many and should be kicked.
+    pub fn violation(&mut self, now: Instant) -> bool {
+        if now.saturating_duration_since(self.window_start)
+            >= Duration::from_millis(VIOLATION_WINDOW_MS)
+        {
+            self.window_start = now;
+            self.violations = 0;
+        }
+        self.violations += 1;
+        self.violations >= MAX_VIOLATIONS
+    }
+
+    /// How many violations the client has made in the current window.
+    pub fn violations(&self) -> u32 {
+        self.violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clientinfo::ClientInfo;
+    use crate::validation::{
+        check_vote, sanitize_client_info, MessageLimiter, Violation,
+        MAX_CLIENT_MESSAGES_PER_SECOND, MAX_VIOLATIONS, VIOLATION_WINDOW_MS,
+    };
+    use crate::{GAME_HEIGHT, GAME_WIDTH};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn sanitize_client_info_test() {
+        let info = ClientInfo {
+            mouse_pos: (-50.0, GAME_HEIGHT * 2.0),
+            ..ClientInfo::default()
+        };
+        assert_eq!(
+            sanitize_client_info(info).unwrap().mouse_pos,
+            (0.0, GAME_HEIGHT)
+        );
+
+        let info = ClientInfo {
+            mouse_pos: (GAME_WIDTH / 2.0, 10.0),
+            ..ClientInfo::default()
+        };
+        assert_eq!(
+            sanitize_client_info(info).unwrap().mouse_pos,
+            (GAME_WIDTH / 2.0, 10.0)
+        );
+
+        let info = ClientInfo {
+            mouse_pos: (f32::NAN, 10.0),
+            ..ClientInfo::default()
+        };
+        assert_eq!(
+            sanitize_client_info(info).unwrap_err(),
+            Violation::MousePosNotFinite
+        );
+
+        assert_eq!(check_vote(0), Ok(0));
+        assert_eq!(check_vote(200), Err(Violation::VoteOutOfRange(200)));
+    }
+
+    #[test]
+    fn message_limiter_test() {
+        let start = Instant::now();
+        let mut limiter = MessageLimiter::new(start);
+        for _ in 0..MAX_CLIENT_MESSAGES_PER_SECOND {
+            assert_eq!(limiter.allow_message(start), Ok(()));
+        }
+        assert_eq!(
+            limiter.allow_message(start),
+            Err(Violation::TooManyMessages)
+        );
+        // waiting lets a few more through
+        let later = start + Duration::from_millis(100);
+        assert_eq!(limiter.allow_message(later), Ok(()));
+
+        for _ in 0..MAX_VIOLATIONS - 1 {
+            assert!(!limiter.violation(later));
+        }
+        assert!(limiter.violation(later));
+        // old violations are forgotten once the window is over
+        let next_window = later + Duration::from_millis(VIOLATION_WINDOW_MS);
+        assert!(!limiter.violation(next_window));
+        assert_eq!(limiter.violations(), 1);
+    }
+}
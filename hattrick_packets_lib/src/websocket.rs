@@ -0,0 +1,203 @@
+/// The port the server accepts websocket connections on, browsers can not open plain tcp sockets so web clients connect here instead.
+pub static WEBSOCKET_PORT: u16 = 8112;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{accept_websocket, connect_websocket, WEBSOCKET_HANDSHAKE_TIMEOUT_MS};
+
+#[cfg(target_arch = "wasm32")]
+pub use browser::{connect_websocket, BrowserWebSocket};
+
+/// Websockets everywhere but the browser are tungstenite over a tcp stream, the server always uses these.
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use crate::framing::MAX_FRAME_SIZE;
+    use crate::transport::{is_timeout, Channel, Transport, TransportError};
+    use std::net::TcpStream;
+    use std::time::Duration;
+    use tungstenite::handshake::HandshakeError;
+    use tungstenite::protocol::WebSocketConfig;
+    use tungstenite::{Message, WebSocket};
+
+    /// How long in milliseconds the websocket upgrade can take before the connection is given up on.
+    pub static WEBSOCKET_HANDSHAKE_TIMEOUT_MS: u64 = 5000;
+
+    /// Websocket messages are limited to the same size as tcp frames, so both transports can carry exactly the same packets.
+    fn config() -> WebSocketConfig {
+        WebSocketConfig {
+            max_message_size: Some(MAX_FRAME_SIZE),
+            max_frame_size: Some(MAX_FRAME_SIZE),
+            ..WebSocketConfig::default()
+        }
+    }
+
+    /// Turns a handshake error into a transport error, a handshake that is still waiting on the other side has timed out.
+    fn handshake_error<Role: tungstenite::handshake::HandshakeRole>(
+        e: HandshakeError<Role>,
+    ) -> TransportError {
+        match e {
+            HandshakeError::Interrupted(_) => TransportError::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "websocket handshake timed out",
+            )),
+            HandshakeError::Failure(e) => e.into(),
+        }
+    }
+
+    /// Upgrades a tcp connection that was just accepted into a websocket, this waits on the client to send its upgrade request.
+    pub fn accept_websocket(stream: TcpStream) -> Result<WebSocket<TcpStream>, TransportError> {
+        stream.set_read_timeout(Some(Duration::from_millis(WEBSOCKET_HANDSHAKE_TIMEOUT_MS)))?;
+        tungstenite::accept_with_config(stream, Some(config())).map_err(handshake_error)
+    }
+
+    /// Opens a websocket to the server at the given address, the address is a host and port like for tcp.
+    pub fn connect_websocket(address: &str) -> Result<WebSocket<TcpStream>, TransportError> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(Duration::from_millis(WEBSOCKET_HANDSHAKE_TIMEOUT_MS)))?;
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+        let _ = stream.set_nodelay(true);
+        let (socket, _) = tungstenite::client::client_with_config(
+            format!("ws://{}/", address),
+            stream,
+            Some(config()),
+        )
+        .map_err(handshake_error)?;
+        Ok(socket)
+    }
+
+    /// Websockets run over tcp, so they are reliable and ordered like it, and every channel is sent the same way.
+    /// Payloads are sent as binary messages, each message is a whole payload so no framing of our own is needed.
+    impl Transport for WebSocket<TcpStream> {
+        fn send_on(&mut self, _channel: Channel, payload: &[u8]) -> Result<(), TransportError> {
+            self.send(Message::Binary(payload.to_vec()))?;
+            Ok(())
+        }
+
+        fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+            // a zero duration read timeout is an error, so always wait at least a millisecond
+            self.get_ref()
+                .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+            loop {
+                match self.read() {
+                    Ok(Message::Binary(payload)) => return Ok(Some(payload)),
+                    Ok(Message::Text(text)) => return Ok(Some(text.into_bytes())),
+                    Ok(Message::Close(_)) => return Err(TransportError::Closed),
+                    Ok(_) => {} // pings are answered by tungstenite itself, there is nothing for us to do with them
+                    Err(tungstenite::Error::Io(e)) if is_timeout(&e) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        fn is_reliable(&self) -> bool {
+            true
+        }
+    }
+
+    impl From<tungstenite::Error> for TransportError {
+        fn from(e: tungstenite::Error) -> Self {
+            match e {
+                tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+                    TransportError::Closed
+                }
+                tungstenite::Error::Io(e) => TransportError::Io(e),
+                e => TransportError::WebSocket(Box::new(e)),
+            }
+        }
+    }
+}
+
+/// Browser builds open their websocket through javascript, with the quad_net plugin loaded next to the games wasm.
+/// The browser only hands over messages between frames, so nothing here ever waits.
+#[cfg(target_arch = "wasm32")]
+mod browser {
+    use crate::transport::{Channel, Transport, TransportError};
+    use quad_net::web_socket::WebSocket;
+    use std::time::Duration;
+
+    /// BrowserWebSocket is a websocket opened by the browser, payloads sent before it has finished opening are held until it has.
+    pub struct BrowserWebSocket {
+        socket: WebSocket,
+        waiting: Vec<Vec<u8>>,
+    }
+
+    /// Starts opening a websocket to the server at the given address, the address is a host and port like for tcp.
+    /// The browser opens it in the background, so this can not tell if the server is there, the handshake timing out is how we find out.
+    pub fn connect_websocket(address: &str) -> Result<BrowserWebSocket, TransportError> {
+        let socket = WebSocket::connect(format!("ws://{}/", address)).map_err(|e| {
+            TransportError::Io(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                format!("{:?}", e),
+            ))
+        })?;
+        Ok(BrowserWebSocket {
+            socket,
+            waiting: vec![],
+        })
+    }
+
+    impl BrowserWebSocket {
+        /// Sends everything that was held while the socket was opening, once it has opened.
+        fn send_waiting(&mut self) {
+            if self.socket.connected() {
+                for payload in self.waiting.drain(..) {
+                    self.socket.send_bytes(&payload);
+                }
+            }
+        }
+    }
+
+    /// Browser websockets are reliable and ordered like any other, and every channel is sent the same way.
+    /// The browser can not be blocked on, so recv_timeout only returns what has already arrived and the caller should try again next frame.
+    impl Transport for BrowserWebSocket {
+        fn send_on(&mut self, _channel: Channel, payload: &[u8]) -> Result<(), TransportError> {
+            self.waiting.push(payload.to_vec());
+            self.send_waiting();
+            Ok(())
+        }
+
+        fn recv_timeout(&mut self, _timeout: Duration) -> Result<Option<Vec<u8>>, TransportError> {
+            self.send_waiting();
+            Ok(self.socket.try_recv())
+        }
+
+        fn is_reliable(&self) -> bool {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::{Channel, Transport};
+    use crate::websocket::{accept_websocket, connect_websocket};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn websocket_round_trip_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = accept_websocket(stream).unwrap();
+            let payload = socket
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap()
+                .unwrap();
+            socket.send_on(Channel::Reliable, &payload).unwrap(); // echo it back
+        });
+
+        let mut client = connect_websocket(&address).unwrap();
+        assert!(client
+            .recv_timeout(Duration::from_millis(10))
+            .unwrap()
+            .is_none()); // nothing sent yet, so this times out rather than failing
+        client.send_on(Channel::Unreliable, b"hello").unwrap();
+        assert_eq!(
+            client.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Some(b"hello".to_vec())
+        );
+        server.join().unwrap();
+    }
+}
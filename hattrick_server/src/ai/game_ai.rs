@@ -6,6 +6,7 @@ use hattrick_packets_lib::gamestate::GameState;
 use hattrick_packets_lib::gametypes::GameType;
 use hattrick_packets_lib::keystate::KeyState;
 use hattrick_packets_lib::pong::PongClientState;
+use hattrick_packets_lib::rules::PongRules;
 use hattrick_packets_lib::tank::TankClientState;
 use hattrick_packets_lib::team::Team;
 use std::sync::{Arc, Mutex};
@@ -13,16 +14,19 @@ use std::thread;
 use std::thread::{sleep, JoinHandle};
 use std::time::{Duration, SystemTime};
 
-/// Delay in milliseconds for how long to wait between ai ticks.
-static AI_TICK_DELAY_MS: u64 = 8;
+/// Delay in milliseconds for how long to wait between ai ticks, unless the server is told otherwise.
+pub static AI_TICK_DELAY_MS: u64 = 8;
 
 /// This function takes in the game state arc mutex, the running state arc mutex, an ip address, and the team to connect to and joins the given ip game server.
 /// It will mutate the game state each frame by locking the mutex. To stop the connection thread, set the running state to false. This thread also concludes when connection is lost.
+/// The ai moves its paddle as fast as the pong rules let players, and waits tick_delay_ms between each move.
 pub fn spawn_ai_thread(
     game_state: GameStateRW,
     running: Arc<Mutex<bool>>,
     team_id: Team,
     name: String,
+    rules: PongRules,
+    tick_delay_ms: u64,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         let mut local_gs: GameState = { game_state.read().unwrap().clone() };
@@ -36,10 +40,15 @@ pub fn spawn_ai_thread(
         //  or how close they let the ball get before they stop moving.
         loop {
             // Do ai logic on one of these three lines
-            _previous_tcs = match &game_state.read().unwrap().client_list.get(&name) {
-                None => _previous_tcs.clone(),
-                Some(client) => client.tank_client_state.clone(),
-            };
+            // the server can move the ai to the other team when balancing, so its team is read back from the game state each tick
+            let (team_id, team_changed_at) =
+                match &game_state.read().unwrap().client_list.get(&name) {
+                    None => (team_id.clone(), None),
+                    Some(client) => {
+                        _previous_tcs = client.tank_client_state.clone();
+                        (client.team_id.clone(), client.team_changed_at)
+                    }
+                };
 
             let mut client_packet = ClientInfo {
                 time: SystemTime::now(),
@@ -51,7 +60,6 @@ pub fn spawn_ai_thread(
                     };
                     (x, 0.0)
                 },
-                team_id: team_id.clone(),
                 key_state: KeyState {
                     w_key: false,
                     a_key: false,
@@ -59,10 +67,17 @@ pub fn spawn_ai_thread(
                     d_key: false,
                     space_bar: false,
                 },
-                vote_number: 0,
+                input_sequence: 0,
+                view_time: None,
             };
-            let pcs: PongClientState =
-                get_pong_state_for_ai(&team_id, &local_gs, &mut client_packet, &previous_pcs); // use an ai function to make this pong client state
+            let pcs: PongClientState = get_pong_state_for_ai(
+                &team_id,
+                &local_gs,
+                &mut client_packet,
+                &previous_pcs,
+                &rules,
+                tick_delay_ms,
+            ); // use an ai function to make this pong client state
 
             // let tcs: TankClientState = _previous_tcs.clone(); // this doesnt work
 
@@ -76,8 +91,12 @@ pub fn spawn_ai_thread(
                 key_state: client_packet.key_state,
                 pong_client_state: pcs, // use modified pong client state
                 tank_client_state: _previous_tcs.clone(), // use modified tank client state
-                team_id: client_packet.team_id,
-                vote_number: client_packet.vote_number,
+                team_id,
+                vote_number: 0, // ai never votes
+                last_input_sequence: 0,
+                rtt_ms: 0,
+                lag_compensation: 0.0,
+                team_changed_at,
             };
 
             {
@@ -86,7 +105,7 @@ pub fn spawn_ai_thread(
                 lock.client_list.insert(name.clone(), client_state);
             } // update the servers game state from this ai
 
-            sleep(Duration::from_millis(AI_TICK_DELAY_MS)); // ai tick rate, probably can be pretty slow
+            sleep(Duration::from_millis(tick_delay_ms)); // ai tick rate, probably can be pretty slow
 
             if !(*running.lock().unwrap()) {
                 // stop ai if our running lock is off.
@@ -2,21 +2,25 @@ use hattrick_packets_lib::clientinfo::ClientInfo;
 use hattrick_packets_lib::gamestate::GameState;
 use hattrick_packets_lib::gametypes::GameType;
 use hattrick_packets_lib::pong::{
-    get_pong_paddle_width, PongClientState, BLUE_TEAM_PADDLE_Y, PADDLE_MOVE_SPEED,
-    PONG_PADDLE_WIDTH, RED_TEAM_PADDLE_Y,
+    get_pong_paddle_width, PongClientState, BLUE_TEAM_PADDLE_Y, PONG_PADDLE_WIDTH,
+    RED_TEAM_PADDLE_Y,
 };
+use hattrick_packets_lib::rules::PongRules;
 use hattrick_packets_lib::team::Team;
 use hattrick_packets_lib::{Magnitude, GAME_WIDTH};
 
 /// Multiplicative modifier applied to the ball speed to determine how close the ball gets to the paddle before the AI stops reacting.
 static REACTION_DISTANCE_MODIFIER: f32 = 1.0;
-static PADDLE_MOVE_SPEED_MODIFIER: f32 = 5.0;
+/// Multiplier on the players paddle speed for how fast the AI can move its paddle.
+static PADDLE_MOVE_SPEED_MODIFIER: f32 = 1.0;
 
 pub fn get_pong_state_for_ai(
     team_id: &Team,
     local_gs: &GameState,
     client_packet: &mut ClientInfo,
     previous_pcs: &PongClientState,
+    rules: &PongRules,
+    tick_delay_ms: u64,
 ) -> PongClientState {
     // paddle y values based on team
     // BlueTeam => 10.0,
@@ -48,6 +52,10 @@ pub fn get_pong_state_for_ai(
             _ => -1.0, // only get a ball height if we are playing pong
         }; // ball y value
 
+        // the ai moves its paddle once per ai tick
+        let paddle_step =
+            rules.paddle_move_speed * PADDLE_MOVE_SPEED_MODIFIER * (tick_delay_ms as f32 / 1000.0);
+
         let new_paddle_x = {
             if (ball_height - paddle_y).abs() < reaction_distance
                 || ball_height > RED_TEAM_PADDLE_Y
@@ -60,10 +68,10 @@ pub fn get_pong_state_for_ai(
                 // client_packet.mouse_pos.0 - (paddle_width / 2.0) // center paddle to ball
                 if (client_packet.mouse_pos.0 - (paddle_width / 2.0)) < previous_client_x {
                     // mouse is to the left of the paddle at the moment
-                    previous_client_x - (PADDLE_MOVE_SPEED * PADDLE_MOVE_SPEED_MODIFIER)
+                    previous_client_x - paddle_step
                 } else {
                     // mouse is to the right of the paddle at the moment
-                    previous_client_x + (PADDLE_MOVE_SPEED * PADDLE_MOVE_SPEED_MODIFIER)
+                    previous_client_x + paddle_step
                 }
             }
         };
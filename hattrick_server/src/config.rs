@@ -0,0 +1,256 @@
+use crate::ai::game_ai::AI_TICK_DELAY_MS;
+use crate::{
+    Server, ServerBuilder, DEFAULT_AI_PLAYERS, DEFAULT_SERVER_NAME,
+    DEFAULT_SHUTDOWN_COUNTDOWN_SECS, MAX_PLAYERS, SERVER_PORT, SERVER_TICK_RATE,
+};
+use hattrick_packets_lib::compression::{
+    CompressionConfig, DEFAULT_COMPRESSION_LEVEL, DEFAULT_COMPRESSION_MIN_SIZE,
+};
+use hattrick_packets_lib::conditioner::NetworkConditions;
+use hattrick_packets_lib::connection::{HEARTBEAT_INTERVAL_MS, MAX_BAD_PACKETS};
+use hattrick_packets_lib::gamestate::SNAPSHOT_RATE;
+use hattrick_packets_lib::rules::GameRules;
+use hattrick_packets_lib::transport::IDLE_TIMEOUT_MS;
+use hattrick_packets_lib::websocket::WEBSOCKET_PORT;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+/// Highest tick rate the server can be configured with, past this the game thread spends all its time stepping.
+pub static MAX_TICK_RATE: u32 = 1000;
+
+/// ServerConfig is everything the dedicated server can be tuned with, read from a toml file so a deployment can be changed without recompiling.
+/// Anything left out of the file keeps its default, which is the same as a server started with Server::builder(). See server.toml for an example with every setting.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The name shown in the clients server list.
+    pub name: String,
+    /// The address to accept clients on, 0.0.0.0 for every address the machine has.
+    pub bind_address: IpAddr,
+    /// The port clients connect to over tcp, and over udp if it is turned on.
+    pub port: u16,
+    pub udp: bool,
+    pub websocket: bool,
+    pub websocket_port: u16,
+    /// Answer discovery queries from clients on the local network.
+    pub discovery: bool,
+    /// Most players that can be connected at once, the ai do not count.
+    pub max_players: u32,
+    /// How many ai players each room gets.
+    pub ai_players: u32,
+    pub auto_balance: bool,
+    /// How many times a second each rooms game is stepped.
+    pub tick_rate: u32,
+    /// How many times a second each client is sent a snapshot, no more than the tick rate.
+    pub snapshot_rate: u32,
+    /// How long in milliseconds each ai waits between moves.
+    pub ai_tick_delay_ms: u64,
+    /// How many seconds players are warned for before the server shuts down after being asked to stop.
+    pub shutdown_countdown_secs: u64,
+    /// How long in milliseconds a client can go without sending anything before it is dropped, longer than the heartbeat interval.
+    pub idle_timeout_ms: u64,
+    /// How many unreadable packets in a row a client can send before it is dropped.
+    pub max_bad_packets: u32,
+    pub compression: CompressionSection,
+    pub network: NetworkSection,
+    pub rules: GameRules,
+}
+
+/// CompressionSection is the [compression] table of the config file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionSection {
+    /// Clients still have to ask for compression, turning this off only lets the server turn them down.
+    pub enabled: bool,
+    /// From 0 for fastest to 9 for smallest.
+    pub level: u32,
+    /// Payloads smaller than this many bytes are not compressed.
+    pub min_size: usize,
+}
+
+/// NetworkSection is the [network] table of the config file, it makes the connection to every client worse on purpose for testing.
+/// It uses the same units as the --net command line arguments, everything at 0 leaves the network conditioner off.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSection {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    /// Percentage of payloads lost, from 0 to 100.
+    pub loss: f64,
+    /// Percentage of payloads sent twice, from 0 to 100.
+    pub duplicate: f64,
+    /// Most kilobits sent each second, 0 for no limit.
+    pub bandwidth_kbps: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            name: DEFAULT_SERVER_NAME.to_string(),
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: SERVER_PORT,
+            udp: false,
+            websocket: true,
+            websocket_port: WEBSOCKET_PORT,
+            discovery: true,
+            max_players: MAX_PLAYERS,
+            ai_players: DEFAULT_AI_PLAYERS,
+            auto_balance: false,
+            tick_rate: SERVER_TICK_RATE,
+            snapshot_rate: SNAPSHOT_RATE,
+            ai_tick_delay_ms: AI_TICK_DELAY_MS,
+            shutdown_countdown_secs: DEFAULT_SHUTDOWN_COUNTDOWN_SECS,
+            idle_timeout_ms: IDLE_TIMEOUT_MS,
+            max_bad_packets: MAX_BAD_PACKETS,
+            compression: CompressionSection::default(),
+            network: NetworkSection::default(),
+            rules: GameRules::default(),
+        }
+    }
+}
+
+impl Default for CompressionSection {
+    fn default() -> Self {
+        CompressionSection {
+            enabled: true,
+            level: DEFAULT_COMPRESSION_LEVEL,
+            min_size: DEFAULT_COMPRESSION_MIN_SIZE,
+        }
+    }
+}
+
+impl NetworkSection {
+    /// The conditions to run the network conditioner with, None if they would not change anything.
+    pub fn conditions(&self) -> Option<NetworkConditions> {
+        let conditions = NetworkConditions {
+            latency_ms: self.latency_ms,
+            jitter_ms: self.jitter_ms,
+            loss: self.loss / 100.0,
+            duplicate: self.duplicate / 100.0,
+            bandwidth_kbps: Some(self.bandwidth_kbps).filter(|kbps| *kbps > 0),
+        };
+        if conditions == NetworkConditions::default() {
+            None
+        } else {
+            Some(conditions)
+        }
+    }
+}
+
+/// ConfigError is why the server could not use its config, the server prints it and refuses to start.
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// The config was read, but a value in it would break the server or the game.
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+            ConfigError::Invalid(e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads the config from a toml file, it still has to be validated before it is used.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Checks every value makes sense, returning what is wrong with the first one that does not.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |e: String| Err(ConfigError::Invalid(e));
+        if self.name.trim().is_empty() {
+            return invalid("name can not be empty".to_string());
+        }
+        if self.websocket && self.port != 0 && self.websocket_port == self.port {
+            return invalid(format!(
+                "websocket_port can not be the same as port ({})",
+                self.port
+            ));
+        } // both are tcp, so they can not share a port
+        if self.max_players == 0 {
+            return invalid("max_players must be at least 1".to_string());
+        }
+        if self.tick_rate == 0 || self.tick_rate > MAX_TICK_RATE {
+            return invalid(format!(
+                "tick_rate must be between 1 and {}, not {}",
+                MAX_TICK_RATE, self.tick_rate
+            ));
+        }
+        if self.snapshot_rate == 0 || self.snapshot_rate > self.tick_rate {
+            return invalid(format!(
+                "snapshot_rate must be between 1 and the tick_rate ({}), not {}",
+                self.tick_rate, self.snapshot_rate
+            ));
+        }
+        if self.ai_tick_delay_ms == 0 {
+            return invalid("ai_tick_delay_ms must be at least 1".to_string());
+        }
+        if self.idle_timeout_ms <= HEARTBEAT_INTERVAL_MS {
+            return invalid(format!(
+                "idle_timeout_ms must be longer than the heartbeat interval ({}ms), not {}",
+                HEARTBEAT_INTERVAL_MS, self.idle_timeout_ms
+            ));
+        }
+        if self.max_bad_packets == 0 {
+            return invalid("max_bad_packets must be at least 1".to_string());
+        }
+        if self.compression.level > 9 {
+            return invalid(format!(
+                "compression.level must be between 0 and 9, not {}",
+                self.compression.level
+            ));
+        }
+        for (name, percent) in [
+            ("network.loss", self.network.loss),
+            ("network.duplicate", self.network.duplicate),
+        ] {
+            if !(0.0..=100.0).contains(&percent) {
+                return invalid(format!(
+                    "{} must be a percentage between 0 and 100, not {}",
+                    name, percent
+                ));
+            }
+        }
+        self.rules.validate().map_err(ConfigError::Invalid)
+    }
+
+    /// A server builder with everything from the config filled in.
+    pub fn builder(&self) -> ServerBuilder {
+        Server::builder()
+            .name(self.name.clone())
+            .bind_address(self.bind_address)
+            .port(self.port)
+            .websocket_port(Some(self.websocket_port).filter(|_| self.websocket))
+            .udp(self.udp)
+            .discovery(self.discovery)
+            .max_players(self.max_players)
+            .ai_players(self.ai_players)
+            .auto_balance(self.auto_balance)
+            .tick_rate(self.tick_rate)
+            .snapshot_rate(self.snapshot_rate)
+            .ai_tick_delay_ms(self.ai_tick_delay_ms)
+            .rules(self.rules)
+            .idle_timeout_ms(self.idle_timeout_ms)
+            .max_bad_packets(self.max_bad_packets)
+            .compression(if self.compression.enabled {
+                Some(CompressionConfig {
+                    level: self.compression.level,
+                    min_size: self.compression.min_size,
+                })
+            } else {
+                None
+            })
+            .network_conditions(self.network.conditions())
+    }
+}
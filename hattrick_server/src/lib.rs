@@ -0,0 +1,1554 @@
+use crate::ai::game_ai::AI_TICK_DELAY_MS;
+use crate::room::{RoomHandle, Rooms};
+use crate::session::{SessionTicket, Sessions};
+use crate::view::client_view;
+use hattrick_packets_lib::clientinfo::ClientInfo;
+use hattrick_packets_lib::clientstate::ClientState;
+use hattrick_packets_lib::codec::Codec;
+use hattrick_packets_lib::compression::{CompressionConfig, CompressionStats, Compressor};
+use hattrick_packets_lib::conditioner::{ConditionedTransport, NetworkConditions};
+use hattrick_packets_lib::connection::{
+    ConnectionMonitor, ConnectionState, DisconnectReason, HEARTBEAT_INTERVAL_MS, MAX_BAD_PACKETS,
+};
+use hattrick_packets_lib::delta::SnapshotEncoder;
+use hattrick_packets_lib::discovery::{DiscoveryResponder, ServerAnnouncement, DISCOVERY_PORT};
+use hattrick_packets_lib::framing::FramedStream;
+use hattrick_packets_lib::gamestate::{GameState, SNAPSHOT_RATE};
+use hattrick_packets_lib::gametypes::GameType::{PONG, TANK};
+use hattrick_packets_lib::handshake::{
+    respond_to_hello, Capability, ClientHello, HandshakeResponse, RejectReason, ServerWelcome,
+    HANDSHAKE_CODEC, PROTOCOL_VERSION,
+};
+use hattrick_packets_lib::keystate::KeyState;
+use hattrick_packets_lib::lag_compensation::{lag_compensation_for, TankHistory};
+use hattrick_packets_lib::message::{ClientMessage, GameEvent, ServerMessage, MAX_CHAT_LENGTH};
+use hattrick_packets_lib::ping::{PingTracker, Pong};
+use hattrick_packets_lib::pong::get_pong_paddle_width;
+use hattrick_packets_lib::room::{check_room_name, RoomRejectReason};
+use hattrick_packets_lib::rules::GameRules;
+use hattrick_packets_lib::team::Team::BlueTeam;
+use hattrick_packets_lib::team::{balance_teams, check_team_request, smaller_team, Team};
+use hattrick_packets_lib::transport::{is_timeout, Channel, Transport, IDLE_TIMEOUT_MS};
+use hattrick_packets_lib::udp::UdpListener;
+use hattrick_packets_lib::validation::{
+    check_vote, sanitize_client_info, MessageLimiter, Violation, VIOLATION_WINDOW_MS,
+};
+use hattrick_packets_lib::websocket::{accept_websocket, WEBSOCKET_PORT};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::thread::{sleep, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+mod ai;
+pub mod config;
+mod room;
+mod session;
+mod view;
+
+/// How many times a second the game is stepped unless the server is told otherwise, every tick steps the game by the same amount of time.
+pub static SERVER_TICK_RATE: u32 = 60;
+
+/// Most ticks the game thread will run back to back to catch up after falling behind.
+static MAX_CATCH_UP_TICKS: u32 = 5;
+
+/// How often in milliseconds the compression stats are logged.
+static COMPRESSION_STATS_INTERVAL_MS: u64 = 60_000;
+
+/// How often in milliseconds threads that wait on something else check if the server is stopping.
+static STOP_POLL_INTERVAL_MS: u64 = 50;
+
+/// The port clients connect to over tcp and udp, unless the server is told otherwise.
+pub static SERVER_PORT: u16 = 8111;
+
+/// Most players that can be connected at once, the ai do not count. New players are turned away once this many are connected.
+pub static MAX_PLAYERS: u32 = 16;
+
+/// The name shown in the clients server list, unless the server is given its own.
+pub static DEFAULT_SERVER_NAME: &str = "Hattrick server";
+
+/// How many ai players are added to each room, unless the server is told otherwise.
+pub static DEFAULT_AI_PLAYERS: u32 = 1;
+
+/// How many seconds players are warned for before the server shuts down, unless the server is told otherwise.
+/// This is kept under the 10 seconds docker waits after asking a container to stop, before it kills it.
+pub static DEFAULT_SHUTDOWN_COUNTDOWN_SECS: u64 = 5;
+
+type GameStateRW = Arc<RwLock<GameState>>;
+
+/// Messages waiting to be sent to each client on their next turn, keyed by client uuid.
+type OutboxRW = Arc<Mutex<HashMap<String, Vec<ServerMessage>>>>;
+
+/// Every player session, including players who dropped and may reconnect. Always lock this before the rooms or a game state if both are needed.
+type SessionsRW = Arc<Mutex<Sessions>>;
+
+/// Every room open on the server, each with its own game state and outbox.
+type RoomsRW = Arc<Mutex<Rooms>>;
+
+/// Everything kept about a single clients connection from one turn to the next.
+struct ClientConnection {
+    /// The room the client is playing in, this changes when they join another room.
+    room: RoomHandle,
+    /// When we last heard from the client, and whether they should be dropped.
+    monitor: ConnectionMonitor,
+    /// Snapshots are sent as changes from the last one the client acked.
+    snapshots: SnapshotEncoder,
+    /// Pings sent to the client, to measure their round trip time.
+    pings: PingTracker,
+    /// How fast the client is sending messages, and how many bad ones it has sent.
+    limiter: MessageLimiter,
+    /// Compresses what we send the client, None if the client did not ask for compression or it is turned off.
+    compressor: Option<Compressor>,
+}
+
+/// GameSettings is how every rooms game is run, each room gets a copy when it is opened.
+#[derive(Debug, Clone, Copy)]
+struct GameSettings {
+    /// New players are put on the smaller team, team changes cant make the teams uneven, and teams are evened out before each round.
+    auto_balance: bool,
+    /// How many ai players each room gets, they alternate between the blue and red team.
+    ai_players: u32,
+    /// How many times a second each rooms game is stepped.
+    tick_rate: u32,
+    /// How many times a second each client is sent a snapshot, and so how many inputs a second each client sends.
+    snapshot_rate: u32,
+    /// How long in milliseconds each ai waits between moves.
+    ai_tick_delay_ms: u64,
+    rules: GameRules,
+}
+
+/// ServerSettings is how the server was told to run, every client connection gets a copy.
+#[derive(Debug, Clone, Copy)]
+struct ServerSettings {
+    game: GameSettings,
+    /// How to compress what is sent to clients that ask for it, None if compression is turned off.
+    compression: Option<CompressionConfig>,
+    /// Makes what the server sends to every client worse on purpose, for testing how the game plays on bad connections. None unless asked for.
+    network_conditions: Option<NetworkConditions>,
+    /// Most players that can be connected at once, the ai do not count.
+    max_players: u32,
+    /// The port clients connect to over tcp and udp, this is what discovery tells clients to join on.
+    port: u16,
+    /// How long in milliseconds a client can go without sending anything before it is dropped.
+    idle_timeout_ms: u64,
+    /// How many unreadable packets in a row a client can send before it is dropped.
+    max_bad_packets: u32,
+}
+
+/// Compression stats for every client since they were last logged.
+type CompressionStatsRW = Arc<Mutex<CompressionStats>>;
+
+/// ServerShared is everything the servers threads share, each field is locked separately.
+#[derive(Clone)]
+struct ServerShared {
+    rooms: RoomsRW,
+    sessions: SessionsRW,
+    compression_stats: CompressionStatsRW,
+    /// Set to false to stop the server, every thread checks this and winds down on its own.
+    running: Arc<Mutex<bool>>,
+    /// Set to false once the server starts shutting down, new players are turned away from then on while the players already in carry on.
+    accepting: Arc<Mutex<bool>>,
+}
+
+impl ServerShared {
+    fn is_running(&self) -> bool {
+        *self.running.lock().unwrap()
+    }
+
+    fn is_accepting(&self) -> bool {
+        *self.accepting.lock().unwrap()
+    }
+}
+
+/// ServerBuilder sets up a server before it is started, everything has a default so a server can be started with just Server::builder().start().
+/// The dedicated server binary fills this in from its command line, and the client fills it in when the player hosts a game.
+#[derive(Debug, Clone)]
+pub struct ServerBuilder {
+    name: String,
+    bind_address: IpAddr,
+    port: u16,
+    websocket_port: Option<u16>,
+    udp: bool,
+    discovery: bool,
+    max_players: u32,
+    ai_players: u32,
+    auto_balance: bool,
+    tick_rate: u32,
+    snapshot_rate: u32,
+    ai_tick_delay_ms: u64,
+    rules: GameRules,
+    compression: Option<CompressionConfig>,
+    network_conditions: Option<NetworkConditions>,
+    idle_timeout_ms: u64,
+    max_bad_packets: u32,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        ServerBuilder {
+            name: DEFAULT_SERVER_NAME.to_string(),
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: SERVER_PORT,
+            websocket_port: Some(WEBSOCKET_PORT),
+            udp: false,
+            discovery: true,
+            max_players: MAX_PLAYERS,
+            ai_players: DEFAULT_AI_PLAYERS,
+            auto_balance: false,
+            tick_rate: SERVER_TICK_RATE,
+            snapshot_rate: SNAPSHOT_RATE,
+            ai_tick_delay_ms: AI_TICK_DELAY_MS,
+            rules: GameRules::default(),
+            compression: Some(CompressionConfig::default()),
+            network_conditions: None,
+            idle_timeout_ms: IDLE_TIMEOUT_MS,
+            max_bad_packets: MAX_BAD_PACKETS,
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// The name shown in the clients server list.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The address to accept clients on, every address the machine has by default.
+    pub fn bind_address(mut self, bind_address: IpAddr) -> Self {
+        self.bind_address = bind_address;
+        self
+    }
+
+    /// The port clients connect to over tcp, and over udp if it is turned on. 0 picks any free port.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// The port websocket clients connect to, None to not accept websockets at all. 0 picks any free port.
+    pub fn websocket_port(mut self, port: Option<u16>) -> Self {
+        self.websocket_port = port;
+        self
+    }
+
+    /// Accept clients over udp as well as tcp, on the same port number. Off by default.
+    pub fn udp(mut self, udp: bool) -> Self {
+        self.udp = udp;
+        self
+    }
+
+    /// Answer discovery queries so the server shows up in the server list of clients on the local network. On by default.
+    pub fn discovery(mut self, discovery: bool) -> Self {
+        self.discovery = discovery;
+        self
+    }
+
+    /// Most players that can be connected at once, the ai do not count.
+    pub fn max_players(mut self, max_players: u32) -> Self {
+        self.max_players = max_players;
+        self
+    }
+
+    /// How many ai players to add to each room, they alternate between the blue and red team.
+    pub fn ai_players(mut self, ai_players: u32) -> Self {
+        self.ai_players = ai_players;
+        self
+    }
+
+    /// Put new players on the smaller team, keep team changes from making the teams uneven, and even out the teams before each round.
+    pub fn auto_balance(mut self, auto_balance: bool) -> Self {
+        self.auto_balance = auto_balance;
+        self
+    }
+
+    /// How many times a second each rooms game is stepped.
+    pub fn tick_rate(mut self, tick_rate: u32) -> Self {
+        self.tick_rate = tick_rate;
+        self
+    }
+
+    /// How many times a second each client is sent a snapshot, it can not be more than the tick rate. Clients are told it as they join and send one input per snapshot.
+    pub fn snapshot_rate(mut self, snapshot_rate: u32) -> Self {
+        self.snapshot_rate = snapshot_rate;
+        self
+    }
+
+    /// How long in milliseconds each ai waits between moves, lower makes the ai react faster.
+    pub fn ai_tick_delay_ms(mut self, ai_tick_delay_ms: u64) -> Self {
+        self.ai_tick_delay_ms = ai_tick_delay_ms;
+        self
+    }
+
+    /// The rules every game mode is played with, they are sent to each client as they join.
+    pub fn rules(mut self, rules: GameRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// How to compress what is sent to clients that ask for it, None to turn compression down for everyone.
+    pub fn compression(mut self, compression: Option<CompressionConfig>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Makes what the server sends worse on purpose, only for testing.
+    pub fn network_conditions(mut self, network_conditions: Option<NetworkConditions>) -> Self {
+        self.network_conditions = network_conditions;
+        self
+    }
+
+    /// How long in milliseconds a client can go without sending anything before it is dropped, it has to be longer than the heartbeat interval.
+    pub fn idle_timeout_ms(mut self, idle_timeout_ms: u64) -> Self {
+        self.idle_timeout_ms = idle_timeout_ms;
+        self
+    }
+
+    /// How many unreadable packets in a row a client can send before it is dropped, a readable packet starts the count again.
+    pub fn max_bad_packets(mut self, max_bad_packets: u32) -> Self {
+        self.max_bad_packets = max_bad_packets;
+        self
+    }
+
+    /// Binds every port and starts the servers threads, returning the running server.
+    /// Failing to bind a port the server needs is an error, the discovery port is the exception as the server works fine without being discoverable.
+    /// Rules that would break the game, a tick rate of 0, or a snapshot rate of 0 or above the tick rate, are an invalid input error.
+    pub fn start(self) -> std::io::Result<Server> {
+        self.rules
+            .validate()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        if self.tick_rate == 0 || self.ai_tick_delay_ms == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "tick rate and ai tick delay must be more than zero",
+            ));
+        }
+        if self.snapshot_rate == 0 || self.snapshot_rate > self.tick_rate {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "snapshot rate must be more than zero and no more than the tick rate",
+            ));
+        } // the game does not change between ticks, so more snapshots than ticks would be sending the same thing twice
+        if self.idle_timeout_ms <= HEARTBEAT_INTERVAL_MS || self.max_bad_packets == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "idle timeout must be longer than the heartbeat interval, and max bad packets must be more than zero",
+            ));
+        } // a quiet client only sends a heartbeat each interval, a shorter timeout would drop them between heartbeats
+
+        let tcp_listener = TcpListener::bind((self.bind_address, self.port))?;
+        tcp_listener.set_nonblocking(true)?;
+        let local_addr = tcp_listener.local_addr()?;
+        let udp_listener = if self.udp {
+            let listener = UdpListener::bind((self.bind_address, local_addr.port()))?;
+            listener.set_read_timeout(Some(Duration::from_millis(STOP_POLL_INTERVAL_MS)))?;
+            Some(listener)
+        } else {
+            None
+        };
+        let websocket_listener = match self.websocket_port {
+            Some(port) => {
+                let listener = TcpListener::bind((self.bind_address, port))?;
+                listener.set_nonblocking(true)?;
+                Some(listener)
+            }
+            None => None,
+        };
+        let websocket_addr = match &websocket_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+        let discovery_responder = if self.discovery {
+            match DiscoveryResponder::bind(DISCOVERY_PORT).and_then(|responder| {
+                responder.set_read_timeout(Some(Duration::from_millis(STOP_POLL_INTERVAL_MS)))?;
+                Ok(responder)
+            }) {
+                Ok(responder) => Some(responder),
+                Err(e) => {
+                    println!("not answering discovery queries: {}", e);
+                    None
+                }
+            } // say another server on the same machine already has the discovery port
+        } else {
+            None
+        };
+
+        let settings = ServerSettings {
+            game: GameSettings {
+                auto_balance: self.auto_balance,
+                ai_players: self.ai_players,
+                tick_rate: self.tick_rate,
+                snapshot_rate: self.snapshot_rate,
+                ai_tick_delay_ms: self.ai_tick_delay_ms,
+                rules: self.rules,
+            },
+            compression: self.compression,
+            network_conditions: self.network_conditions,
+            max_players: self.max_players,
+            port: local_addr.port(),
+            idle_timeout_ms: self.idle_timeout_ms,
+            max_bad_packets: self.max_bad_packets,
+        };
+        if let Some(conditions) = settings.network_conditions {
+            println!("network conditioner on: {}", conditions);
+        }
+        let shared = ServerShared {
+            rooms: Arc::new(Mutex::new(Rooms::new(settings.game))),
+            sessions: Arc::new(Mutex::new(Sessions::default())),
+            compression_stats: Arc::new(Mutex::new(CompressionStats::default())),
+            running: Arc::new(Mutex::new(true)),
+            accepting: Arc::new(Mutex::new(true)),
+        };
+        println!("accepting tcp clients on port {}", local_addr.port());
+        let mut threads = vec![spawn_tcp_listener_thread(
+            tcp_listener,
+            shared.clone(),
+            settings,
+        )];
+
+        // udp is opt in, tcp is always available.
+        if let Some(listener) = udp_listener {
+            threads.push(spawn_udp_listener_thread(
+                listener,
+                shared.clone(),
+                settings,
+            ));
+        }
+
+        // websockets are for browser clients, they are on their own port.
+        if let Some(listener) = websocket_listener {
+            threads.push(spawn_websocket_listener_thread(
+                listener,
+                shared.clone(),
+                settings,
+            ));
+        }
+
+        if settings.compression.is_some() {
+            threads.push(spawn_compression_stats_thread(shared.clone()));
+        }
+
+        if let Some(responder) = discovery_responder {
+            threads.push(spawn_discovery_thread(
+                responder,
+                shared.clone(),
+                self.name,
+                settings,
+            ));
+        }
+
+        threads.push(spawn_room_cleanup_thread(shared.clone()));
+
+        Ok(Server {
+            shared,
+            local_addr,
+            websocket_addr,
+            threads,
+        })
+    }
+}
+
+/// Server is a running game server, its threads keep running until it is stopped.
+pub struct Server {
+    shared: ServerShared,
+    local_addr: SocketAddr,
+    websocket_addr: Option<SocketAddr>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// The address the server accepts tcp clients on, the port is the real one even if the server was started on port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The address the server accepts websocket clients on, None if websockets are turned off.
+    pub fn websocket_addr(&self) -> Option<SocketAddr> {
+        self.websocket_addr
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.shared.is_running()
+    }
+
+    /// Starts shutting the server down, every player is told the server closes once the countdown is over and no new players are let in.
+    /// The tcp and websocket ports are closed straight away, udp keeps running for the players already connected over it.
+    /// Nothing is actually stopped until stop is called, which should be done once the countdown is over.
+    pub fn announce_shutdown(&self, countdown: Duration) {
+        *self.shared.accepting.lock().unwrap() = false;
+        let seconds = countdown.as_secs_f64().ceil() as u32;
+        println!("shutting down in {} seconds", seconds);
+        let rooms = self.shared.rooms.lock().unwrap().handles();
+        for room in rooms {
+            broadcast_message(&room.outbox, ServerMessage::ShuttingDown { seconds });
+        }
+    }
+
+    /// Stops the server, every client is told the server is closing. Returns once every thread has finished.
+    pub fn stop(self) {
+        *self.shared.accepting.lock().unwrap() = false;
+        *self.shared.running.lock().unwrap() = false;
+        self.wait();
+    }
+
+    /// Waits for the server to stop, which a server only does when stop is called.
+    /// Every client is disconnected and every thread, including each rooms game and ai, has finished by the time this returns.
+    pub fn wait(self) {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+
+        let rooms = self.shared.rooms.lock().unwrap().take_all();
+        for room in rooms {
+            room.close();
+        } // stop every rooms game and ai once the clients are all gone
+
+        let stats = std::mem::take(&mut *self.shared.compression_stats.lock().unwrap());
+        if stats.payloads > 0 {
+            println!("compression since last logged: {}", stats);
+        } // the clients merged their last stats as they disconnected
+        println!("server stopped");
+    }
+}
+
+/// Spawns a thread that accepts clients over tcp, each client is handled on its own thread.
+/// The listener does not block, so the thread can notice the server stopping between clients.
+fn spawn_tcp_listener_thread(
+    listener: TcpListener,
+    shared: ServerShared,
+    settings: ServerSettings,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut client_threads: Vec<JoinHandle<()>> = vec![];
+        while shared.is_accepting() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if is_timeout(&e) => {
+                    sleep(Duration::from_millis(STOP_POLL_INTERVAL_MS));
+                    continue;
+                }
+                Err(e) => {
+                    println!("tcp listener error: {}", e);
+                    continue;
+                }
+            };
+            client_threads.retain(|t| !t.is_finished());
+            {
+                let _ = stream.set_nonblocking(false);
+                stream
+                    .set_write_timeout(Option::from(Duration::from_secs(5)))
+                    .unwrap();
+                let _ = stream.set_nodelay(true);
+            } // set the write timeout for the client, read timeouts are set on each read. Nagle is turned off as small messages would otherwise be held back and miss the snapshot rate.
+
+            client_threads.push(handle_client(
+                Box::new(FramedStream::new(stream)),
+                shared.clone(),
+                settings,
+            ));
+            println!("Client count: {}", client_threads.len());
+        }
+        drop(listener); // new connections are refused from here on
+        for client in client_threads {
+            let _ = client.join();
+        } // clients see the server stopping on their own, and say goodbye before their thread ends
+    })
+}
+
+/// Spawns a thread that answers discovery queries from clients looking for servers on the local network.
+fn spawn_discovery_thread(
+    responder: DiscoveryResponder,
+    shared: ServerShared,
+    server_name: String,
+    settings: ServerSettings,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        println!("answering discovery queries as {:?}", server_name);
+        while shared.is_accepting() {
+            let result = responder.answer_next(|| {
+                let rooms = shared.rooms.lock().unwrap();
+                ServerAnnouncement {
+                    name: server_name.clone(),
+                    game_mode: rooms.lobby().info().game_mode,
+                    player_count: rooms.player_count(),
+                    capacity: settings.max_players,
+                    port: settings.port,
+                    protocol_version: PROTOCOL_VERSION,
+                }
+            }); // the game mode shown is the lobbies, as that is where new players land
+            match result {
+                Err(e) if is_timeout(&e) => {} // no one asked, check if we are still running and wait again
+                Err(e) => println!("discovery error: {}", e),
+                Ok(()) => {}
+            }
+        }
+    })
+}
+
+/// Spawns a thread that logs how well compression is working every so often, along with how many players there were,
+/// so whoever runs the server can tell if compression is worth it for the number of players they get.
+fn spawn_compression_stats_thread(shared: ServerShared) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_logged = Instant::now();
+        while shared.is_running() {
+            sleep(Duration::from_millis(STOP_POLL_INTERVAL_MS));
+            if last_logged.elapsed() < Duration::from_millis(COMPRESSION_STATS_INTERVAL_MS) {
+                continue;
+            }
+            last_logged = Instant::now();
+            let stats = std::mem::take(&mut *shared.compression_stats.lock().unwrap());
+            if stats.payloads > 0 {
+                println!(
+                    "compression with {} players: {}",
+                    shared.rooms.lock().unwrap().player_count(),
+                    stats
+                );
+            }
+        }
+    })
+}
+
+/// Spawns a thread that accepts clients over websockets on the websocket port.
+/// Each connection is upgraded to a websocket on its own thread, so a client that is slow to send its upgrade request does not hold up anyone else.
+fn spawn_websocket_listener_thread(
+    listener: TcpListener,
+    shared: ServerShared,
+    settings: ServerSettings,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        println!(
+            "accepting websocket clients on port {}",
+            listener.local_addr().map(|addr| addr.port()).unwrap_or(0)
+        );
+        let mut client_threads: Vec<JoinHandle<()>> = vec![];
+        while shared.is_accepting() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if is_timeout(&e) => {
+                    sleep(Duration::from_millis(STOP_POLL_INTERVAL_MS));
+                    continue;
+                }
+                Err(e) => {
+                    println!("websocket listener error: {}", e);
+                    continue;
+                }
+            };
+            client_threads.retain(|t| !t.is_finished());
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_write_timeout(Option::from(Duration::from_secs(5)));
+            let _ = stream.set_nodelay(true);
+            let shared = shared.clone();
+            client_threads.push(thread::spawn(move || match accept_websocket(stream) {
+                Ok(socket) => {
+                    let _ = handle_client(Box::new(socket), shared, settings).join();
+                }
+                Err(e) => println!("websocket upgrade failed: {}", e),
+            }));
+            println!("Websocket client count: {}", client_threads.len());
+        }
+        drop(listener);
+        for client in client_threads {
+            let _ = client.join();
+        }
+    })
+}
+
+/// Spawns a thread that accepts clients over udp on the same port as tcp, each new address that sends us a datagram is handled as a new client.
+fn spawn_udp_listener_thread(
+    mut listener: UdpListener,
+    shared: ServerShared,
+    settings: ServerSettings,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        println!("accepting udp clients");
+        let mut client_threads: Vec<JoinHandle<()>> = vec![];
+        while shared.is_running() {
+            match listener.accept() {
+                Ok(transport) => {
+                    client_threads.retain(|t| !t.is_finished());
+                    println!("udp client from {}", transport.peer_addr());
+                    client_threads.push(handle_client(
+                        Box::new(transport),
+                        shared.clone(),
+                        settings,
+                    ));
+                    println!("Udp client count: {}", client_threads.len());
+                }
+                Err(e) if is_timeout(&e) => {} // nothing new arrived, check if we are still running
+                Err(e) => {
+                    println!("udp listener error: {}", e);
+                }
+            }
+        }
+        for client in client_threads {
+            let _ = client.join();
+        }
+    })
+}
+
+/// Spawns a thread that closes rooms nobody has been in for a while, the lobby is always left open.
+fn spawn_room_cleanup_thread(shared: ServerShared) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while shared.is_running() {
+            sleep(Duration::from_millis(STOP_POLL_INTERVAL_MS));
+            let abandoned = shared.rooms.lock().unwrap().take_abandoned(Instant::now());
+            for room in abandoned {
+                room.close();
+            } // closing waits on the rooms threads, so it is done without holding the lock
+        }
+    })
+}
+
+/// This function spawns the game thread, that handles running the entire game server while reading the games state.
+/// The game is stepped at the fixed tick rate from the settings, so the simulation plays out the same no matter how busy the host is.
+/// If the thread falls behind it runs extra ticks to catch up, up to MAX_CATCH_UP_TICKS, after which the missed time is dropped rather than spiraling.
+/// Events that come out of the game, like a vote finishing, are broadcast to every client through the outbox.
+/// If auto_balance is on, the teams are evened out every time a new round starts. Every room runs its own game thread, which stops once running is set to false.
+fn spawn_game_thread(
+    game_state_rw: GameStateRW,
+    outbox: OutboxRW,
+    running: Arc<Mutex<bool>>,
+    settings: GameSettings,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let tick = Duration::from_secs_f64(1.0 / settings.tick_rate as f64);
+        let mut next_tick = Instant::now();
+        let mut tank_history = TankHistory::default();
+
+        while *running.lock().unwrap() {
+            let mut ticks_run = 0;
+            while Instant::now() >= next_tick && ticks_run < MAX_CATCH_UP_TICKS {
+                step_game(
+                    &game_state_rw,
+                    &outbox,
+                    &mut tank_history,
+                    &settings,
+                    tick.as_secs_f32(),
+                );
+                next_tick += tick;
+                ticks_run += 1;
+            } // run every tick that is due, this is more than one when the last tick or the sleep took too long.
+
+            let behind = Instant::now().saturating_duration_since(next_tick);
+            if ticks_run == MAX_CATCH_UP_TICKS && !behind.is_zero() {
+                println!(
+                    "game thread is {}ms behind, skipping ahead",
+                    behind.as_millis()
+                );
+                next_tick = Instant::now();
+            } // too far behind to catch up, so let the game slow down instead of running ticks forever.
+
+            sleep(next_tick.saturating_duration_since(Instant::now()));
+        } // loop to constantly update the game thread, see function comment for more info.
+    })
+}
+
+/// Steps the game by a single tick, difference is the length of a tick in seconds.
+/// tank_history is where tanks were on previous ticks, bullets are checked against where their shooter saw the tanks rather than where they are now.
+fn step_game(
+    game_state_rw: &GameStateRW,
+    outbox: &OutboxRW,
+    tank_history: &mut TankHistory,
+    settings: &GameSettings,
+    difference: f32,
+) {
+    let rules = &settings.rules;
+    let copy_gs = {
+        let lock = game_state_rw.read().unwrap();
+        lock.clone()
+    }; // make a copy of the game state, so we can read it to make decisions for the game thread.
+
+    if !copy_gs.client_list.is_empty() {
+        // basic game logic goes here
+        match copy_gs.game_type {
+            PONG(mut pgs) => {
+                // step the physics of the ball
+                pgs.step_ball(&difference);
+                // step the game state using the clients
+                pgs.step_game_state(&copy_gs.client_list, &rules.pong);
+
+                // update the game state that is on the server with the new game state that has been stepped.
+                {
+                    let mut lock = game_state_rw.write().unwrap();
+                    lock.time = SystemTime::now();
+                    if pgs.blue_points >= rules.pong.points_to_win
+                        || pgs.red_points >= rules.pong.points_to_win
+                    {
+                        lock.vote_running = true;
+                        if let Some(game_type) = lock.try_conclude_vote(rules.vote_time) {
+                            broadcast_message(
+                                outbox,
+                                ServerMessage::Event(GameEvent::VoteConcluded(game_type)),
+                            );
+                            if settings.auto_balance {
+                                rebalance_teams(&mut lock.client_list, outbox);
+                            }
+                        }
+                    } else {
+                        lock.vote_running = false;
+                        lock.game_type = PONG(pgs);
+                        lock.time = SystemTime::now();
+                    }
+                } // at the end of the game loop for pong, we add all the new data into the game state.
+            }
+
+            TANK(mut tgs) => {
+                let mut client_list = copy_gs.client_list.clone();
+                // move the tanks, shoot, and check for hits against where each shooter saw the tanks
+                tgs.step_with_history(
+                    &mut client_list,
+                    difference,
+                    &rules.tank,
+                    tank_history,
+                    SystemTime::now(),
+                );
+
+                {
+                    let mut lock = game_state_rw.write().unwrap();
+
+                    if tgs.red_score >= rules.tank.win_score
+                        || tgs.blue_score >= rules.tank.win_score
+                    {
+                        lock.vote_running = true;
+                        if let Some(game_type) = lock.try_conclude_vote(rules.vote_time) {
+                            broadcast_message(
+                                outbox,
+                                ServerMessage::Event(GameEvent::VoteConcluded(game_type)),
+                            );
+                            if settings.auto_balance {
+                                rebalance_teams(&mut lock.client_list, outbox);
+                            }
+                        }
+                        lock.time = SystemTime::now();
+                    } else {
+                        lock.vote_running = false;
+                        lock.game_type = TANK(tgs);
+                        keep_stepped_tanks(&mut lock.client_list, client_list);
+                        lock.time = SystemTime::now();
+                    }
+                } // at the end of the game loop where game mechanics run, we now move all the new data into the game state for the server.
+            }
+        }
+    } // only run the game loop if there are clients connected.
+}
+
+/// Reads the hello from a newly connected client and answers it, returning the welcome that was sent and the clients session if the client was accepted.
+/// A hello with the token of a recent session picks that session back up. Compression is turned down if the server has it turned off.
+/// Every hello is turned down once the server has stopped accepting players because it is shutting down.
+fn server_handshake(
+    transport: &mut dyn Transport,
+    sessions: &SessionsRW,
+    rooms: &RoomsRW,
+    settings: &ServerSettings,
+    accepting: bool,
+) -> Option<(ServerWelcome, SessionTicket)> {
+    let frame = match transport.recv_timeout(Duration::from_millis(settings.idle_timeout_ms)) {
+        Ok(Some(frame)) => frame,
+        Ok(None) => {
+            println!("client timed out during handshake");
+            return None;
+        }
+        Err(e) => {
+            println!("client disconnected during handshake: {}", e);
+            return None;
+        }
+    };
+
+    let mut ticket = None;
+    let response = match HANDSHAKE_CODEC.decode::<ClientHello>(&frame) {
+        Ok(hello) => {
+            println!(
+                "client hello, protocol: {}, build: {}",
+                hello.protocol_version, hello.build_version
+            );
+            match hello.check() {
+                Ok(()) if !accepting => HandshakeResponse::Rejected(RejectReason::ShuttingDown),
+                Ok(()) => {
+                    let mut sessions = sessions.lock().unwrap();
+                    let joined = sessions.join(hello.session_token.as_deref(), Instant::now());
+                    if !joined.resumed
+                        && rooms.lock().unwrap().player_count() >= settings.max_players
+                    {
+                        sessions.end(&joined.token);
+                        HandshakeResponse::Rejected(RejectReason::ServerFull {
+                            capacity: settings.max_players,
+                        })
+                    } else {
+                        let mut response = respond_to_hello(
+                            &hello,
+                            env!("CARGO_PKG_VERSION"),
+                            &joined.client_id,
+                            &joined.token,
+                            joined.resumed,
+                            &settings.game.rules,
+                            settings.game.snapshot_rate,
+                        );
+                        if let HandshakeResponse::Welcome(welcome) = &mut response {
+                            welcome.udp_cookie = transport.cookie();
+                            if settings.compression.is_none() {
+                                welcome
+                                    .capabilities
+                                    .retain(|capability| capability != &Capability::Compression);
+                            }
+                        }
+                        ticket = Some(joined);
+                        response
+                    } // players coming back to their session are let in even when full, they were counted before they dropped.
+                }
+                Err(reason) => HandshakeResponse::Rejected(reason),
+            } // only hellos that will be accepted get a session.
+        }
+        Err(e) => HandshakeResponse::Rejected(RejectReason::MalformedHello(e.to_string())),
+    };
+
+    if let Ok(ser) = HANDSHAKE_CODEC.encode(&response) {
+        let _ = transport.send_on(Channel::Reliable, &ser);
+    }
+
+    match (response, ticket) {
+        (HandshakeResponse::Welcome(welcome), Some(ticket)) => Some((welcome, ticket)),
+        (response, _) => {
+            if let HandshakeResponse::Rejected(reason) = response {
+                println!("client rejected: {}", reason);
+            }
+            None
+        }
+    }
+}
+
+/// This function handles a given client, it spawns a thread that will relay the game state to them, as well as take in their client info and insert that info into the game states client list under their uuid.
+/// Each client is denoted by a random gen uuid, which stays the same if they reconnect with their session token.
+/// The thread is closed when they either disconnect, or the thread closes. Their client state is then parked in their session for a while in case they come back,
+/// unless they left on purpose, or another connection has already taken over their session.
+fn handle_client(
+    transport: Box<dyn Transport>,
+    shared: ServerShared,
+    settings: ServerSettings,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let ServerShared {
+            rooms,
+            sessions,
+            compression_stats,
+            running,
+            accepting,
+        } = shared;
+        let mut transport: Box<dyn Transport> = match settings.network_conditions {
+            Some(conditions) => Box::new(ConditionedTransport::new(transport, conditions)),
+            None => transport,
+        };
+        let (welcome, ticket) = match server_handshake(
+            transport.as_mut(),
+            &sessions,
+            &rooms,
+            &settings,
+            *accepting.lock().unwrap(),
+        ) {
+            Some(accepted) => accepted,
+            None => return,
+        }; // the client gets no game state until it has completed the handshake.
+        let codec = welcome.codec();
+        let uuid = ticket.client_id.clone();
+        if ticket.resumed {
+            println!("client {} resumed their session", uuid);
+        }
+
+        let room = {
+            let rooms = rooms.lock().unwrap();
+            ticket
+                .parked_room
+                .and_then(|id| rooms.get(id))
+                .or_else(|| rooms.find_player(&uuid))
+                .unwrap_or_else(|| rooms.lobby())
+        }; // resumed players go back to the room they were in if it is still open, everyone else starts in the lobby.
+        let parked_state = ticket
+            .parked_state
+            .clone()
+            .filter(|_| ticket.parked_room == Some(room.id)); // a state from a room that has since closed is no use in another game
+
+        {
+            let mut lock = room.game_state.write().unwrap();
+            let team_id = if settings.game.auto_balance {
+                smaller_team(&lock.client_list)
+            } else {
+                BlueTeam
+            }; // only used if they are a new player, resumed players stay on their team
+            let client = lock
+                .client_list
+                .entry(uuid.to_string())
+                .or_insert(parked_state.unwrap_or(new_client_state(team_id)));
+            client.last_input_sequence = 0; // a new connection numbers its inputs from the start again
+        } // block to add new client to client list with all default data, or what they had before if they are resuming.
+
+        {
+            room.outbox.lock().unwrap().insert(uuid.to_string(), vec![]);
+            push_message(&room.outbox, &uuid, ServerMessage::RoomJoined(room.info()));
+        } // give the client somewhere to receive events, and tell them which room they are in. The room info counts them, so it is made after they are added.
+
+        let mut connection = ClientConnection {
+            room,
+            monitor: ConnectionMonitor::new(
+                Duration::from_millis(settings.idle_timeout_ms),
+                settings.max_bad_packets,
+                Instant::now(),
+            ),
+            snapshots: SnapshotEncoder::default(),
+            pings: PingTracker::default(),
+            limiter: MessageLimiter::new(Instant::now()),
+            compressor: match settings.compression {
+                Some(config) if welcome.compression() => Some(Compressor::new(config)),
+                _ => None,
+            },
+        };
+        let snapshot_interval = Duration::from_secs_f64(1.0 / settings.game.snapshot_rate as f64);
+        let mut next_snapshot = Instant::now();
+
+        loop {
+            let local_gs = {
+                let lock = connection.room.game_state.read().unwrap();
+                lock.clone()
+            }; // lock read of local gs gets locked once we need to loop through the client handler once again.
+
+            // every message waiting for this client gets sent first, then the snapshot to end our turn.
+            // the snapshot only has what this client is allowed to see.
+            let now = Instant::now();
+            if !sessions
+                .lock()
+                .unwrap()
+                .is_current(&ticket.token, ticket.generation)
+            {
+                connection.monitor.disconnect(DisconnectReason::Kicked(
+                    "reconnected from somewhere else".to_string(),
+                ));
+            } // the player came back on a new connection before this one noticed it had dropped.
+            if !*running.lock().unwrap() {
+                connection
+                    .monitor
+                    .disconnect(DisconnectReason::ServerClosing);
+            }
+            let mut messages = take_messages(&connection.room.outbox, &uuid);
+            match connection.monitor.check(now) {
+                ConnectionState::Connected => {
+                    if let Some(id) = connection.pings.ping(now) {
+                        messages.push(ServerMessage::Ping(id));
+                    }
+                    messages.push(ServerMessage::Snapshot(
+                        connection.snapshots.encode(&client_view(&local_gs, &uuid)),
+                    ));
+                }
+                ConnectionState::Stalled => {
+                    if connection.monitor.needs_heartbeat(now) {
+                        messages.push(ServerMessage::Heartbeat);
+                    }
+                } // dont pile snapshots up on a client that is not reading them, just let them know we are still here.
+                ConnectionState::Disconnected(_) => {}
+            }
+
+            if !messages.is_empty() {
+                match send_messages(
+                    transport.as_mut(),
+                    codec,
+                    connection.compressor.as_mut(),
+                    &messages,
+                ) {
+                    Ok(()) => connection.monitor.sent(now),
+                    Err(e) => connection
+                        .monitor
+                        .disconnect(DisconnectReason::ConnectionLost(e)),
+                }
+                if let Some(compressor) = &mut connection.compressor {
+                    compression_stats
+                        .lock()
+                        .unwrap()
+                        .merge(&compressor.take_stats());
+                }
+            }
+
+            if !connection.monitor.is_disconnected() {
+                receive_client_turn(
+                    transport.as_mut(),
+                    codec,
+                    &rooms,
+                    &uuid,
+                    &mut connection,
+                    &settings.game,
+                );
+            }
+
+            if let Some(reason) = connection.monitor.disconnect_reason() {
+                let _ = send_messages(
+                    transport.as_mut(),
+                    codec,
+                    connection.compressor.as_mut(),
+                    &[ServerMessage::Disconnect(reason.clone())],
+                ); // the client may already be gone, so it does not matter if this fails.
+                println!("client disconnected: {}", reason);
+                break;
+            }
+
+            next_snapshot += snapshot_interval;
+            let now = Instant::now();
+            if next_snapshot > now {
+                sleep(next_snapshot - now);
+            } else {
+                next_snapshot = now; // the client is slower than the snapshot rate, send the next one as soon as we can.
+            }
+        } // loop that constantly requests data from the client, also replicates the current game state to the client, and changes the clients state to their current input if they have any.
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            if sessions.is_current(&ticket.token, ticket.generation) {
+                let room = &connection.room;
+                let client = room.game_state.write().unwrap().client_list.remove(&*uuid);
+                room.outbox.lock().unwrap().remove(&*uuid);
+                match (connection.monitor.disconnect_reason(), client) {
+                    (Some(DisconnectReason::Left | DisconnectReason::Kicked(_)), _) | (_, None) => {
+                        sessions.end(&ticket.token)
+                    } // kicked players dont get to come back as the same player
+                    (_, Some(client)) => {
+                        sessions.park(&ticket.token, room.id, client, Instant::now())
+                    }
+                }
+            }
+        } // remove the client from the game and from the outbox once they have disconnected, keeping their state around in case they reconnect.
+    })
+}
+
+/// Sends each message to the client in order, stopping at the first message that fails.
+/// Pongs get their send time set right before they go out. If the client asked for compression, every message goes through the compressor.
+fn send_messages(
+    transport: &mut dyn Transport,
+    codec: Codec,
+    mut compressor: Option<&mut Compressor>,
+    messages: &[ServerMessage],
+) -> Result<(), String> {
+    for message in messages {
+        let ser = match message {
+            ServerMessage::Pong(pong) => codec.encode(&ServerMessage::Pong(pong.stamped())),
+            _ => codec.encode(message),
+        }
+        .map_err(|e| e.to_string())?;
+        let ser = match compressor.as_deref_mut() {
+            Some(compressor) => compressor.compress(&ser),
+            None => ser,
+        };
+        transport
+            .send_on(message.channel(), &ser)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Reads messages from the client until the message that ends their turn, acting on each one as it arrives.
+/// The turn also ends when the turn timeout passes, so a lost or late input does not stall the client for long.
+/// The connections monitor hears about every message, and is disconnected if the client should be dropped.
+fn receive_client_turn(
+    transport: &mut dyn Transport,
+    codec: Codec,
+    rooms: &RoomsRW,
+    uuid: &str,
+    connection: &mut ClientConnection,
+    game: &GameSettings,
+) {
+    let deadline = Instant::now() + transport.turn_timeout();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let frame = match transport.recv_timeout(remaining) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return, // nothing more this turn
+            Err(e) => {
+                connection
+                    .monitor
+                    .disconnect(DisconnectReason::ConnectionLost(e.to_string()));
+                return;
+            }
+        };
+
+        let now = Instant::now();
+        // the room can change part way through the turn, so look it up again for every message
+        let room = connection.room.clone();
+        let game_state_rw = &room.game_state;
+        let outbox = &room.outbox;
+        let message = match codec.decode::<ClientMessage>(&frame) {
+            Ok(message) => {
+                connection.monitor.heard(now);
+                message
+            }
+            Err(e) => {
+                if connection.monitor.bad_packet(now) == &ConnectionState::Connected {
+                    push_message(
+                        outbox,
+                        uuid,
+                        ServerMessage::Error(format!("could not read packet: {}", e)),
+                    );
+                    continue;
+                }
+                return; // too many bad packets
+            }
+        };
+
+        if let Err(violation) = connection.limiter.allow_message(now) {
+            if !matches!(message, ClientMessage::Disconnect(_)) {
+                report_violation(connection, uuid, violation, now);
+                if connection.monitor.is_disconnected() {
+                    return;
+                }
+                continue;
+            }
+        } // a client that is leaving is always let through, so we know why.
+
+        match message {
+            ClientMessage::Input(c) => {
+                match sanitize_client_info(c) {
+                    Ok(c) => apply_client_info(game_state_rw, uuid, c, game),
+                    Err(violation) => report_violation(connection, uuid, violation, now),
+                }
+                return;
+            }
+            ClientMessage::Chat(text) => {
+                let text = text.trim();
+                if text.is_empty() || text.chars().count() > MAX_CHAT_LENGTH {
+                    push_message(
+                        outbox,
+                        uuid,
+                        ServerMessage::Error(format!(
+                            "chat messages must be between 1 and {} characters",
+                            MAX_CHAT_LENGTH
+                        )),
+                    );
+                } else {
+                    broadcast_message(
+                        outbox,
+                        ServerMessage::Event(GameEvent::Chat {
+                            name: uuid.chars().take(8).collect(),
+                            message: text.to_string(),
+                        }),
+                    );
+                }
+            }
+            ClientMessage::Vote(vote_number) => match check_vote(vote_number) {
+                Ok(vote_number) => {
+                    let mut lock = game_state_rw.write().unwrap();
+                    if let Some(client) = lock.client_list.get_mut(uuid) {
+                        client.vote_number = vote_number;
+                    }
+                }
+                Err(violation) => {
+                    report_violation(connection, uuid, violation, now);
+                    if connection.monitor.is_disconnected() {
+                        return;
+                    }
+                }
+            },
+            ClientMessage::TeamRequest(team) => {
+                let mut lock = game_state_rw.write().unwrap();
+                let now = SystemTime::now();
+                match check_team_request(&lock.client_list, uuid, &team, game.auto_balance, now) {
+                    Ok(()) => {
+                        if let Some(client) = lock.client_list.get_mut(uuid) {
+                            if client.team_id != team {
+                                client.team_id = team.clone();
+                                client.team_changed_at = Some(now);
+                                broadcast_message(
+                                    outbox,
+                                    ServerMessage::Event(GameEvent::TeamChanged {
+                                        name: uuid.chars().take(8).collect(),
+                                        team,
+                                    }),
+                                );
+                            }
+                        }
+                    }
+                    Err(reason) => push_message(outbox, uuid, ServerMessage::TeamRejected(reason)),
+                }
+            }
+            ClientMessage::SnapshotAck(sequence) => connection.snapshots.ack(sequence),
+            ClientMessage::Ping(id) => {
+                push_message(outbox, uuid, ServerMessage::Pong(Pong::reply_to(id)))
+            }
+            ClientMessage::Pong(pong) => {
+                if connection.pings.pong(&pong, Instant::now()).is_some() {
+                    let rtt = connection.pings.rtt().unwrap_or_default();
+                    let mut lock = game_state_rw.write().unwrap();
+                    if let Some(client) = lock.client_list.get_mut(uuid) {
+                        client.rtt_ms = rtt.as_millis() as u32;
+                    }
+                }
+            }
+            ClientMessage::Heartbeat => {} // hearing it was all it was for
+            ClientMessage::ListRooms => {
+                let list = rooms.lock().unwrap().list();
+                push_message(outbox, uuid, ServerMessage::RoomList(list));
+            }
+            ClientMessage::CreateRoom(name) => {
+                let opened =
+                    check_room_name(&name).and_then(|name| rooms.lock().unwrap().open(name));
+                match opened {
+                    Ok(to) => move_to_room(connection, uuid, to, game.auto_balance),
+                    Err(reason) => push_message(outbox, uuid, ServerMessage::RoomRejected(reason)),
+                }
+            }
+            ClientMessage::JoinRoom(id) => {
+                let to = rooms.lock().unwrap().get(id);
+                match to {
+                    Some(to) if to.id == room.id => push_message(
+                        outbox,
+                        uuid,
+                        ServerMessage::RoomRejected(RoomRejectReason::AlreadyInRoom),
+                    ),
+                    Some(to) => move_to_room(connection, uuid, to, game.auto_balance),
+                    None => push_message(
+                        outbox,
+                        uuid,
+                        ServerMessage::RoomRejected(RoomRejectReason::NotFound(id)),
+                    ),
+                }
+            }
+            ClientMessage::LeaveRoom => {
+                let lobby = rooms.lock().unwrap().lobby();
+                if lobby.id != room.id {
+                    move_to_room(connection, uuid, lobby, game.auto_balance);
+                }
+            }
+            ClientMessage::Disconnect(reason) => {
+                connection.monitor.disconnect(reason);
+                return;
+            }
+        }
+    }
+}
+
+/// Moves a player out of the room they are in and into another, they start the other rooms game as a new player but keep their team if they can.
+/// The rest of their turn is played in the new room.
+fn move_to_room(connection: &mut ClientConnection, uuid: &str, to: RoomHandle, auto_balance: bool) {
+    let from = std::mem::replace(&mut connection.room, to.clone());
+    let previous = from.game_state.write().unwrap().client_list.remove(uuid);
+    from.outbox.lock().unwrap().remove(uuid);
+
+    {
+        let mut lock = to.game_state.write().unwrap();
+        let team_id = match previous {
+            Some(previous) if !auto_balance => previous.team_id,
+            _ => smaller_team(&lock.client_list),
+        };
+        lock.client_list
+            .insert(uuid.to_string(), new_client_state(team_id));
+    }
+    to.outbox.lock().unwrap().insert(uuid.to_string(), vec![]);
+    push_message(&to.outbox, uuid, ServerMessage::RoomJoined(to.info()));
+    println!(
+        "client {} moved from room {:?} to {:?}",
+        uuid, from.name, to.name
+    );
+}
+
+/// The state a player starts a game with, on the given team.
+fn new_client_state(team_id: Team) -> ClientState {
+    ClientState {
+        time: SystemTime::now(),
+        // pos: (0.0, 0.0),
+        team_id,
+        mouse_pos: (0.0, 0.0),
+        key_state: KeyState::default(),
+
+        pong_client_state: Default::default(),
+        tank_client_state: Default::default(),
+        vote_number: 0,
+        last_input_sequence: 0,
+        rtt_ms: 0,
+        lag_compensation: 0.0,
+        team_changed_at: None,
+    }
+}
+
+/// Kicks a client if they have sent too many bad things recently.
+/// Only the first bad message in each violation window is logged, so a client flooding the server does not flood the log as well.
+fn report_violation(
+    connection: &mut ClientConnection,
+    uuid: &str,
+    violation: Violation,
+    now: Instant,
+) {
+    let kick = connection.limiter.violation(now);
+    if connection.limiter.violations() == 1 {
+        println!(
+            "client {} sent a bad message: {}, any more in the next {}ms are counted but not logged",
+            uuid, violation, VIOLATION_WINDOW_MS
+        );
+    }
+    if kick {
+        connection
+            .monitor
+            .disconnect(DisconnectReason::Kicked(format!(
+                "too many bad messages, last one was {}",
+                violation
+            )));
+    }
+}
+
+/// Applies a clients input to their client state, depending on the game type the input is interpreted differently.
+fn apply_client_info(game_state_rw: &GameStateRW, uuid: &str, c: ClientInfo, game: &GameSettings) {
+    // here we can decide if we want to do anything with the client state given if it is different enough,
+    // this would allow us to only take changes if they are large enough, compressing how often we have to lock the game state, if we decide to be threadsafe.
+
+    let mut local_gs = game_state_rw.write().unwrap();
+    /*
+    Lock the game state once we have received all data from the client,
+    at this point, all we need to do is manage it, so we can lock the entire game state now.
+    */
+
+    let prev_client = match local_gs.client_list.get(uuid) {
+        None => ClientState::default(),
+        Some(client) => client.clone(),
+    };
+
+    if c.input_sequence <= prev_client.last_input_sequence {
+        return;
+    } // an older input that arrived late, we have already applied something newer.
+
+    let lag_compensation = lag_compensation_for(
+        c.view_time,
+        prev_client.rtt_ms,
+        game.rules.tank.max_lag_compensation_ms,
+    );
+
+    match &local_gs.game_type {
+        // depending on the game type, handle the clients info differently.
+        PONG(_pgs) => {
+            let paddle_width = get_pong_paddle_width(&local_gs.client_list, &prev_client.team_id);
+            let pong_client_state = prev_client.pong_client_state.apply_input(
+                &c,
+                &prev_client.team_id,
+                paddle_width,
+                &game.rules.pong,
+                game.snapshot_rate,
+                SystemTime::now(),
+            );
+
+            let client_state: ClientState = ClientState {
+                // create the new client state from the information we have from the client info.
+                time: c.time,
+                team_id: prev_client.team_id.clone(), // the server decides teams, not the input
+                mouse_pos: c.mouse_pos,
+                key_state: c.key_state,
+                pong_client_state,
+                tank_client_state: prev_client.tank_client_state,
+                vote_number: prev_client.vote_number,
+                last_input_sequence: c.input_sequence,
+                rtt_ms: prev_client.rtt_ms,
+                lag_compensation,
+                team_changed_at: prev_client.team_changed_at,
+            };
+
+            {
+                local_gs.client_list.insert(uuid.to_string(), client_state);
+            }
+        }
+
+        TANK(_tgs) => {
+            let client_state: ClientState = ClientState {
+                // create the new client state from the information we have from the client info.
+                time: c.time,
+                team_id: prev_client.team_id.clone(), // the server decides teams, not the input
+                mouse_pos: c.mouse_pos,
+                key_state: c.key_state.clone(),
+                pong_client_state: prev_client.pong_client_state,
+                tank_client_state: prev_client.tank_client_state,
+                vote_number: prev_client.vote_number,
+                last_input_sequence: c.input_sequence,
+                rtt_ms: prev_client.rtt_ms,
+                lag_compensation,
+                team_changed_at: prev_client.team_changed_at,
+            };
+
+            {
+                local_gs.client_list.insert(uuid.to_string(), client_state);
+            }
+        }
+    }
+}
+
+/// Puts the tanks from a stepped copy of the client list back into the game states client list.
+/// Only the tanks are taken, inputs, team changes and votes that came in while the tick ran are kept, and players who left during the tick are not brought back.
+fn keep_stepped_tanks(
+    client_list: &mut HashMap<String, ClientState>,
+    stepped: HashMap<String, ClientState>,
+) {
+    for (uuid, stepped_client) in stepped {
+        if let Some(client) = client_list.get_mut(&uuid) {
+            client.tank_client_state = stepped_client.tank_client_state;
+        }
+    }
+}
+
+/// Evens out the teams, letting everyone know who was moved.
+fn rebalance_teams(client_list: &mut HashMap<String, ClientState>, outbox: &OutboxRW) {
+    for uuid in balance_teams(client_list, SystemTime::now()) {
+        let team = client_list[&uuid].team_id.clone();
+        broadcast_message(
+            outbox,
+            ServerMessage::Event(GameEvent::TeamChanged {
+                name: uuid.chars().take(8).collect(),
+                team,
+            }),
+        );
+    }
+}
+
+/// Removes and returns every message waiting to be sent to the given client.
+fn take_messages(outbox: &OutboxRW, uuid: &str) -> Vec<ServerMessage> {
+    match outbox.lock().unwrap().get_mut(uuid) {
+        None => vec![],
+        Some(messages) => std::mem::take(messages),
+    }
+}
+
+/// Queues a message to be sent to a single client on their next turn.
+fn push_message(outbox: &OutboxRW, uuid: &str, message: ServerMessage) {
+    if let Some(messages) = outbox.lock().unwrap().get_mut(uuid) {
+        messages.push(message);
+    }
+}
+
+/// Queues a message to be sent to every connected client on their next turn.
+fn broadcast_message(outbox: &OutboxRW, message: ServerMessage) {
+    for messages in outbox.lock().unwrap().values_mut() {
+        messages.push(message.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keep_stepped_tanks;
+    use hattrick_packets_lib::clientstate::ClientState;
+    use std::collections::HashMap;
+
+    #[test]
+    fn keep_stepped_tanks_test() {
+        let mut stepped = HashMap::new();
+        let mut moved = ClientState::default();
+        moved.tank_client_state.tank_x = 10.0;
+        stepped.insert("stayed".to_string(), moved.clone());
+        stepped.insert("left".to_string(), moved);
+
+        // while the tick ran one player left and the other voted
+        let mut client_list = HashMap::new();
+        client_list.insert(
+            "stayed".to_string(),
+            ClientState {
+                vote_number: 2,
+                ..ClientState::default()
+            },
+        );
+
+        keep_stepped_tanks(&mut client_list, stepped);
+        assert_eq!(client_list.len(), 1);
+        assert_eq!(client_list["stayed"].tank_client_state.tank_x, 10.0);
+        assert_eq!(client_list["stayed"].vote_number, 2);
+    }
+}
@@ -0,0 +1,333 @@
+use crate::ai::game_ai::spawn_ai_thread;
+use crate::session::SESSION_GRACE_PERIOD_MS;
+use crate::{spawn_game_thread, GameSettings, GameStateRW, OutboxRW};
+use hattrick_packets_lib::gamestate::GameState;
+use hattrick_packets_lib::room::{
+    RoomId, RoomInfo, RoomRejectReason, LOBBY_ROOM_ID, LOBBY_ROOM_NAME, MAX_ROOMS,
+};
+use hattrick_packets_lib::team::Team::{BlueTeam, RedTeam};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long in milliseconds a room other than the lobby can sit empty before it is closed.
+/// This is the same as the session grace period, so a player who dropped can reconnect to their room.
+pub static EMPTY_ROOM_TIMEOUT_MS: u64 = SESSION_GRACE_PERIOD_MS;
+
+/// RoomHandle is what a connection keeps of the room its player is in, enough to play in the room without locking the room list.
+#[derive(Clone)]
+pub struct RoomHandle {
+    pub id: RoomId,
+    pub name: String,
+    pub game_state: GameStateRW,
+    /// Messages waiting to be sent to each player in the room, a player is in the room if they have an entry in here.
+    pub outbox: OutboxRW,
+}
+
+impl RoomHandle {
+    /// How many players are in the room, not counting the ai.
+    pub fn player_count(&self) -> u32 {
+        self.outbox.lock().unwrap().len() as u32
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            id: self.id,
+            name: self.name.clone(),
+            game_mode: self.game_state.read().unwrap().game_type.to_string(),
+            player_count: self.player_count(),
+        }
+    }
+}
+
+/// Room is a single game running on the server, with its own game thread and ai.
+pub struct Room {
+    handle: RoomHandle,
+    /// Set to false to stop the rooms game thread and ai.
+    running: Arc<Mutex<bool>>,
+    threads: Vec<JoinHandle<()>>,
+    /// When the last player left the room, None while there are players in it.
+    empty_since: Option<Instant>,
+}
+
+impl Room {
+    /// Stops the rooms game thread and ai, returning once they have finished.
+    pub fn close(self) {
+        *self.running.lock().unwrap() = false;
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        println!("closed room {:?}", self.handle.name);
+    }
+}
+
+/// Rooms is every room open on the server. Lock this after the sessions and before any rooms game state if more than one is needed.
+pub struct Rooms {
+    rooms: BTreeMap<RoomId, Room>,
+    next_id: RoomId,
+    settings: GameSettings,
+}
+
+impl Rooms {
+    /// Makes the room list with the lobby already open, every room is run with the given settings.
+    pub fn new(settings: GameSettings) -> Self {
+        let mut rooms = Rooms {
+            rooms: BTreeMap::new(),
+            next_id: LOBBY_ROOM_ID,
+            settings,
+        };
+        let _ = rooms.open(LOBBY_ROOM_NAME.to_string());
+        rooms
+    }
+
+    /// Opens a new room and starts its game, the room is closed after a while if nobody joins it.
+    pub fn open(&mut self, name: String) -> Result<RoomHandle, RoomRejectReason> {
+        if self.rooms.len() >= MAX_ROOMS {
+            return Err(RoomRejectReason::TooManyRooms { max: MAX_ROOMS });
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let handle = RoomHandle {
+            id,
+            name,
+            game_state: Arc::new(RwLock::new(GameState::default())),
+            outbox: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let running = Arc::new(Mutex::new(true));
+        let mut threads = vec![spawn_game_thread(
+            Arc::clone(&handle.game_state),
+            Arc::clone(&handle.outbox),
+            Arc::clone(&running),
+            self.settings,
+        )];
+        for a in 0..self.settings.ai_players {
+            let team = if a % 2 == 0 { RedTeam } else { BlueTeam };
+            threads.push(spawn_ai_thread(
+                Arc::clone(&handle.game_state),
+                Arc::clone(&running),
+                team,
+                format!("ai{}", a),
+                self.settings.rules.pong,
+                self.settings.ai_tick_delay_ms,
+            ));
+        }
+        println!("opened room {} {:?}", id, handle.name);
+        self.rooms.insert(
+            id,
+            Room {
+                handle: handle.clone(),
+                running,
+                threads,
+                empty_since: Some(Instant::now()),
+            },
+        );
+        Ok(handle)
+    }
+
+    pub fn get(&self, id: RoomId) -> Option<RoomHandle> {
+        self.rooms.get(&id).map(|room| room.handle.clone())
+    }
+
+    pub fn lobby(&self) -> RoomHandle {
+        self.get(LOBBY_ROOM_ID).unwrap()
+    }
+
+    /// The room the given player is in, if they are in one.
+    pub fn find_player(&self, uuid: &str) -> Option<RoomHandle> {
+        self.rooms
+            .values()
+            .find(|room| {
+                room.handle
+                    .game_state
+                    .read()
+                    .unwrap()
+                    .client_list
+                    .contains_key(uuid)
+            })
+            .map(|room| room.handle.clone())
+    }
+
+    /// A handle to every open room, for sending something to everyone on the server.
+    pub fn handles(&self) -> Vec<RoomHandle> {
+        self.rooms
+            .values()
+            .map(|room| room.handle.clone())
+            .collect()
+    }
+
+    /// Every open room, in the order they were opened.
+    pub fn list(&self) -> Vec<RoomInfo> {
+        self.rooms.values().map(|room| room.handle.info()).collect()
+    }
+
+    /// How many players are on the server, across every room.
+    pub fn player_count(&self) -> u32 {
+        self.rooms
+            .values()
+            .map(|room| room.handle.player_count())
+            .sum()
+    }
+
+    /// Takes every room that has been empty for longer than the empty room timeout out of the list, for them to be closed.
+    /// The lobby is never taken. Closing joins the rooms threads, so do it after letting go of the lock.
+    pub fn take_abandoned(&mut self, now: Instant) -> Vec<Room> {
+        for room in self.rooms.values_mut() {
+            if room.handle.player_count() > 0 {
+                room.empty_since = None;
+            } else if room.empty_since.is_none() {
+                room.empty_since = Some(now);
+            }
+        }
+        let timeout = Duration::from_millis(EMPTY_ROOM_TIMEOUT_MS);
+        let abandoned: Vec<RoomId> = self
+            .rooms
+            .iter()
+            .filter(|(id, room)| {
+                **id != LOBBY_ROOM_ID
+                    && room
+                        .empty_since
+                        .is_some_and(|since| now.saturating_duration_since(since) >= timeout)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        abandoned
+            .iter()
+            .filter_map(|id| self.rooms.remove(id))
+            .collect()
+    }
+
+    /// Takes every room out of the list, for when the server is stopping.
+    pub fn take_all(&mut self) -> Vec<Room> {
+        std::mem::take(&mut self.rooms).into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ai::game_ai::AI_TICK_DELAY_MS;
+    use crate::room::{RoomHandle, Rooms, EMPTY_ROOM_TIMEOUT_MS};
+    use crate::{GameSettings, SERVER_TICK_RATE};
+    use hattrick_packets_lib::clientstate::ClientState;
+    use hattrick_packets_lib::gamestate::SNAPSHOT_RATE;
+    use hattrick_packets_lib::room::{RoomRejectReason, LOBBY_ROOM_ID, MAX_ROOMS};
+    use hattrick_packets_lib::rules::GameRules;
+    use std::time::{Duration, Instant};
+
+    fn rooms() -> Rooms {
+        Rooms::new(GameSettings {
+            auto_balance: true,
+            ai_players: 0,
+            tick_rate: SERVER_TICK_RATE,
+            snapshot_rate: SNAPSHOT_RATE,
+            ai_tick_delay_ms: AI_TICK_DELAY_MS,
+            rules: GameRules::default(),
+        })
+    }
+
+    fn add_player(room: &RoomHandle, uuid: &str) {
+        room.game_state
+            .write()
+            .unwrap()
+            .client_list
+            .insert(uuid.to_string(), ClientState::default());
+        room.outbox.lock().unwrap().insert(uuid.to_string(), vec![]);
+    }
+
+    fn remove_player(room: &RoomHandle, uuid: &str) {
+        room.game_state.write().unwrap().client_list.remove(uuid);
+        room.outbox.lock().unwrap().remove(uuid);
+    }
+
+    fn close_all(mut rooms: Rooms) {
+        for room in rooms.take_all() {
+            room.close();
+        }
+    }
+
+    #[test]
+    fn rooms_open_with_lobby_test() {
+        let mut rooms = rooms();
+        assert_eq!(rooms.lobby().id, LOBBY_ROOM_ID);
+        assert_eq!(rooms.list().len(), 1);
+
+        let room = rooms.open("other".to_string()).unwrap();
+        assert_ne!(room.id, LOBBY_ROOM_ID);
+        assert_eq!(rooms.get(room.id).unwrap().name, "other");
+        assert_eq!(rooms.list().len(), 2);
+        assert!(rooms.get(room.id + 1).is_none());
+
+        // the lobby counts towards the limit
+        for n in 2..MAX_ROOMS {
+            rooms.open(format!("room {}", n)).unwrap();
+        }
+        assert!(matches!(
+            rooms.open("one too many".to_string()),
+            Err(RoomRejectReason::TooManyRooms { max }) if max == MAX_ROOMS
+        ));
+        close_all(rooms);
+    }
+
+    #[test]
+    fn rooms_player_count_test() {
+        let mut rooms = rooms();
+        let lobby = rooms.lobby();
+        let room = rooms.open("other".to_string()).unwrap();
+        add_player(&lobby, "a");
+        add_player(&lobby, "b");
+        add_player(&room, "c");
+
+        assert_eq!(lobby.player_count(), 2);
+        assert_eq!(room.info().player_count, 1);
+        assert_eq!(rooms.player_count(), 3);
+        close_all(rooms);
+    }
+
+    #[test]
+    fn rooms_move_from_lobby_test() {
+        let mut rooms = rooms();
+        let lobby = rooms.lobby();
+        add_player(&lobby, "a");
+        assert_eq!(rooms.find_player("a").unwrap().id, LOBBY_ROOM_ID);
+
+        let room = rooms.open("other".to_string()).unwrap();
+        remove_player(&lobby, "a");
+        add_player(&room, "a");
+        assert_eq!(rooms.find_player("a").unwrap().id, room.id);
+        assert_eq!(lobby.player_count(), 0);
+        assert_eq!(rooms.player_count(), 1);
+        assert!(rooms.find_player("b").is_none());
+        close_all(rooms);
+    }
+
+    #[test]
+    fn rooms_take_abandoned_test() {
+        let mut rooms = rooms();
+        let empty = rooms.open("empty".to_string()).unwrap();
+        let busy = rooms.open("busy".to_string()).unwrap();
+        add_player(&busy, "a");
+
+        let now = Instant::now();
+        assert!(rooms.take_abandoned(now).is_empty());
+
+        // only the empty room times out, the lobby is never taken even though nobody is in it
+        let later = now + Duration::from_millis(EMPTY_ROOM_TIMEOUT_MS);
+        let abandoned = rooms.take_abandoned(later);
+        assert_eq!(abandoned.len(), 1);
+        assert!(rooms.get(empty.id).is_none());
+        assert!(rooms.get(LOBBY_ROOM_ID).is_some());
+        for room in abandoned {
+            room.close();
+        }
+
+        // the busy room starts its timeout when its last player leaves, not when it was opened
+        remove_player(&busy, "a");
+        assert!(rooms.take_abandoned(later).is_empty());
+        let abandoned = rooms.take_abandoned(later + Duration::from_millis(EMPTY_ROOM_TIMEOUT_MS));
+        assert_eq!(abandoned.len(), 1);
+        for room in abandoned {
+            room.close();
+        }
+        close_all(rooms);
+    }
+}
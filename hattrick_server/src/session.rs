@@ -0,0 +1,196 @@
+use hattrick_packets_lib::clientstate::ClientState;
+use hattrick_packets_lib::room::RoomId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long in milliseconds a dropped clients state is kept for them to reconnect and carry on where they left off.
+pub static SESSION_GRACE_PERIOD_MS: u64 = 30_000;
+
+/// Session is a single player on the server, it outlives their connection so they can reconnect as the same player.
+#[derive(Debug)]
+struct Session {
+    /// The players key in the client list, this is public to every client unlike the token.
+    client_id: String,
+    /// Goes up every time a new connection takes over the session, a connection whose generation is old has been replaced.
+    generation: u64,
+    /// The players state, the room they were in and when their connection dropped, while nobody is connected to the session.
+    parked: Option<(Instant, RoomId, ClientState)>,
+}
+
+/// SessionTicket is what a connection gets when it joins, either a brand new session or one it resumed with its token.
+#[derive(Debug)]
+pub struct SessionTicket {
+    /// The secret the client sends back when reconnecting to get this session again.
+    pub token: String,
+    pub client_id: String,
+    pub generation: u64,
+    /// True if an existing session was picked back up.
+    pub resumed: bool,
+    /// The players state from when they dropped, None for new sessions or when the previous connection is still in the game.
+    pub parked_state: Option<ClientState>,
+    /// The room the player was in when they dropped.
+    pub parked_room: Option<RoomId>,
+}
+
+/// Sessions is every player on the server by their session token, including players who dropped recently and may come back.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    sessions: HashMap<String, Session>,
+    next_generation: u64,
+}
+
+impl Sessions {
+    /// Joins a connection to a session, resuming the session with the given token if there is one, otherwise starting a new one.
+    /// Resuming a session that still has a connection takes it over, the old connection should notice it is no longer current and close.
+    pub fn join(&mut self, token: Option<&str>, now: Instant) -> SessionTicket {
+        self.expire(now);
+        self.next_generation += 1;
+        let generation = self.next_generation;
+
+        if let Some(session) = token.and_then(|token| self.sessions.get_mut(token)) {
+            session.generation = generation;
+            let parked = session.parked.take();
+            return SessionTicket {
+                token: token.unwrap().to_string(),
+                client_id: session.client_id.clone(),
+                generation,
+                resumed: true,
+                parked_room: parked.as_ref().map(|(_, room_id, _)| *room_id),
+                parked_state: parked.map(|(_, _, state)| state),
+            };
+        }
+
+        let token = Uuid::new_v4().to_string();
+        let client_id = Uuid::new_v4().to_string();
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                client_id: client_id.clone(),
+                generation,
+                parked: None,
+            },
+        );
+        SessionTicket {
+            token,
+            client_id,
+            generation,
+            resumed: false,
+            parked_state: None,
+            parked_room: None,
+        }
+    }
+
+    /// True if the given connection is still the one using the session.
+    pub fn is_current(&self, token: &str, generation: u64) -> bool {
+        self.sessions
+            .get(token)
+            .is_some_and(|session| session.generation == generation)
+    }
+
+    /// Keeps the players state for the grace period after their connection dropped, so they can resume it in the same room.
+    pub fn park(&mut self, token: &str, room_id: RoomId, state: ClientState, now: Instant) {
+        if let Some(session) = self.sessions.get_mut(token) {
+            session.parked = Some((now, room_id, state));
+        }
+    }
+
+    /// Forgets the session, for when the player left on purpose or never finished joining.
+    pub fn end(&mut self, token: &str) {
+        self.sessions.remove(token);
+    }
+
+    /// Forgets every parked session that has been waiting longer than the grace period.
+    fn expire(&mut self, now: Instant) {
+        let grace_period = Duration::from_millis(SESSION_GRACE_PERIOD_MS);
+        self.sessions.retain(|_, session| match &session.parked {
+            None => true,
+            Some((parked_at, _, _)) => now.saturating_duration_since(*parked_at) < grace_period,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::{Sessions, SESSION_GRACE_PERIOD_MS};
+    use hattrick_packets_lib::clientstate::ClientState;
+    use std::time::{Duration, Instant};
+
+    fn parked_state() -> ClientState {
+        ClientState {
+            rtt_ms: 42,
+            ..ClientState::default()
+        }
+    }
+
+    #[test]
+    fn session_join_test() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+        let first = sessions.join(None, now);
+        let second = sessions.join(None, now);
+        assert!(!first.resumed);
+        assert!(first.parked_state.is_none() && first.parked_room.is_none());
+        assert_ne!(first.token, second.token);
+        assert_ne!(first.client_id, second.client_id);
+
+        // a token we never handed out starts a new session instead of resuming one
+        let unknown = sessions.join(Some("not a token"), now);
+        assert!(!unknown.resumed);
+        assert_ne!(unknown.token, "not a token");
+    }
+
+    #[test]
+    fn session_park_and_resume_test() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+        let ticket = sessions.join(None, now);
+        sessions.park(&ticket.token, 3, parked_state(), now);
+
+        let later = now + Duration::from_millis(SESSION_GRACE_PERIOD_MS - 1);
+        let resumed = sessions.join(Some(&ticket.token), later);
+        assert!(resumed.resumed);
+        assert_eq!(resumed.token, ticket.token);
+        assert_eq!(resumed.client_id, ticket.client_id);
+        assert_eq!(resumed.parked_room, Some(3));
+        assert_eq!(resumed.parked_state.unwrap().rtt_ms, 42);
+
+        // the parked state is handed out once, taking over again does not get it a second time
+        let again = sessions.join(Some(&ticket.token), later);
+        assert!(again.resumed);
+        assert!(again.parked_state.is_none());
+    }
+
+    #[test]
+    fn session_expires_after_grace_period_test() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+        let parked = sessions.join(None, now);
+        let connected = sessions.join(None, now);
+        sessions.park(&parked.token, 1, parked_state(), now);
+
+        let later = now + Duration::from_millis(SESSION_GRACE_PERIOD_MS);
+        let ticket = sessions.join(Some(&parked.token), later);
+        assert!(!ticket.resumed);
+        assert_ne!(ticket.client_id, parked.client_id);
+
+        // sessions that still have a connection are never expired
+        assert!(sessions.is_current(&connected.token, connected.generation));
+    }
+
+    #[test]
+    fn session_is_current_test() {
+        let mut sessions = Sessions::default();
+        let now = Instant::now();
+        let old = sessions.join(None, now);
+        assert!(sessions.is_current(&old.token, old.generation));
+
+        // a new connection resuming the session replaces the old one
+        let new = sessions.join(Some(&old.token), now);
+        assert!(!sessions.is_current(&old.token, old.generation));
+        assert!(sessions.is_current(&new.token, new.generation));
+
+        sessions.end(&new.token);
+        assert!(!sessions.is_current(&new.token, new.generation));
+    }
+}
@@ -0,0 +1,93 @@
+use hattrick_packets_lib::gamestate::GameState;
+use hattrick_packets_lib::gametypes::GameType;
+use hattrick_packets_lib::keystate::KeyState;
+
+/// Builds the game state that the given client is allowed to see, this is what gets serialized for their connection.
+/// The viewers own client state is left alone, everyone else has anything the viewer does not need to draw the game redacted:
+/// - raw key state is never needed, everything it does is already applied to their game mode state.
+/// - votes are hidden until the vote closes, so nobody can wait to see what everyone else picked.
+/// - mouse position is only needed in tank, where it is used to draw which way their turret points.
+pub fn client_view(gs: &GameState, viewer: &str) -> GameState {
+    let mut view = gs.clone();
+    let needs_mouse_pos = matches!(gs.game_type, GameType::TANK(_));
+
+    for (uuid, client) in view.client_list.iter_mut() {
+        if uuid == viewer {
+            continue;
+        }
+
+        client.key_state = KeyState::default();
+        if gs.vote_running {
+            client.vote_number = 0;
+        }
+        if !needs_mouse_pos {
+            client.mouse_pos = (0.0, 0.0);
+        }
+    }
+
+    view
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::view::client_view;
+    use hattrick_packets_lib::clientstate::ClientState;
+    use hattrick_packets_lib::gamestate::GameState;
+    use hattrick_packets_lib::gametypes::GameType::TANK;
+    use hattrick_packets_lib::keystate::KeyState;
+    use hattrick_packets_lib::tank::TankGameState;
+
+    fn busy_client() -> ClientState {
+        ClientState {
+            mouse_pos: (120.0, 80.0),
+            key_state: KeyState {
+                w_key: true,
+                space_bar: true,
+                ..KeyState::default()
+            },
+            vote_number: 2,
+            ..ClientState::default()
+        }
+    }
+
+    fn game_state() -> GameState {
+        let mut gs = GameState::default();
+        gs.client_list.insert("me".to_string(), busy_client());
+        gs.client_list.insert("other".to_string(), busy_client());
+        gs
+    }
+
+    #[test]
+    fn view_redacts_key_state_test() {
+        let view = client_view(&game_state(), "me");
+        assert_eq!(view.client_list["me"].key_state, busy_client().key_state);
+        assert_eq!(view.client_list["other"].key_state, KeyState::default());
+    }
+
+    #[test]
+    fn view_hides_votes_while_running_test() {
+        let mut gs = game_state();
+        gs.vote_running = true;
+        let view = client_view(&gs, "me");
+        assert_eq!(view.client_list["me"].vote_number, 2);
+        assert_eq!(view.client_list["other"].vote_number, 0);
+
+        // once the vote closes everyone can see what was picked
+        gs.vote_running = false;
+        let view = client_view(&gs, "me");
+        assert_eq!(view.client_list["other"].vote_number, 2);
+    }
+
+    #[test]
+    fn view_hides_mouse_pos_outside_tank_test() {
+        let mut gs = game_state();
+        let view = client_view(&gs, "me");
+        assert_eq!(view.client_list["me"].mouse_pos, (120.0, 80.0));
+        assert_eq!(view.client_list["other"].mouse_pos, (0.0, 0.0));
+
+        // tank needs it to draw where the other turrets point
+        gs.game_type = TANK(TankGameState::default());
+        let view = client_view(&gs, "me");
+        assert_eq!(view.client_list["other"].mouse_pos, (120.0, 80.0));
+    }
+}